@@ -0,0 +1,217 @@
+//! Compute units, transaction size and rent for ConfirmMessage as the VAA payload grows.
+//!
+//! Only BPF programs are metered, so build the program first and keep the `.so` in the path of
+//! the benchmark:
+//!
+//! ```
+//! $ cargo build-bpf && cp target/deploy/messenger_solana.so .
+//! $ cargo bench --bench costs > costs.txt
+//! ```
+//!
+//! ConfirmMessage only reads the PostedVAA account, so rather than running the bridge the posted
+//! VAA and the registered chain are preloaded into the test environment. The `rent` column is the
+//! rent exempt balance of the PostedVAA account, which the relayer pays when posting it.
+
+use std::sync::Mutex;
+
+use byteorder::{
+    LittleEndian,
+    WriteBytesExt,
+};
+use solana_program::instruction::{
+    AccountMeta,
+    Instruction,
+};
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_program;
+use solana_program::sysvar::{
+    clock,
+    rent,
+};
+use solana_program_test::{
+    tokio,
+    ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::account::Account;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+
+use messenger_solana::Instruction::ConfirmMessage;
+
+
+const ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
+
+/// Emitter registered for `CHAIN`, every VAA below comes from it.
+const CHAIN: u16 = 2;
+const EMITTER: [u8; 32] = [4u8; 32];
+
+/// Payload lengths to measure, the program logs the payload so it is kept to ASCII.
+const SIZES: &[usize] = &[0, 32, 128, 256, 512, 768];
+
+
+/// Collects the runtime's program log lines, which is where compute unit usage is reported.
+#[derive(Default)]
+struct Capture(Mutex<Vec<String>>);
+
+impl log::Log for Capture {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().ends_with("stable_log")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl Capture {
+    fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Compute units the outermost invocation of `program` reported since the last `clear`.
+    fn consumed(&self, program: &Pubkey) -> u64 {
+        let prefix = format!("Program {} consumed ", program);
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|line| line.strip_prefix(&prefix)?.split(' ').next()?.parse().ok())
+            .expect("no compute units reported, is the program running as BPF?")
+    }
+}
+
+
+/// PostedVAA account data as the bridge stores it: the `vaa` magic followed by a borsh encoded
+/// `MessageData`.
+fn posted_vaa(sequence: u64, payload: &[u8]) -> Vec<u8> {
+    let mut data = b"vaa".to_vec();
+    data.write_u8(1).unwrap(); // vaa_version
+    data.write_u8(32).unwrap(); // consistency_level
+    data.write_u32::<LittleEndian>(0).unwrap(); // vaa_time
+    data.extend_from_slice(&[0u8; 32]); // vaa_signature_account
+    data.write_u32::<LittleEndian>(0).unwrap(); // submission_time
+    data.write_u32::<LittleEndian>(0).unwrap(); // nonce
+    data.write_u64::<LittleEndian>(sequence).unwrap();
+    data.write_u16::<LittleEndian>(CHAIN).unwrap();
+    data.extend_from_slice(&EMITTER);
+    data.write_u32::<LittleEndian>(payload.len() as u32).unwrap();
+    data.extend_from_slice(payload);
+    data
+}
+
+fn account(data: Vec<u8>, owner: Pubkey) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// The instruction `instruction::confirm_message` builds, which can't be used here as it logs to
+/// the browser console.
+fn confirm_message(payer: Pubkey, vaa: Pubkey, sequence: u64) -> Instruction {
+    let wormhole = wormhole_sdk::id();
+    let chain = Pubkey::find_program_address(&[b"EmitterAddress", &CHAIN.to_le_bytes()], &ID).0;
+    let claim = Pubkey::find_program_address(
+        &[&EMITTER, &CHAIN.to_be_bytes(), &sequence.to_be_bytes()],
+        &ID,
+    )
+    .0;
+
+    Instruction {
+        program_id: ID,
+        accounts:   vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(wormhole_sdk::config(&wormhole), false),
+            AccountMeta::new(claim, false),
+            AccountMeta::new_readonly(chain, false),
+            AccountMeta::new(wormhole_sdk::fee_collector(&wormhole), false),
+            AccountMeta::new_readonly(vaa, false),
+            AccountMeta::new_readonly(wormhole, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(rent::id(), false),
+            AccountMeta::new_readonly(clock::id(), false),
+        ],
+        data:       borsh::BorshSerialize::try_to_vec(&ConfirmMessage).unwrap(),
+    }
+}
+
+/// Run `instruction` in its own transaction, returning the compute units and transaction size.
+async fn measure(
+    context: &mut ProgramTestContext,
+    capture: &Capture,
+    instruction: Instruction,
+) -> (u64, usize) {
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    let tx_bytes = bincode::serialize(&transaction).unwrap().len();
+
+    capture.clear();
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    (capture.consumed(&ID), tx_bytes)
+}
+
+
+async fn run(capture: &Capture) {
+    let vaas: Vec<(Pubkey, Vec<u8>)> = SIZES
+        .iter()
+        .enumerate()
+        .map(|(sequence, &size)| (Pubkey::new_unique(), posted_vaa(sequence as u64, &vec![b'x'; size])))
+        .collect();
+
+    let mut context = {
+        let mut test = ProgramTest::default();
+        test.prefer_bpf(true);
+        test.add_program("messenger_solana", ID, None);
+
+        let chain = Pubkey::find_program_address(&[b"EmitterAddress", &CHAIN.to_le_bytes()], &ID).0;
+        test.add_account(chain, account(EMITTER.to_vec(), ID));
+        for (key, data) in &vaas {
+            test.add_account(*key, account(data.clone(), wormhole_sdk::id()));
+        }
+
+        test.start_with_context().await
+    };
+
+    println!(
+        "{:<20} {:>8} {:>14} {:>9} {:>10}",
+        "instruction", "payload", "compute_units", "tx_bytes", "rent"
+    );
+
+    let payer = context.payer.pubkey();
+    for (sequence, ((vaa, data), &size)) in vaas.into_iter().zip(SIZES).enumerate() {
+        let instruction = confirm_message(payer, vaa, sequence as u64);
+        let (units, tx_bytes) = measure(&mut context, capture, instruction).await;
+        println!(
+            "{:<20} {:>8} {:>14} {:>9} {:>10}",
+            "confirm_message",
+            size,
+            units,
+            tx_bytes,
+            Rent::default().minimum_balance(data.len())
+        );
+    }
+}
+
+
+fn main() {
+    // Installed before ProgramTest sets up its own logger, which then fails to replace this one.
+    let capture: &'static Capture = Box::leak(Box::new(Capture::default()));
+    log::set_logger(capture).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    tokio::runtime::Runtime::new().unwrap().block_on(run(capture));
+}
@@ -0,0 +1,417 @@
+//! Command line tool for driving the Solana Messenger.
+//!
+//! ```text
+//! $ messenger send --nick Alice --text "Hello Bob" --consistency confirmed
+//! $ messenger post-vaa signed.vaa
+//! $ messenger recv signed.vaa
+//! $ messenger show --vaa signed.vaa
+//! ```
+//!
+//! Every command prints a JSON object when run with `--output json`, so it can be scripted.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use futures::executor::block_on;
+use serde_json::{
+    json,
+    Value,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::{
+    read_keypair_file,
+    Keypair,
+    Signer,
+};
+use solana_sdk::transaction::Transaction;
+use structopt::StructOpt;
+
+use messenger::state::Consistency;
+use messenger_client::{
+    Envelope,
+    Message,
+    Messenger,
+    PostedMessage,
+    SignedVaa,
+};
+
+mod rpc;
+use rpc::Node;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+
+#[derive(StructOpt)]
+#[structopt(name = "messenger", about = "Send and receive Wormhole Messenger messages on Solana.")]
+struct Opts {
+    /// JSON RPC endpoint of the Solana node.
+    #[structopt(long, default_value = "http://localhost:8899")]
+    url: String,
+
+    /// Keypair file used to sign and pay for transactions.
+    #[structopt(long, default_value = "~/.config/solana/id.json")]
+    keypair: String,
+
+    /// Messenger program id.
+    #[structopt(long)]
+    program_id: Pubkey,
+
+    /// Wormhole core bridge program id, defaults to the bridge the SDK was built for.
+    #[structopt(long)]
+    wormhole: Option<Pubkey>,
+
+    /// Output format, `display` or `json`.
+    #[structopt(long, default_value = "display")]
+    output: Output,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Send a message through Wormhole.
+    Send {
+        #[structopt(long)]
+        nick: String,
+
+        #[structopt(long)]
+        text: String,
+
+        #[structopt(long, default_value = "0")]
+        nonce: u32,
+
+        /// `confirmed` or `finalized`, defaults to the program's configured level.
+        #[structopt(long, parse(try_from_str = parse_consistency))]
+        consistency: Option<Consistency>,
+
+        /// Wormhole chain id the message is for, 0 for any chain.
+        #[structopt(long, default_value = "0")]
+        chain: u16,
+
+        /// Post on a named channel, from that channel's emitter rather than the default one.
+        #[structopt(long)]
+        channel: Option<String>,
+    },
+
+    /// Verify the signatures on a signed VAA and post it to the bridge.
+    PostVaa {
+        /// File containing the signed VAA bytes.
+        file: PathBuf,
+    },
+
+    /// Deliver a posted VAA to the Messenger.
+    Recv {
+        /// File containing the signed VAA bytes, used to find the PostedVAA account.
+        file: PathBuf,
+    },
+
+    /// Decode a message account, a PostedVAA account, or a signed VAA file.
+    Show {
+        /// Message or PostedVAA account to decode.
+        #[structopt(long, conflicts_with_all = &["vaa", "sender"])]
+        account: Option<Pubkey>,
+
+        /// Signed VAA file to decode.
+        #[structopt(long, conflicts_with = "sender")]
+        vaa: Option<PathBuf>,
+
+        /// Show the `index`th message sent by this sender.
+        #[structopt(long, requires = "index")]
+        sender: Option<Pubkey>,
+
+        #[structopt(long)]
+        index: Option<u64>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Display,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "display" => Ok(Output::Display),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+fn parse_consistency(s: &str) -> std::result::Result<Consistency, String> {
+    match s {
+        "confirmed" => Ok(Consistency::Confirmed),
+        "finalized" => Ok(Consistency::Finalized),
+        _ => Err(format!("unknown consistency level: {}", s)),
+    }
+}
+
+
+/// Everything a command needs to talk to the cluster.
+struct Context {
+    node:      Node,
+    payer:     Keypair,
+    messenger: Messenger,
+}
+
+impl Context {
+    fn submit(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<String> {
+        let mut all = vec![&self.payer];
+        all.extend_from_slice(signers);
+        let (blockhash, _) = self.node.0.get_recent_blockhash()?;
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&self.payer.pubkey()), &all, blockhash);
+        Ok(self.node.0.send_and_confirm_transaction(&transaction)?.to_string())
+    }
+}
+
+
+fn main() {
+    let opts = Opts::from_args();
+    let output = opts.output;
+    match run(opts) {
+        Ok(value) => print(output, &value),
+        Err(e) => {
+            print(output, &json!({ "error": e.to_string() }));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(opts: Opts) -> Result<Value> {
+    let keypair = match opts.keypair.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", std::env::var("HOME")?, rest),
+        None => opts.keypair.clone(),
+    };
+
+    let mut context = Context {
+        node:      Node(RpcClient::new_with_commitment(opts.url, CommitmentConfig::confirmed())),
+        payer:     read_keypair_file(&keypair)?,
+        messenger: Messenger::new(opts.program_id, opts.wormhole.unwrap_or_else(wormhole_sdk::id)),
+    };
+
+    match opts.command {
+        Command::Send {
+            nick,
+            text,
+            nonce,
+            consistency,
+            chain,
+            channel,
+        } => send(&mut context, Message { nick, text }, nonce, consistency, chain, channel.as_deref()),
+        Command::PostVaa { file } => post_vaa(&mut context, &read_vaa(&file)?),
+        Command::Recv { file } => recv(&mut context, &read_vaa(&file)?),
+        Command::Show {
+            account,
+            vaa,
+            sender,
+            index,
+        } => match (account, vaa, sender.zip(index)) {
+            (Some(account), _, _) => show_account(&mut context, &account),
+            (_, Some(file), _) => Ok(describe_vaa(&read_vaa(&file)?)),
+            (_, _, Some((sender, index))) => {
+                let account = context.messenger.message(&sender, index);
+                show_account(&mut context, &account)
+            }
+            _ => Err("one of --account, --vaa or --sender/--index is required".into()),
+        },
+    }
+}
+
+fn send(
+    context: &mut Context,
+    payload: Message,
+    nonce: u32,
+    consistency: Option<Consistency>,
+    chain: u16,
+    channel: Option<&str>,
+) -> Result<Value> {
+    let payer = context.payer.pubkey();
+    let index = block_on(context.messenger.message_count(&mut context.node, &payer))?;
+    let instruction = block_on(context.messenger.send_message(
+        &mut context.node,
+        &payer,
+        payload,
+        nonce,
+        consistency,
+        chain,
+        channel,
+    ))?;
+
+    let signature = context.submit(&[instruction], &[])?;
+    let emitter = match channel {
+        Some(channel) => context.messenger.channel_emitter(channel),
+        None => context.messenger.emitter(),
+    };
+    Ok(json!({
+        "signature": signature,
+        "message": context.messenger.message(&payer, index).to_string(),
+        "index": index,
+        "emitter": emitter.to_string(),
+    }))
+}
+
+fn post_vaa(context: &mut Context, vaa: &SignedVaa) -> Result<Value> {
+    let payer = context.payer.pubkey();
+    let signature_set = Keypair::new();
+    let guardians = block_on(context.messenger.read_guardian_set(&mut context.node, vaa.guardian_set_index))?;
+    let transactions = messenger_client::vaa::verify_and_post(
+        context.messenger.wormhole,
+        payer,
+        signature_set.pubkey(),
+        vaa,
+        &guardians.keys,
+    )?;
+
+    // Every transaction but the last verifies signatures and must be signed by the signature set.
+    let mut signatures = Vec::new();
+    let (post, verify) = transactions.split_last().unwrap();
+    for instructions in verify {
+        signatures.push(context.submit(instructions, &[&signature_set])?);
+    }
+    signatures.push(context.submit(post, &[])?);
+
+    Ok(json!({
+        "signatures": signatures,
+        "digest": hex::encode(vaa.digest()),
+        "posted_vaa": context.messenger.posted_vaa(&vaa.digest()).to_string(),
+    }))
+}
+
+fn recv(context: &mut Context, vaa: &SignedVaa) -> Result<Value> {
+    let payer = context.payer.pubkey();
+    let posted_vaa = context.messenger.posted_vaa(&vaa.digest());
+    let instruction = block_on(context.messenger.recv_message(&mut context.node, &payer, &posted_vaa))?;
+
+    let signature = context.submit(&[instruction], &[])?;
+    Ok(json!({
+        "signature": signature,
+        "posted_vaa": posted_vaa.to_string(),
+    }))
+}
+
+fn show_account(context: &mut Context, account: &Pubkey) -> Result<Value> {
+    let data = block_on(messenger_client::Rpc::get_account_data(&mut context.node, account))?
+        .ok_or_else(|| format!("account {} not found", account))?;
+    let mut value = describe_message(&PostedMessage::decode(&data)?);
+    value["account"] = json!(account.to_string());
+    Ok(value)
+}
+
+fn read_vaa(file: &PathBuf) -> Result<SignedVaa> {
+    Ok(SignedVaa::parse(&std::fs::read(file)?)?)
+}
+
+
+fn describe_message(posted: &PostedMessage) -> Value {
+    json!({
+        "sender": hex::encode(posted.sender),
+        "target_chain": posted.target_chain,
+        "target": posted.target.map(hex::encode),
+        "recipient": posted.recipient.map(hex::encode),
+        "room": posted.room,
+        "nick": posted.message.nick,
+        "text": posted.message.text,
+        "emitter_chain": posted.data.emitter_chain,
+        "emitter_address": hex::encode(posted.data.emitter_address),
+        "sequence": posted.data.sequence,
+        "nonce": posted.data.nonce,
+        "consistency_level": posted.data.consistency_level,
+        "submission_time": posted.data.submission_time,
+    })
+}
+
+fn describe_vaa(vaa: &SignedVaa) -> Value {
+    let message = borsh::BorshDeserialize::try_from_slice(&vaa.payload)
+        .map(|envelope: Envelope| {
+            json!({
+                "sender": hex::encode(envelope.sender),
+                "target_chain": envelope.target_chain,
+                "target": envelope.target.map(hex::encode),
+                "recipient": envelope.recipient.map(hex::encode),
+                "room": envelope.room,
+                "nick": envelope.message.nick,
+                "text": envelope.message.text,
+            })
+        })
+        .unwrap_or(Value::Null);
+
+    json!({
+        "version": vaa.version,
+        "guardian_set_index": vaa.guardian_set_index,
+        "signatures": vaa.signatures.iter().map(|s| s.index).collect::<Vec<_>>(),
+        "timestamp": vaa.timestamp,
+        "nonce": vaa.nonce,
+        "emitter_chain": vaa.emitter_chain,
+        "emitter_address": hex::encode(vaa.emitter_address),
+        "sequence": vaa.sequence,
+        "consistency_level": vaa.consistency_level,
+        "digest": hex::encode(vaa.digest()),
+        "message": message,
+    })
+}
+
+fn print(output: Output, value: &Value) {
+    match output {
+        Output::Json => println!("{}", value),
+        Output::Display => {
+            if let Value::Object(fields) = value {
+                for (key, value) in fields {
+                    match value {
+                        Value::String(s) => println!("{}: {}", key, s),
+                        other => println!("{}: {}", key, other),
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_describe_vaa() {
+        let vaa = SignedVaa {
+            version:         1,
+            emitter_chain:   1,
+            sequence:        9,
+            payload:         borsh::BorshSerialize::try_to_vec(&Envelope {
+                sender:       [1; 32],
+                target_chain: 0,
+                target:       None,
+                recipient:    None,
+                room:         None,
+                message:      Message {
+                    nick: "Alice".to_string(),
+                    text: "Hi".to_string(),
+                },
+            })
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let value = describe_vaa(&vaa);
+        assert_eq!(value["sequence"], 9);
+        assert_eq!(value["message"]["nick"], "Alice");
+        assert_eq!(value["message"]["text"], "Hi");
+        assert_eq!(value["message"]["sender"], hex::encode([1; 32]));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert!(parse_consistency("confirmed").unwrap() == Consistency::Confirmed);
+        assert!(parse_consistency("soon").is_err());
+        assert!(Output::from_str("json").unwrap() == Output::Json);
+    }
+}
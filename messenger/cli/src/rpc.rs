@@ -0,0 +1,23 @@
+//! Adapts the blocking `RpcClient` to the client library's `Rpc` trait.
+
+use async_trait::async_trait;
+use messenger_client::{
+    ClientError,
+    Rpc,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+
+
+pub struct Node(pub RpcClient);
+
+#[async_trait]
+impl Rpc for Node {
+    async fn get_account_data(&mut self, key: &Pubkey) -> Result<Option<Vec<u8>>, ClientError> {
+        let account = self
+            .0
+            .get_account_with_commitment(key, self.0.commitment())
+            .map_err(|e| ClientError::Rpc(e.to_string()))?;
+        Ok(account.value.map(|account| account.data))
+    }
+}
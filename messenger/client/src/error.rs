@@ -0,0 +1,34 @@
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    /// The RPC backend failed to answer a request.
+    #[error("RPC error: {0}")]
+    Rpc(String),
+
+    /// An account we needed does not exist.
+    #[error("Account {0} not found")]
+    AccountNotFound(Pubkey),
+
+    /// Account data or a VAA could not be decoded.
+    #[error("Decode error: {0}")]
+    Decode(#[from] std::io::Error),
+
+    /// A Messenger account has the wrong kind or a layout this client can't read.
+    #[error("Invalid account: {0}")]
+    InvalidAccount(#[from] ProgramError),
+
+    /// A VAA is signed by a guardian that is not in the guardian set.
+    #[error("Unknown guardian {0}")]
+    UnknownGuardian(u8),
+
+    /// A guardian set has more guardians than the bridge can verify signatures for.
+    #[error("Guardian set of {0} is too large")]
+    GuardianSetTooLarge(usize),
+
+    /// The bridge SDK refused to build an instruction.
+    #[error("Invalid instruction")]
+    InvalidInstruction,
+}
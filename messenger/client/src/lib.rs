@@ -0,0 +1,630 @@
+//! Off-chain client for the Solana Messenger program.
+//!
+//! Consumers of the Messenger otherwise have to re-derive the emitter, sequence, fee collector,
+//! config and PostedVAA addresses by hand. `Messenger` wraps those derivations for a given program
+//! and bridge deployment, reads state back through any `Rpc` backend, and assembles the
+//! verify_signatures, post_vaa and recv_message sequence needed to deliver a signed VAA.
+
+use borsh::BorshDeserialize;
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use wormhole_sdk::{
+    BridgeData,
+    MessageData,
+};
+
+use messenger::instruction::{
+    self,
+    PostedVaa,
+};
+use messenger::state::{
+    decode,
+    Config,
+    Consistency,
+    Inbox,
+    Multisig,
+    Nick,
+    Proposal,
+    Room,
+    Scheduled,
+    Sender,
+};
+
+pub mod error;
+pub mod rpc;
+pub mod vaa;
+
+pub use error::ClientError;
+pub use messenger::bridge::{
+    GuardianSet,
+    SignatureSet,
+};
+pub use messenger::events;
+pub use messenger_common::{
+    Envelope,
+    Message,
+    Registration,
+};
+pub use rpc::Rpc;
+pub use vaa::{
+    GuardianSignature,
+    SignedVaa,
+};
+
+
+/// A Wormhole message read back from chain, either one we posted or a PostedVAA, along with the
+/// Messenger payload decoded from it.
+#[derive(Debug)]
+pub struct PostedMessage {
+    pub data:         MessageData,
+    pub sender:       [u8; 32],
+    pub target_chain: u16,
+    pub target:       Option<[u8; 32]>,
+    pub recipient:    Option<[u8; 32]>,
+    pub room:         Option<u64>,
+    pub message:      Message,
+}
+
+impl PostedMessage {
+    pub fn decode(account: &[u8]) -> Result<Self, ClientError> {
+        let data = message_data(account)?;
+        let envelope = Envelope::try_from_slice(&data.payload)?;
+        Ok(PostedMessage {
+            data,
+            sender: envelope.sender,
+            target_chain: envelope.target_chain,
+            target: envelope.target,
+            recipient: envelope.recipient,
+            room: envelope.room,
+            message: envelope.message,
+        })
+    }
+}
+
+
+/// Decode a message or PostedVAA account, both of which start with a three byte magic before the
+/// data.
+fn message_data(account: &[u8]) -> Result<MessageData, ClientError> {
+    Ok(MessageData::try_from_slice(account.get(3..).unwrap_or_default())?)
+}
+
+
+/// Lamports charged for each message sent, excluding rent for the message account and the
+/// transaction fee.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SendCost {
+    pub bridge_fee:   u64,
+    pub protocol_fee: u64,
+}
+
+impl SendCost {
+    pub fn total(&self) -> u64 {
+        self.bridge_fee + self.protocol_fee
+    }
+}
+
+
+/// Addresses and helpers for a Messenger program deployed against a particular bridge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Messenger {
+    pub program_id: Pubkey,
+    pub wormhole:   Pubkey,
+}
+
+impl Messenger {
+    pub fn new(program_id: Pubkey, wormhole: Pubkey) -> Self {
+        Messenger {
+            program_id,
+            wormhole,
+        }
+    }
+
+    /// The Messenger config account.
+    pub fn config(&self) -> Pubkey {
+        messenger::state::config(&self.program_id).0
+    }
+
+    /// A sender's state account, holding their message count.
+    pub fn sender(&self, sender: &Pubkey) -> Pubkey {
+        messenger::state::sender(&self.program_id, sender).0
+    }
+
+    /// The message account for the `count`th message sent by `sender`.
+    pub fn message(&self, sender: &Pubkey, count: u64) -> Pubkey {
+        messenger::state::message(&self.program_id, sender, count).0
+    }
+
+    /// The route allowing `emitter` on `chain` to deliver messages to `program`.
+    pub fn route(&self, program: &Pubkey, chain: u16, emitter: &[u8; 32]) -> Pubkey {
+        messenger::state::route(&self.program_id, program, chain, emitter).0
+    }
+
+    /// The outbound cap for messages to `chain`, which may not exist if the chain is uncapped.
+    pub fn destination(&self, chain: u16) -> Pubkey {
+        messenger::state::destination(&self.program_id, chain).0
+    }
+
+    /// The treasury protocol fees are paid into.
+    pub fn treasury(&self) -> Pubkey {
+        messenger::state::treasury(&self.program_id).0
+    }
+
+    /// Address of room `id`.
+    pub fn room(&self, id: u64) -> Pubkey {
+        messenger::state::room(&self.program_id, id).0
+    }
+
+    /// Address of room `id`'s inbox.
+    pub fn inbox(&self, id: u64) -> Pubkey {
+        messenger::state::inbox(&self.program_id, id).0
+    }
+
+    /// Address of `owner`'s block list entry for `sender` on `chain`.
+    pub fn block(&self, owner: &Pubkey, chain: u16, sender: &[u8; 32]) -> Pubkey {
+        messenger::state::block(&self.program_id, owner, chain, sender).0
+    }
+
+    /// Address of `sender`'s scheduled message `id`.
+    pub fn scheduled(&self, sender: &Pubkey, id: u64) -> Pubkey {
+        messenger::state::scheduled(&self.program_id, sender, id).0
+    }
+
+    /// Address of multisig `id`, which is also the sender its messages are posted as.
+    pub fn multisig(&self, id: u64) -> Pubkey {
+        messenger::state::multisig(&self.program_id, id).0
+    }
+
+    /// Address of multisig `multisig`'s proposal `id`.
+    pub fn proposal(&self, multisig: u64, id: u64) -> Pubkey {
+        messenger::state::proposal(&self.program_id, &self.multisig(multisig), id).0
+    }
+
+    /// Address of the directory entry for `nick`.
+    pub fn nick(&self, nick: &str) -> Pubkey {
+        messenger::state::nick(&self.program_id, nick).0
+    }
+
+    /// The emitter the Messenger posts messages from.
+    pub fn emitter(&self) -> Pubkey {
+        wormhole_sdk::emitter(&self.program_id).0
+    }
+
+    /// The emitter messages on `channel` are posted from, each with its own sequence.
+    pub fn channel_emitter(&self, channel: &str) -> Pubkey {
+        messenger::state::channel_emitter(&self.program_id, channel).0
+    }
+
+    /// The bridge's sequence account for our emitter.
+    pub fn sequence(&self) -> Pubkey {
+        wormhole_sdk::sequence(&self.wormhole, &self.emitter())
+    }
+
+    /// The bridge's fee collector.
+    pub fn fee_collector(&self) -> Pubkey {
+        wormhole_sdk::fee_collector(&self.wormhole)
+    }
+
+    /// The bridge's own config account.
+    pub fn bridge_config(&self) -> Pubkey {
+        wormhole_sdk::config(&self.wormhole)
+    }
+
+    /// The bridge's account for guardian set `index`.
+    pub fn guardian_set(&self, index: u32) -> Pubkey {
+        Pubkey::find_program_address(&[b"GuardianSet", &index.to_be_bytes()], &self.wormhole).0
+    }
+
+    /// The PostedVAA account the bridge creates for a VAA with the given body digest.
+    pub fn posted_vaa(&self, digest: &[u8; 32]) -> Pubkey {
+        Pubkey::find_program_address(&[b"PostedVAA", digest], &self.wormhole).0
+    }
+
+    /// Current bridge fee per message, read from the bridge config account.
+    pub async fn fee<R: Rpc>(&self, rpc: &mut R) -> Result<u64, ClientError> {
+        let key = self.bridge_config();
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        Ok(BridgeData::try_from_slice(&data)?.config.fee)
+    }
+
+    /// Read the Messenger's config account.
+    pub async fn read_config<R: Rpc>(&self, rpc: &mut R) -> Result<Config, ClientError> {
+        let key = self.config();
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        Ok(decode::<Config>(&data)?)
+    }
+
+    /// What sending a message currently costs: the bridge fee plus our protocol fee.
+    pub async fn send_cost<R: Rpc>(&self, rpc: &mut R) -> Result<SendCost, ClientError> {
+        Ok(SendCost {
+            bridge_fee:   self.fee(rpc).await?,
+            protocol_fee: self.read_config(rpc).await?.protocol_fee,
+        })
+    }
+
+    /// Number of messages `sender` has sent, which is also the index of their next message.
+    pub async fn message_count<R: Rpc>(&self, rpc: &mut R, sender: &Pubkey) -> Result<u64, ClientError> {
+        match rpc.get_account_data(&self.sender(sender)).await? {
+            None => Ok(0),
+            Some(data) => Ok(decode::<Sender>(&data)?.count),
+        }
+    }
+
+    /// Read back the `count`th message sent by `sender`.
+    pub async fn read_message<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        sender: &Pubkey,
+        count: u64,
+    ) -> Result<Option<PostedMessage>, ClientError> {
+        match rpc.get_account_data(&self.message(sender, count)).await? {
+            None => Ok(None),
+            Some(data) => Ok(Some(PostedMessage::decode(&data)?)),
+        }
+    }
+
+    /// Read back a VAA the bridge has verified and posted.
+    pub async fn read_posted_vaa<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        digest: &[u8; 32],
+    ) -> Result<Option<PostedMessage>, ClientError> {
+        match rpc.get_account_data(&self.posted_vaa(digest)).await? {
+            None => Ok(None),
+            Some(data) => Ok(Some(PostedMessage::decode(&data)?)),
+        }
+    }
+
+    /// Read guardian set `index` from the bridge.
+    pub async fn read_guardian_set<R: Rpc>(&self, rpc: &mut R, index: u32) -> Result<GuardianSet, ClientError> {
+        let key = self.guardian_set(index);
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        Ok(GuardianSet::deserialize(&mut &data[..])?)
+    }
+
+    /// Read back the signature set a PostedVAA account was posted with, returning its address
+    /// alongside it.
+    pub async fn read_signature_set<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        posted_vaa: &Pubkey,
+    ) -> Result<(Pubkey, SignatureSet), ClientError> {
+        let data = rpc
+            .get_account_data(posted_vaa)
+            .await?
+            .ok_or(ClientError::AccountNotFound(*posted_vaa))?;
+        let key = message_data(&data)?.vaa_signature_account;
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        Ok((key, SignatureSet::deserialize(&mut &data[..])?))
+    }
+
+    /// Build a SendMessage instruction for `payer`'s next message to `target_chain` (0 for any
+    /// chain), optionally on a `channel`, looking up their message count so the right message
+    /// account is used.
+    pub async fn send_message<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        payload: Message,
+        nonce: u32,
+        consistency: Option<Consistency>,
+        target_chain: u16,
+        channel: Option<&str>,
+    ) -> Result<Instruction, ClientError> {
+        let count = self.message_count(rpc, payer).await?;
+        Ok(instruction::send_message(
+            self.program_id,
+            self.wormhole,
+            *payer,
+            count,
+            payload,
+            nonce,
+            consistency,
+            target_chain,
+            channel,
+        ))
+    }
+
+    /// Read room `id`, with its owner and members.
+    pub async fn read_room<R: Rpc>(&self, rpc: &mut R, id: u64) -> Result<Room, ClientError> {
+        let key = self.room(id);
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        Ok(decode::<Room>(&data)?)
+    }
+
+    /// Read the messages delivered to room `id`, oldest first. Each is read back from the
+    /// PostedVAA account its inbox entry points to, entries whose account is gone are skipped, as
+    /// are messages from senders the room owner has muted.
+    pub async fn read_inbox<R: Rpc>(&self, rpc: &mut R, id: u64) -> Result<Vec<PostedMessage>, ClientError> {
+        self.read_entries(rpc, id, false).await
+    }
+
+    /// Read the quarantined messages from muted senders in room `id`'s inbox, as `read_inbox`.
+    pub async fn read_quarantine<R: Rpc>(&self, rpc: &mut R, id: u64) -> Result<Vec<PostedMessage>, ClientError> {
+        self.read_entries(rpc, id, true).await
+    }
+
+    async fn read_entries<R: Rpc>(&self, rpc: &mut R, id: u64, quarantined: bool) -> Result<Vec<PostedMessage>, ClientError> {
+        let key = self.inbox(id);
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        let inbox = decode::<Inbox>(&data)?;
+
+        let mut messages = Vec::new();
+        for entry in inbox.messages().into_iter().filter(|entry| entry.quarantined == quarantined) {
+            if let Some(data) = rpc.get_account_data(&entry.vaa).await? {
+                messages.push(PostedMessage::decode(&data)?);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Build a SendRoomMessage instruction for `payer`'s next message, posted to room `room`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_room_message<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        room: u64,
+        payload: Message,
+        nonce: u32,
+        consistency: Option<Consistency>,
+        target_chain: u16,
+    ) -> Result<Instruction, ClientError> {
+        let count = self.message_count(rpc, payer).await?;
+        Ok(instruction::send_room_message(
+            self.program_id,
+            self.wormhole,
+            *payer,
+            count,
+            room,
+            payload,
+            nonce,
+            consistency,
+            target_chain,
+        ))
+    }
+
+    /// Look up `nick` in the directory, returning who it is registered to if anyone.
+    pub async fn read_nick<R: Rpc>(&self, rpc: &mut R, nick: &str) -> Result<Option<Nick>, ClientError> {
+        match rpc.get_account_data(&self.nick(nick)).await? {
+            None => Ok(None),
+            Some(data) => Ok(Some(decode::<Nick>(&data)?)),
+        }
+    }
+
+    /// Build a RegisterNick instruction registering `nick` to `payer`.
+    pub async fn register_nick<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        nick: &str,
+        nonce: u32,
+    ) -> Result<Instruction, ClientError> {
+        let count = self.message_count(rpc, payer).await?;
+        Ok(instruction::register_nick(self.program_id, self.wormhole, *payer, count, nick, nonce))
+    }
+
+    /// Read `sender`'s scheduled message `id`, if it is still waiting to be posted.
+    pub async fn read_scheduled<R: Rpc>(&self, rpc: &mut R, sender: &Pubkey, id: u64) -> Result<Option<Scheduled>, ClientError> {
+        match rpc.get_account_data(&self.scheduled(sender, id)).await? {
+            None => Ok(None),
+            Some(data) => Ok(Some(decode::<Scheduled>(&data)?)),
+        }
+    }
+
+    /// Build a Crank instruction posting `sender`'s scheduled message `id`, with `cranker` paying
+    /// the fees and collecting the escrow.
+    pub async fn crank<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        cranker: &Pubkey,
+        sender: &Pubkey,
+        id: u64,
+    ) -> Result<Instruction, ClientError> {
+        let key = self.scheduled(sender, id);
+        let scheduled = self.read_scheduled(rpc, sender, id).await?.ok_or(ClientError::AccountNotFound(key))?;
+        let count = self.message_count(rpc, sender).await?;
+        Ok(instruction::crank(
+            self.program_id,
+            self.wormhole,
+            *cranker,
+            *sender,
+            count,
+            id,
+            scheduled.target_chain,
+        ))
+    }
+
+    /// Read multisig `id`.
+    pub async fn read_multisig<R: Rpc>(&self, rpc: &mut R, id: u64) -> Result<Multisig, ClientError> {
+        let key = self.multisig(id);
+        let data = rpc.get_account_data(&key).await?.ok_or(ClientError::AccountNotFound(key))?;
+        Ok(decode::<Multisig>(&data)?)
+    }
+
+    /// Read multisig `multisig`'s proposal `id`, if it is still pending.
+    pub async fn read_proposal<R: Rpc>(&self, rpc: &mut R, multisig: u64, id: u64) -> Result<Option<Proposal>, ClientError> {
+        match rpc.get_account_data(&self.proposal(multisig, id)).await? {
+            None => Ok(None),
+            Some(data) => Ok(Some(decode::<Proposal>(&data)?)),
+        }
+    }
+
+    /// Build a Propose instruction proposing `payload` as `multisig`'s next proposal.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn propose<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        proposer: &Pubkey,
+        multisig: u64,
+        payload: Message,
+        nonce: u32,
+        target_chain: u16,
+        expiry: i64,
+    ) -> Result<Instruction, ClientError> {
+        let proposal = self.read_multisig(rpc, multisig).await?.proposals;
+        Ok(instruction::propose(
+            self.program_id,
+            *proposer,
+            multisig,
+            proposal,
+            payload,
+            nonce,
+            target_chain,
+            expiry,
+        ))
+    }
+
+    /// Build an Approve instruction for `multisig`'s proposal `id`, with `signer` paying to post
+    /// the message should their approval meet the threshold.
+    pub async fn approve<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        signer: &Pubkey,
+        multisig: u64,
+        id: u64,
+    ) -> Result<Instruction, ClientError> {
+        let key = self.proposal(multisig, id);
+        let proposal = self.read_proposal(rpc, multisig, id).await?.ok_or(ClientError::AccountNotFound(key))?;
+        let count = self.message_count(rpc, &self.multisig(multisig)).await?;
+        Ok(instruction::approve(
+            self.program_id,
+            self.wormhole,
+            *signer,
+            multisig,
+            id,
+            proposal.proposer,
+            count,
+            proposal.target_chain,
+        ))
+    }
+
+    /// Read back the accounts the receive policy needs for a VAA that has already been posted.
+    pub async fn read_posted_vaa_accounts<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        posted_vaa: &Pubkey,
+    ) -> Result<PostedVaa, ClientError> {
+        let data = rpc
+            .get_account_data(posted_vaa)
+            .await?
+            .ok_or(ClientError::AccountNotFound(*posted_vaa))?;
+        let posted = message_data(&data)?;
+        let (signature_set, signatures) = self.read_signature_set(rpc, posted_vaa).await?;
+        Ok(PostedVaa {
+            vaa: *posted_vaa,
+            signature_set,
+            guardian_set: self.guardian_set(signatures.guardian_set_index),
+            emitter_chain: posted.emitter_chain,
+            emitter_address: posted.emitter_address,
+            sequence: posted.sequence,
+        })
+    }
+
+    /// Build the instruction receiving a VAA that has already been posted: RecvRegistration for
+    /// nickname registrations, otherwise RecvMessage.
+    pub async fn recv_message<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        posted_vaa: &Pubkey,
+    ) -> Result<Instruction, ClientError> {
+        let posted = self.read_posted_vaa_accounts(rpc, posted_vaa).await?;
+        let data = rpc
+            .get_account_data(posted_vaa)
+            .await?
+            .ok_or(ClientError::AccountNotFound(*posted_vaa))?;
+        self.recv_instruction(rpc, payer, &posted, &message_data(&data)?.payload).await
+    }
+
+    /// The instruction receiving `posted`, which carries `payload`. Messages also get the accounts
+    /// for their room's inbox and the directory entry for their nick.
+    async fn recv_instruction<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        posted: &PostedVaa,
+        payload: &[u8],
+    ) -> Result<Instruction, ClientError> {
+        if let Some(registration) = Registration::parse(payload) {
+            return Ok(instruction::recv_registration(
+                self.program_id,
+                *payer,
+                posted.vaa,
+                posted.signature_set,
+                posted.guardian_set,
+                posted.emitter_chain,
+                posted.emitter_address,
+                &registration.nick,
+            ));
+        }
+
+        let envelope = Envelope::try_from_slice(payload)?;
+        let mut recv = instruction::recv_message(
+            self.program_id,
+            *payer,
+            posted.vaa,
+            posted.signature_set,
+            posted.guardian_set,
+            posted.emitter_chain,
+            posted.emitter_address,
+        );
+        recv = instruction::with_nick(recv, &envelope.message.nick, posted.emitter_chain, &posted.emitter_address);
+        if let Some(recipient) = envelope.recipient {
+            let recipient = Pubkey::new_from_array(recipient);
+            recv = instruction::to_recipient(recv, &recipient, posted.emitter_chain, &envelope.sender);
+        }
+        if let Some(room) = envelope.room {
+            let owner = self.read_room(rpc, room).await?.owner;
+            recv = instruction::to_inbox(
+                recv,
+                room,
+                &owner,
+                posted.emitter_chain,
+                &posted.emitter_address,
+                posted.sequence,
+                &envelope.sender,
+            );
+        }
+        Ok(recv)
+    }
+
+    /// Build a RecvBatch instruction receiving several posted VAAs at once. Routed messages can't
+    /// be batched and are skipped by the program.
+    pub async fn recv_batch<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        posted_vaas: &[Pubkey],
+    ) -> Result<Instruction, ClientError> {
+        let mut vaas = Vec::new();
+        for posted_vaa in posted_vaas {
+            vaas.push(self.read_posted_vaa_accounts(rpc, posted_vaa).await?);
+        }
+        Ok(instruction::recv_batch(self.program_id, *payer, &vaas))
+    }
+
+    /// Build every transaction needed to deliver a signed VAA to the Messenger: one or more
+    /// verify_signatures transactions (which must also be signed by `signature_set`), then a
+    /// final transaction posting the VAA and receiving it.
+    pub async fn receive<R: Rpc>(
+        &self,
+        rpc: &mut R,
+        payer: &Pubkey,
+        signature_set: &Pubkey,
+        vaa: &SignedVaa,
+    ) -> Result<Vec<Vec<Instruction>>, ClientError> {
+        let guardians = self.read_guardian_set(rpc, vaa.guardian_set_index).await?;
+        let mut transactions = vaa::verify_and_post(self.wormhole, *payer, *signature_set, vaa, &guardians.keys)?;
+        let posted = PostedVaa {
+            vaa:             self.posted_vaa(&vaa.digest()),
+            signature_set:   *signature_set,
+            guardian_set:    self.guardian_set(vaa.guardian_set_index),
+            emitter_chain:   vaa.emitter_chain,
+            emitter_address: vaa.emitter_address,
+            sequence:        vaa.sequence,
+        };
+        let recv = self.recv_instruction(rpc, payer, &posted, &vaa.payload).await?;
+        transactions.last_mut().unwrap().push(recv);
+        Ok(transactions)
+    }
+}
@@ -0,0 +1,27 @@
+//! The client only ever needs to read raw account data, so any RPC backend can be plugged in by
+//! implementing `Rpc`. An implementation for `BanksClient` is provided so the same code can be
+//! driven from `solana-program-test`.
+
+use async_trait::async_trait;
+use solana_banks_client::BanksClient;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::ClientError;
+
+
+#[async_trait]
+pub trait Rpc {
+    /// Fetch an account's data, or `None` if the account does not exist.
+    async fn get_account_data(&mut self, key: &Pubkey) -> Result<Option<Vec<u8>>, ClientError>;
+}
+
+#[async_trait]
+impl Rpc for BanksClient {
+    async fn get_account_data(&mut self, key: &Pubkey) -> Result<Option<Vec<u8>>, ClientError> {
+        let account = self
+            .get_account(*key)
+            .await
+            .map_err(|e| ClientError::Rpc(e.to_string()))?;
+        Ok(account.map(|account| account.data))
+    }
+}
@@ -0,0 +1,297 @@
+//! Signed VAA parsing, and the instructions needed to get a signed VAA verified and posted to the
+//! Solana bridge so the Messenger can receive it.
+
+use std::io::{
+    Cursor,
+    Read,
+    Write,
+};
+
+use byteorder::{
+    BigEndian,
+    ReadBytesExt,
+    WriteBytesExt,
+};
+use sha3::Digest;
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use wormhole_sdk::{
+    PostVAAData,
+    VerifySignaturesData,
+};
+
+use crate::error::ClientError;
+
+
+/// The most signatures we check in a single verify_signatures transaction. Each signature adds 85
+/// bytes to the secp256k1 instruction, this keeps a transaction comfortably under the size limit.
+pub const SIGNATURES_PER_TRANSACTION: usize = 7;
+
+/// Guardian slots in the bridge's verify_signatures instruction, the largest guardian set it can
+/// verify against.
+pub const MAX_GUARDIANS: usize = 19;
+
+
+/// A single guardian's signature over a VAA body digest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GuardianSignature {
+    /// Index of the signing guardian within the guardian set.
+    pub index: u8,
+
+    /// Recoverable secp256k1 signature, `r || s || v`.
+    pub signature: [u8; 65],
+}
+
+
+/// A VAA as produced by the guardian network, in the wire format submitted to every chain.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SignedVaa {
+    pub version:            u8,
+    pub guardian_set_index: u32,
+    pub signatures:         Vec<GuardianSignature>,
+    pub timestamp:          u32,
+    pub nonce:              u32,
+    pub emitter_chain:      u16,
+    pub emitter_address:    [u8; 32],
+    pub sequence:           u64,
+    pub consistency_level:  u8,
+    pub payload:            Vec<u8>,
+}
+
+impl SignedVaa {
+    /// Parse a signed VAA from its wire format.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ClientError> {
+        let mut v = Cursor::new(bytes);
+        let mut vaa = SignedVaa {
+            version: v.read_u8()?,
+            guardian_set_index: v.read_u32::<BigEndian>()?,
+            ..Default::default()
+        };
+
+        for _ in 0..v.read_u8()? {
+            let index = v.read_u8()?;
+            let mut signature = [0u8; 65];
+            v.read_exact(&mut signature)?;
+            vaa.signatures.push(GuardianSignature { index, signature });
+        }
+
+        vaa.timestamp = v.read_u32::<BigEndian>()?;
+        vaa.nonce = v.read_u32::<BigEndian>()?;
+        vaa.emitter_chain = v.read_u16::<BigEndian>()?;
+        v.read_exact(&mut vaa.emitter_address)?;
+        vaa.sequence = v.read_u64::<BigEndian>()?;
+        vaa.consistency_level = v.read_u8()?;
+        v.read_to_end(&mut vaa.payload)?;
+        Ok(vaa)
+    }
+
+    /// Serialize back into the wire format.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut v = Cursor::new(Vec::new());
+        v.write_u8(self.version).unwrap();
+        v.write_u32::<BigEndian>(self.guardian_set_index).unwrap();
+        v.write_u8(self.signatures.len() as u8).unwrap();
+        for signature in &self.signatures {
+            v.write_u8(signature.index).unwrap();
+            v.write_all(&signature.signature).unwrap();
+        }
+        v.write_all(&self.body()).unwrap();
+        v.into_inner()
+    }
+
+    /// The signed portion of the VAA.
+    pub fn body(&self) -> Vec<u8> {
+        let mut v = Cursor::new(Vec::new());
+        v.write_u32::<BigEndian>(self.timestamp).unwrap();
+        v.write_u32::<BigEndian>(self.nonce).unwrap();
+        v.write_u16::<BigEndian>(self.emitter_chain).unwrap();
+        v.write_all(&self.emitter_address).unwrap();
+        v.write_u64::<BigEndian>(self.sequence).unwrap();
+        v.write_u8(self.consistency_level).unwrap();
+        v.write_all(&self.payload).unwrap();
+        v.into_inner()
+    }
+
+    /// Keccak256 digest of the body. Guardians sign this, and the bridge derives the PostedVAA
+    /// account from it.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut h = sha3::Keccak256::default();
+        h.write_all(&self.body()).unwrap();
+        h.finalize().into()
+    }
+
+    /// The instruction data the bridge's post_vaa expects.
+    pub fn post_vaa_data(&self) -> PostVAAData {
+        PostVAAData {
+            version:            self.version,
+            guardian_set_index: self.guardian_set_index,
+            timestamp:          self.timestamp,
+            nonce:              self.nonce,
+            emitter_chain:      self.emitter_chain,
+            emitter_address:    self.emitter_address,
+            sequence:           self.sequence,
+            consistency_level:  self.consistency_level,
+            payload:            self.payload.clone(),
+        }
+    }
+}
+
+
+/// Build a secp256k1 program instruction checking each `(eth address, signature)` pair against
+/// `digest`. The bridge's verify_signatures inspects this instruction to learn which guardians
+/// signed, so the digest is stored once and every signature points at it.
+pub fn secp256k1_instruction(digest: &[u8; 32], signatures: &[([u8; 20], [u8; 65])]) -> Instruction {
+    let count = signatures.len();
+    let digest_offset = 1 + 11 * count;
+    let mut data = Cursor::new(Vec::new());
+
+    data.write_u8(count as u8).unwrap();
+    for i in 0..count {
+        let address_offset = digest_offset + 32 + i * 85;
+        data.write_u16::<byteorder::LittleEndian>((address_offset + 20) as u16).unwrap();
+        data.write_u8(0).unwrap();
+        data.write_u16::<byteorder::LittleEndian>(address_offset as u16).unwrap();
+        data.write_u8(0).unwrap();
+        data.write_u16::<byteorder::LittleEndian>(digest_offset as u16).unwrap();
+        data.write_u16::<byteorder::LittleEndian>(32).unwrap();
+        data.write_u8(0).unwrap();
+    }
+
+    data.write_all(digest).unwrap();
+    for (address, signature) in signatures {
+        data.write_all(address).unwrap();
+        data.write_all(signature).unwrap();
+    }
+
+    Instruction {
+        program_id: solana_sdk::secp256k1_program::id(),
+        accounts:   vec![],
+        data:       data.into_inner(),
+    }
+}
+
+
+/// Build the transactions, as lists of instructions, that verify a VAA's signatures against the
+/// guardian set `keys` and post it to the bridge. Each verify transaction must also be signed by
+/// `signature_set`, a fresh keypair the bridge records the verified signatures in.
+pub fn verify_and_post(
+    wormhole: Pubkey,
+    payer: Pubkey,
+    signature_set: Pubkey,
+    vaa: &SignedVaa,
+    keys: &[[u8; 20]],
+) -> Result<Vec<Vec<Instruction>>, ClientError> {
+    // Signers are looked up in `keys`, so bounding the set also bounds every index we write.
+    if keys.len() > MAX_GUARDIANS {
+        return Err(ClientError::GuardianSetTooLarge(keys.len()));
+    }
+
+    let digest = vaa.digest();
+    let mut transactions = Vec::new();
+
+    for chunk in vaa.signatures.chunks(SIGNATURES_PER_TRANSACTION) {
+        let mut signers = [-1; MAX_GUARDIANS];
+        let mut pairs = Vec::with_capacity(chunk.len());
+        for (position, signature) in chunk.iter().enumerate() {
+            let address = keys
+                .get(signature.index as usize)
+                .ok_or(ClientError::UnknownGuardian(signature.index))?;
+            signers[signature.index as usize] = position as i8;
+            pairs.push((*address, signature.signature));
+        }
+
+        transactions.push(vec![
+            secp256k1_instruction(&digest, &pairs),
+            wormhole_sdk::instructions::verify_signatures(
+                wormhole,
+                payer,
+                vaa.guardian_set_index,
+                signature_set,
+                VerifySignaturesData { signers },
+            )
+            .map_err(|_| ClientError::InvalidInstruction)?,
+        ]);
+    }
+
+    transactions.push(vec![wormhole_sdk::instructions::post_vaa(
+        wormhole,
+        payer,
+        signature_set,
+        vaa.post_vaa_data(),
+    )]);
+
+    Ok(transactions)
+}
+
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let vaa = SignedVaa {
+            version:            1,
+            guardian_set_index: 3,
+            signatures:         vec![GuardianSignature {
+                index:     2,
+                signature: [7; 65],
+            }],
+            timestamp:          1,
+            nonce:              2,
+            emitter_chain:      1,
+            emitter_address:    [4; 32],
+            sequence:           5,
+            consistency_level:  32,
+            payload:            b"Hello".to_vec(),
+        };
+
+        let bytes = vaa.serialize();
+        assert_eq!(bytes.len(), 1 + 4 + 1 + 66 + 51 + 5);
+        assert_eq!(SignedVaa::parse(&bytes).unwrap(), vaa);
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert!(SignedVaa::parse(&[1, 0, 0, 0, 0, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_guardian_bounds() {
+        let vaa = SignedVaa {
+            signatures: vec![GuardianSignature {
+                index:     19,
+                signature: [7; 65],
+            }],
+            ..Default::default()
+        };
+        let payer = Pubkey::new_unique();
+        let set = Pubkey::new_unique();
+
+        // Indexes past the set and sets larger than the bridge supports are errors, not panics.
+        assert!(matches!(
+            verify_and_post(payer, payer, set, &vaa, &[[1; 20]; 19]),
+            Err(ClientError::UnknownGuardian(19))
+        ));
+        assert!(matches!(
+            verify_and_post(payer, payer, set, &vaa, &[[1; 20]; 20]),
+            Err(ClientError::GuardianSetTooLarge(20))
+        ));
+    }
+
+    #[test]
+    fn test_secp256k1_layout() {
+        let instruction = secp256k1_instruction(&[9; 32], &[([1; 20], [2; 65]), ([3; 20], [4; 65])]);
+        let data = instruction.data;
+
+        // Header, digest, then one address and signature per entry.
+        assert_eq!(data.len(), 1 + 2 * 11 + 32 + 2 * 85);
+        assert_eq!(data[0], 2);
+        assert_eq!(&data[23..55], &[9; 32]);
+        assert_eq!(&data[55..75], &[1; 20]);
+        assert_eq!(&data[75..140], &[2; 65][..]);
+
+        // Second entry's signature offset points past the first address and signature.
+        assert_eq!(u16::from_le_bytes([data[12], data[13]]) as usize, 55 + 85 + 20);
+    }
+}
@@ -0,0 +1,202 @@
+//! Drive the Messenger end to end through the client, using `BanksClient` as the RPC backend.
+//!
+//! As with the Messenger's own tests you will need a `bridge.so` in the path of the tests, see
+//! `messenger/solana/tests/test_messages.rs` for how to build one.
+
+use std::convert::TryInto;
+use std::io::Write;
+
+use sha3::Digest;
+use solana_program::bpf_loader_upgradeable::{
+    self,
+    UpgradeableLoaderState,
+};
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{
+    processor,
+    tokio,
+    ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::account::Account;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+
+use messenger::process_instruction;
+use messenger::state::Consistency;
+use messenger_client::{
+    GuardianSignature,
+    Message,
+    Messenger,
+    SignedVaa,
+};
+use wormhole_sdk::bridge_entrypoint;
+
+use secp256k1::{
+    PublicKey,
+    SecretKey,
+};
+
+
+const ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
+
+const GUARDIAN: &str = "ff2f9d893e5c12618c442b34a98cfa3f646c402bf5e2a180ce761a7d8a43d452";
+
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    let mut h = sha3::Keccak256::default();
+    h.write_all(data).unwrap();
+    h.finalize().into()
+}
+
+/// Ethereum style address for a guardian key, which is how the bridge identifies guardians.
+fn guardian_address(secret: &SecretKey) -> [u8; 20] {
+    let public = PublicKey::from_secret_key(secret).serialize();
+    keccak(&public[1..])[12..].try_into().unwrap()
+}
+
+/// Sign a VAA digest the way guardians do.
+fn sign(secret: &SecretKey, digest: &[u8; 32]) -> [u8; 65] {
+    let (signature, recovery) = secp256k1::sign(&secp256k1::Message::parse(&keccak(digest)), secret);
+    let mut out = [0u8; 65];
+    out[..64].copy_from_slice(&signature.serialize());
+    out[64] = recovery.serialize();
+    out
+}
+
+/// Submit a transaction paid for by the test payer, plus any extra signers.
+async fn process(context: &mut ProgramTestContext, instructions: &[Instruction], signers: &[&Keypair]) {
+    let mut all = vec![&context.payer];
+    all.extend_from_slice(signers);
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            instructions,
+            Some(&context.payer.pubkey()),
+            &all,
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+}
+
+
+#[tokio::test]
+pub async fn test_client_roundtrip() {
+    let secret = SecretKey::parse(&hex::decode(GUARDIAN).unwrap().try_into().unwrap()).unwrap();
+    let client = Messenger::new(ID, wormhole_sdk::id());
+
+    // The Messenger is initialised by its upgrade authority, which ProgramTest doesn't give it.
+    let deployer = Keypair::new();
+    let mut context = {
+        let mut test = ProgramTest::default();
+        test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
+        test.add_program("messenger", ID, processor!(process_instruction));
+        test.add_account(
+            messenger::state::program_data(&ID),
+            Account {
+                lamports:   1_000_000_000,
+                data:       bincode::serialize(&UpgradeableLoaderState::ProgramData {
+                    slot:                      0,
+                    upgrade_authority_address: Some(deployer.pubkey()),
+                })
+                .unwrap(),
+                owner:      bpf_loader_upgradeable::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        test.start_with_context().await
+    };
+
+    // Initialize Wormhole and the Messenger.
+    let payer = context.payer.pubkey();
+    process(
+        &mut context,
+        &[
+            wormhole_sdk::instructions::initialize(
+                wormhole_sdk::id(),
+                payer,
+                50,
+                2_000_000_000,
+                &[guardian_address(&secret)],
+            )
+            .unwrap(),
+            messenger::instruction::initialize(
+                ID,
+                payer,
+                deployer.pubkey(),
+                wormhole_sdk::id(),
+                Consistency::Finalized,
+                vec![Consistency::Finalized],
+            ),
+        ],
+        &[&deployer],
+    )
+    .await;
+
+    assert_eq!(client.fee(&mut context.banks_client).await.unwrap(), 50);
+    assert_eq!(client.send_cost(&mut context.banks_client).await.unwrap().total(), 50);
+    assert_eq!(client.message_count(&mut context.banks_client, &payer).await.unwrap(), 0);
+
+    // Send a message through the client and read it back.
+    let payload = Message {
+        nick: "Alice".to_string(),
+        text: "Hello from the client!".to_string(),
+    };
+
+    let send = client
+        .send_message(&mut context.banks_client, &payer, payload.clone(), 3, None, 0, None)
+        .await
+        .unwrap();
+    process(&mut context, &[send], &[]).await;
+
+    assert_eq!(client.message_count(&mut context.banks_client, &payer).await.unwrap(), 1);
+    let posted = client
+        .read_message(&mut context.banks_client, &payer, 0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(posted.message, payload);
+    assert_eq!(posted.sender, payer.to_bytes());
+    assert_eq!(posted.data.emitter_address, client.emitter().to_bytes());
+
+    // Play the guardian, sign it, and deliver it back through the receive sequence.
+    let mut vaa = SignedVaa {
+        version:            1,
+        guardian_set_index: 0,
+        timestamp:          posted.data.submission_time,
+        nonce:              posted.data.nonce,
+        emitter_chain:      posted.data.emitter_chain,
+        emitter_address:    posted.data.emitter_address,
+        sequence:           posted.data.sequence,
+        consistency_level:  posted.data.consistency_level,
+        payload:            posted.data.payload.clone(),
+        ..Default::default()
+    };
+    vaa.signatures.push(GuardianSignature {
+        index:     0,
+        signature: sign(&secret, &vaa.digest()),
+    });
+
+    let signature_set = Keypair::new();
+    let transactions = client
+        .receive(&mut context.banks_client, &payer, &signature_set.pubkey(), &vaa)
+        .await
+        .unwrap();
+
+    let (post, verify) = transactions.split_last().unwrap();
+    for instructions in verify {
+        process(&mut context, instructions, &[&signature_set]).await;
+    }
+    process(&mut context, post, &[]).await;
+
+    let received = client
+        .read_posted_vaa(&mut context.banks_client, &vaa.digest())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.message, payload);
+}
@@ -11,3 +11,232 @@ pub struct Message {
     /// Message text to be output on the target networks node logs.
     pub text: String,
 }
+
+/// The payload actually posted through Wormhole. The sender is filled in by the emitting contract
+/// rather than the user, so receivers can trust it as much as they trust the emitter.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Envelope {
+    /// Address of the account, contract or program that originated the message on the emitting
+    /// chain, left padded with zeroes to 32 bytes.
+    pub sender: [u8; 32],
+
+    /// Wormhole chain id the message is meant for, or 0 for any chain, receivers on other chains
+    /// refuse it. Emitters may cap how many messages are sent to each chain, messages for any
+    /// chain are capped as chain 0.
+    pub target_chain: u16,
+
+    /// Program or contract on the receiving chain the message should be forwarded to, if any.
+    /// Receivers only forward messages from emitters registered for the target.
+    pub target: Option<[u8; 32]>,
+
+    /// User on the receiving chain a direct message is for, padded as `sender`. Receivers drop or
+    /// quarantine the message if the recipient has blocked or muted the sender.
+    pub recipient: Option<[u8; 32]>,
+
+    /// Room the message was posted to, if any. Receivers deliver room messages into the room's
+    /// inbox rather than treating them as broadcasts.
+    pub room: Option<u64>,
+
+    pub message: Message,
+}
+
+/// Wormhole chain ids of the chains the Messenger is deployed to.
+pub const CHAIN_ID_SOLANA: u16 = 1;
+pub const CHAIN_ID_TERRA: u16 = 3;
+
+/// Longest nickname that can be registered, as Solana PDA seeds are limited to 32 bytes.
+pub const MAX_NICK_LEN: usize = 32;
+
+/// Prefix of nickname registration payloads, which are posted from the same emitter as
+/// envelopes. An envelope starts with its sender's padded address, which won't begin with this.
+pub const REGISTRATION_TAG: [u8; 8] = *b"msgrname";
+
+/// A nickname registered on the emitting chain, posted so other chains can copy it into their
+/// own directory.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Registration {
+    /// Address the nick is registered to, padded as `Envelope::sender`.
+    pub owner: [u8; 32],
+
+    pub nick: String,
+}
+
+impl Registration {
+    /// Encode as a payload to post.
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload = REGISTRATION_TAG.to_vec();
+        payload.extend(self.try_to_vec().unwrap());
+        payload
+    }
+
+    /// Decode a received payload, returning `None` if it isn't a registration.
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        match payload.strip_prefix(&REGISTRATION_TAG[..]) {
+            Some(data) => Registration::try_from_slice(data).ok(),
+            None => None,
+        }
+    }
+}
+
+/// Check a nickname can be registered.
+pub fn valid_nick(nick: &str) -> bool {
+    !nick.is_empty() && nick.len() <= MAX_NICK_LEN
+}
+
+/// Who holds a nickname in a chain's directory. Every chain applies the same rule when two
+/// registrations for a nick meet, so directories agree once all registrations are delivered.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Claim {
+    /// Chain the nick was registered on, and the owner's padded address there.
+    pub chain: u16,
+    pub owner: [u8; 32],
+
+    /// Unix time of the registration, taken from the VAA for registrations from other chains.
+    pub time: u64,
+}
+
+impl Claim {
+    /// Whether this claim wins over `other`. The first registered wins, ties are broken by chain
+    /// and then owner so the outcome doesn't depend on delivery order.
+    pub fn precedes(&self, other: &Claim) -> bool {
+        (self.time, self.chain, self.owner) < (other.time, other.chain, other.owner)
+    }
+}
+
+/// Module governance VAAs for the Messenger are addressed to, left padded to 32 bytes as in the
+/// core bridge's `Governance.sol`.
+pub const GOVERNANCE_MODULE: [u8; 32] = *b"\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0Messenger";
+
+/// Governance action upgrading the Messenger on one chain.
+pub const ACTION_CONTRACT_UPGRADE: u8 = 1;
+
+/// A governance VAA's payload upgrading the Messenger on `chain`. The layout follows the core
+/// bridge's: module, action, chain, then the new contract, with integers big endian. What the new
+/// contract is depends on the chain: a buffer account on Solana, a code id on Terra.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContractUpgrade {
+    pub chain:        u16,
+    pub new_contract: [u8; 32],
+}
+
+impl ContractUpgrade {
+    const SIZE: usize = 32 + 1 + 2 + 32;
+
+    /// Encode as a governance payload.
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload = GOVERNANCE_MODULE.to_vec();
+        payload.push(ACTION_CONTRACT_UPGRADE);
+        payload.extend_from_slice(&self.chain.to_be_bytes());
+        payload.extend_from_slice(&self.new_contract);
+        payload
+    }
+
+    /// Decode a governance payload, returning `None` if it isn't a Messenger contract upgrade.
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.len() != Self::SIZE
+            || payload[..32] != GOVERNANCE_MODULE
+            || payload[32] != ACTION_CONTRACT_UPGRADE
+        {
+            return None;
+        }
+
+        let mut new_contract = [0u8; 32];
+        new_contract.copy_from_slice(&payload[35..]);
+        Some(ContractUpgrade {
+            chain: u16::from_be_bytes([payload[33], payload[34]]),
+            new_contract,
+        })
+    }
+}
+
+
+/// Left pad a native address to the 32 byte form used in `Envelope::sender`. Addresses longer
+/// than 32 bytes keep their last 32 bytes.
+pub fn pad_address(address: &[u8]) -> [u8; 32] {
+    let mut padded = [0u8; 32];
+    let len = address.len().min(32);
+    padded[32 - len..].copy_from_slice(&address[address.len() - len..]);
+    padded
+}
+
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_pad_address() {
+        let padded = pad_address(&[1u8; 20]);
+        assert_eq!(&padded[..12], &[0u8; 12]);
+        assert_eq!(&padded[12..], &[1u8; 20]);
+        assert_eq!(pad_address(&[2u8; 32]), [2u8; 32]);
+    }
+
+    #[test]
+    fn test_registration() {
+        let registration = Registration {
+            owner: [1u8; 32],
+            nick:  "alice".to_string(),
+        };
+        assert_eq!(Registration::parse(&registration.payload()), Some(registration));
+
+        // Envelopes aren't mistaken for registrations.
+        let envelope = Envelope {
+            sender:       [0u8; 32],
+            target_chain: 0,
+            target:       None,
+            recipient:    None,
+            room:         None,
+            message:      Message {
+                nick: "alice".to_string(),
+                text: "hi".to_string(),
+            },
+        };
+        assert_eq!(Registration::parse(&envelope.try_to_vec().unwrap()), None);
+
+        assert!(valid_nick("alice"));
+        assert!(!valid_nick(""));
+        assert!(!valid_nick(&"x".repeat(MAX_NICK_LEN + 1)));
+    }
+
+    #[test]
+    fn test_contract_upgrade() {
+        let upgrade = ContractUpgrade {
+            chain:        CHAIN_ID_TERRA,
+            new_contract: pad_address(&7u64.to_be_bytes()),
+        };
+        let payload = upgrade.payload();
+        assert_eq!(&payload[23..32], b"Messenger");
+        assert_eq!(ContractUpgrade::parse(&payload), Some(upgrade));
+
+        // Other modules, actions and truncated payloads are refused.
+        let mut other = payload.clone();
+        other[31] = b's';
+        assert_eq!(ContractUpgrade::parse(&other), None);
+        let mut other = payload.clone();
+        other[32] = 2;
+        assert_eq!(ContractUpgrade::parse(&other), None);
+        assert_eq!(ContractUpgrade::parse(&payload[..66]), None);
+    }
+
+    #[test]
+    fn test_claim_precedes() {
+        let first = Claim {
+            chain: CHAIN_ID_TERRA,
+            owner: [2u8; 32],
+            time:  10,
+        };
+        let later = Claim { time: 11, ..first };
+        assert!(first.precedes(&later));
+        assert!(!later.precedes(&first));
+        assert!(!first.precedes(&first));
+
+        // Simultaneous registrations resolve the same way on every chain.
+        let solana = Claim {
+            chain: CHAIN_ID_SOLANA,
+            ..first
+        };
+        assert!(solana.precedes(&first));
+        assert!(!first.precedes(&solana));
+    }
+}
@@ -0,0 +1,252 @@
+//! Compute units, transaction size and rent for the Messenger's instructions as the payload grows.
+//!
+//! Only BPF programs are metered, so this needs both `bridge.so` (see `tests/test_messages.rs`)
+//! and a BPF build of the Messenger in the path of the benchmark:
+//!
+//! ```
+//! $ cargo build-bpf && cp target/deploy/messenger.so .
+//! $ cargo bench --bench costs > costs.txt
+//! ```
+//!
+//! The output is a plain table, one row per instruction and payload size, so runs against
+//! different versions can be compared with `diff`.
+
+use std::sync::Mutex;
+
+use borsh::BorshSerialize;
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program_test::{
+    tokio,
+    ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use wormhole_sdk::MessageData;
+
+use messenger::instruction::{
+    initialize,
+    send_batch,
+    send_message,
+    send_message_raw,
+};
+use messenger::state::Consistency;
+use messenger::Message;
+use messenger_test_support::{
+    add_program_data,
+    initialize_bridge,
+    message_vaa,
+    post_vaa,
+    process,
+    recv_instruction,
+    Guardians,
+};
+
+
+const ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
+
+/// Text lengths to measure. A SendMessage's accounts, signature and remaining instruction data
+/// take a little over 620 bytes, leaving room for about 600 bytes of text.
+const SIZES: &[usize] = &[0, 32, 128, 256, 512];
+
+/// Largest transaction the runtime accepts, in bytes.
+const PACKET_DATA_SIZE: usize = 1232;
+
+/// Compute units an instruction may use by default.
+const COMPUTE_BUDGET: u64 = 200_000;
+
+
+/// Collects the runtime's program log lines, which is where compute unit usage is reported.
+#[derive(Default)]
+struct Capture(Mutex<Vec<String>>);
+
+impl log::Log for Capture {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().ends_with("stable_log")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl Capture {
+    fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Compute units the outermost invocation of `program` reported since the last `clear`. CPIs
+    /// report before the instruction that invoked them finishes, so the last line is the total.
+    fn consumed(&self, program: &Pubkey) -> u64 {
+        let prefix = format!("Program {} consumed ", program);
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|line| line.strip_prefix(&prefix)?.split(' ').next()?.parse().ok())
+            .expect("no compute units reported, is the program running as BPF?")
+    }
+}
+
+
+struct Row {
+    instruction: &'static str,
+    payload:     usize,
+    units:       u64,
+    tx_bytes:    usize,
+    rent:        Option<u64>,
+}
+
+impl Row {
+    fn print(&self) {
+        let rent = self.rent.map(|rent| rent.to_string()).unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<20} {:>8} {:>14} {:>9} {:>10}",
+            self.instruction, self.payload, self.units, self.tx_bytes, rent
+        );
+    }
+}
+
+
+/// Run `instruction` in its own transaction and measure it.
+async fn measure(
+    context: &mut ProgramTestContext,
+    capture: &Capture,
+    instruction: Instruction,
+) -> (u64, usize) {
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    let tx_bytes = bincode::serialize(&transaction).unwrap().len();
+    assert!(tx_bytes <= PACKET_DATA_SIZE, "transaction is {} bytes", tx_bytes);
+
+    capture.clear();
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    (capture.consumed(&ID), tx_bytes)
+}
+
+/// Rent exempt balance of an account, as paid by the sender for each message account.
+async fn rent(context: &mut ProgramTestContext, account: Pubkey) -> u64 {
+    let account = context.banks_client.get_account(account).await.unwrap().unwrap();
+    Rent::default().minimum_balance(account.data.len())
+}
+
+async fn read_message(context: &mut ProgramTestContext, account: Pubkey) -> MessageData {
+    let account = context.banks_client.get_account(account).await.unwrap().unwrap();
+    borsh::BorshDeserialize::try_from_slice(&account.data[3..]).unwrap()
+}
+
+
+async fn run(capture: &Capture) {
+    let guardians = Guardians::new(0, 19);
+    let deployer = Keypair::new();
+    let mut context = {
+        let mut test = ProgramTest::default();
+        test.prefer_bpf(true);
+        test.add_program("bridge", wormhole_sdk::id(), None);
+        test.add_program("messenger", ID, None);
+        add_program_data(&mut test, ID, deployer.pubkey());
+        test.start_with_context().await
+    };
+
+    initialize_bridge(&mut context, &guardians, 50, 2_000_000_000).await.unwrap();
+    let payer = context.payer.pubkey();
+    let init = initialize(
+        ID,
+        payer,
+        deployer.pubkey(),
+        wormhole_sdk::id(),
+        Consistency::Finalized,
+        vec![Consistency::Finalized],
+    );
+    process(&mut context, &[init], &[&deployer]).await.unwrap();
+
+    println!(
+        "{:<20} {:>8} {:>14} {:>9} {:>10}",
+        "instruction", "payload", "compute_units", "tx_bytes", "rent"
+    );
+
+    let mut count = 0;
+    for &size in SIZES {
+        let payload = Message {
+            nick: "Alice".to_string(),
+            text: "x".repeat(size),
+        };
+        let payload_len = payload.try_to_vec().unwrap().len();
+
+        for (name, build) in [
+            ("send_message", send_message as fn(_, _, _, _, _, _, _, _, _) -> _),
+            ("send_message_raw", send_message_raw),
+        ] {
+            let instruction = build(ID, wormhole_sdk::id(), payer, count, payload.clone(), 0, None, 0, None);
+            let (units, tx_bytes) = measure(&mut context, capture, instruction).await;
+            let account = messenger::state::message(&ID, &payer, count).0;
+            Row {
+                instruction: name,
+                payload: payload_len,
+                units,
+                tx_bytes,
+                rent: Some(rent(&mut context, account).await),
+            }
+            .print();
+            count += 1;
+        }
+
+        // Receive the last message we sent, signed by a full guardian set.
+        let account = messenger::state::message(&ID, &payer, count - 1).0;
+        let mut vaa = message_vaa(&read_message(&mut context, account).await);
+        guardians.sign(&mut vaa);
+        let posted_vaa = post_vaa(&mut context, &guardians, &vaa).await.unwrap();
+
+        let recv = recv_instruction(&mut context, ID, posted_vaa).await;
+        let (units, tx_bytes) = measure(&mut context, capture, recv).await;
+        Row {
+            instruction: "recv_message",
+            payload: payload_len,
+            units,
+            tx_bytes,
+            rent: None,
+        }
+        .print();
+    }
+
+    // A full batch, which has to fit the default compute budget for MAX_BATCH to be usable.
+    let payload = Message {
+        nick: "Alice".to_string(),
+        text: "x".repeat(32),
+    };
+    let payload_len = payload.try_to_vec().unwrap().len();
+    let payloads = vec![payload; messenger::MAX_BATCH];
+    let instruction = send_batch(ID, wormhole_sdk::id(), payer, count, payloads, 0, 0);
+    let (units, tx_bytes) = measure(&mut context, capture, instruction).await;
+    assert!(units <= COMPUTE_BUDGET, "a full batch used {} compute units", units);
+    Row {
+        instruction: "send_batch",
+        payload: payload_len,
+        units,
+        tx_bytes,
+        rent: None,
+    }
+    .print();
+}
+
+
+fn main() {
+    // Installed before ProgramTest sets up its own logger, which then fails to replace this one.
+    let capture: &'static Capture = Box::leak(Box::new(Capture::default()));
+    log::set_logger(capture).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    tokio::runtime::Runtime::new().unwrap().block_on(run(capture));
+}
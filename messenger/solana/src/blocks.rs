@@ -0,0 +1,116 @@
+//! Per-recipient block lists.
+//!
+//! Any user can list senders, identified by chain and padded address as in `Envelope::sender`,
+//! whose messages they don't want. Blocked senders' messages are dropped on receive and muted
+//! senders' messages are delivered quarantined. Either way the receive succeeds, so relayers can
+//! deliver every VAA they see without checking lists first.
+//!
+//! Direct messages are filtered by the list of the recipient named in their envelope. Room
+//! messages are filtered by the room owner's list as they are delivered into the room's inbox.
+//! Routed targets get the sender in their callback and can filter for themselves.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use wormhole_sdk::PostedVAAData;
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::state::{
+    self,
+    Block,
+    BlockMode,
+};
+
+
+/// List `sender` on `chain` in the signer's block list with `mode`, or take them off the list,
+/// returning the entry's rent, if `mode` is `None`.
+pub fn set_block(id: &Pubkey, accs: &[AccountInfo], chain: u16, sender: [u8; 32], mode: Option<BlockMode>) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let owner    = next_account_info(accounts)?;
+    let entry    = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    if !owner.is_signer {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    let (key, bump) = state::block(id, owner.key, chain, &sender);
+    state::check_address(entry, &key)?;
+
+    let mode = match mode {
+        Some(mode) => mode,
+        None => {
+            if **entry.lamports.borrow() == 0 {
+                return Err(MessengerError::NotBlocked.into());
+            }
+
+            // As for routes, draining the lamports lets the runtime reclaim the account.
+            let lamports = **entry.lamports.borrow();
+            **entry.lamports.borrow_mut() = 0;
+            **owner.lamports.borrow_mut() += lamports;
+            entry.data.borrow_mut().fill(0);
+            return Ok(());
+        }
+    };
+
+    if **entry.lamports.borrow() == 0 {
+        let chain_bytes = chain.to_be_bytes();
+        let seeds: &[&[u8]] = &[b"block", owner.key.as_ref(), &chain_bytes, &sender, &[bump]];
+        state::create(id, owner, entry, system, seeds, Block::SIZE)?;
+    }
+
+    let data = Block {
+        owner: *owner.key,
+        chain,
+        sender,
+        mode,
+    };
+    state::save(entry, &data)
+}
+
+/// How `owner` has listed `sender` on `chain`, if at all. The entry is located by key and must
+/// be passed whether or not it exists, so a relayer can't slip blocked messages through by
+/// leaving it out.
+pub fn mode(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    owner: &Pubkey,
+    chain: u16,
+    sender: &[u8; 32],
+) -> Result<Option<BlockMode>, ProgramError> {
+    let entry = bridge::find(accounts, &state::block(id, owner, chain, sender).0)?;
+    if **entry.lamports.borrow() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(state::load::<Block>(id, entry)?.mode))
+}
+
+/// How `recipient` has listed the sender of a received message, emitting `Dropped` if they have
+/// blocked them. The entry is located by key as for `mode`.
+pub fn check(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    recipient: &Pubkey,
+    posted: &PostedVAAData,
+    sender: &[u8; 32],
+) -> Result<Option<BlockMode>, ProgramError> {
+    let mode = mode(id, accounts, recipient, posted.emitter_chain, sender)?;
+    if mode == Some(BlockMode::Block) {
+        Event::Dropped {
+            emitter_chain:   posted.emitter_chain,
+            emitter_address: posted.emitter_address,
+            sequence:        posted.sequence,
+            sender:          *sender,
+            recipient:       *recipient,
+        }
+        .emit()?;
+    }
+    Ok(mode)
+}
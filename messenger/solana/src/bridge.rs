@@ -0,0 +1,147 @@
+//! Helpers for talking to the Wormhole core bridge recorded in our config account.
+//!
+//! The SDK's own `wormhole_sdk::post_message` derives every bridge account from the program id
+//! compiled in through its network feature. These helpers do the same work but take the bridge id
+//! from `Config`, so the same program binary can be pointed at any network.
+
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::{
+    invoke,
+    invoke_signed,
+};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction::transfer;
+
+use wormhole_sdk::instructions;
+
+use crate::error::MessengerError;
+use crate::state::{
+    Config,
+    Consistency,
+};
+
+
+/// The bridge's record of the guardian signatures verified for a VAA, mirroring its account
+/// layout. PostedVAA accounts point back to the signature set they were posted with.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SignatureSet {
+    pub signatures:         Vec<bool>,
+    pub hash:               [u8; 32],
+    pub guardian_set_index: u32,
+}
+
+/// A guardian set as stored by the bridge, mirroring its account layout. Replaced sets are given
+/// an `expiration_time`, the current set has none.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct GuardianSet {
+    pub index:           u32,
+    pub keys:            Vec<[u8; 20]>,
+    pub creation_time:   u32,
+    pub expiration_time: u32,
+}
+
+/// The bridge's config account, mirroring its layout. `guardian_set_index` is the current set.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct BridgeData {
+    pub guardian_set_index:           u32,
+    pub last_lamports:                u64,
+    pub guardian_set_expiration_time: u32,
+    pub fee:                          u64,
+    pub fee_persistent:               u64,
+}
+
+/// Derive the address of the bridge's account for guardian set `index`.
+pub fn guardian_set(config: &Config, index: u32) -> Pubkey {
+    Pubkey::find_program_address(&[b"GuardianSet", &index.to_be_bytes()], &config.wormhole).0
+}
+
+/// Read an account owned by the configured bridge.
+pub fn load<T: BorshDeserialize>(config: &Config, account: &AccountInfo) -> Result<T, ProgramError> {
+    if *account.owner != config.wormhole {
+        return Err(MessengerError::InvalidAccountOwner.into());
+    }
+    Ok(T::deserialize(&mut &account.data.borrow()[..])?)
+}
+
+
+/// Find an account by key rather than position, the same way the SDK locates the bridge config.
+pub fn find<'a, 'b>(accounts: &'a [AccountInfo<'b>], key: &Pubkey) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or(ProgramError::NotEnoughAccountKeys)
+}
+
+/// Read the current message fee from the bridge config account.
+pub fn fee(config: &Config, accounts: &[AccountInfo]) -> Result<u64, ProgramError> {
+    let bridge = find(accounts, &wormhole_sdk::config(&config.wormhole))?;
+    let bridge = wormhole_sdk::read_config(bridge).map_err(|_| MessengerError::InvalidBridgeConfig)?;
+    Ok(bridge.fee)
+}
+
+/// Read the index of the bridge's current guardian set from its config account.
+pub fn current_guardian_set(config: &Config, bridge: &AccountInfo) -> Result<u32, ProgramError> {
+    let key = wormhole_sdk::config(&config.wormhole);
+    if *bridge.key != key {
+        return Err(MessengerError::InvalidBridgeConfig.into());
+    }
+    let bridge: BridgeData = load(config, bridge)?;
+    Ok(bridge.guardian_set_index)
+}
+
+/// Pay the bridge fee for a single message from `payer`.
+pub fn pay_fee(config: &Config, payer: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let fee = fee(config, accounts)?;
+    let fee_collector = wormhole_sdk::fee_collector(&config.wormhole);
+    invoke(&transfer(payer, &fee_collector, fee), accounts)
+}
+
+/// Emit a message through the configured bridge. The fee must already have been paid. When
+/// `seeds` is `None` the program's default emitter from `wormhole_sdk::emitter` is used.
+///
+/// The message account is a PDA of this program, `message_seeds` (including the bump) are used
+/// to sign for it alongside the emitter.
+#[allow(clippy::too_many_arguments)]
+pub fn post_message(
+    config: &Config,
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    message: &Pubkey,
+    message_seeds: &[&[u8]],
+    payload: Vec<u8>,
+    consistency: Consistency,
+    seeds: Option<&[&[u8]]>,
+    accounts: &[AccountInfo],
+    nonce: u32,
+) -> ProgramResult {
+    let (emitter, mut emitter_seeds, bump) = match seeds {
+        None => wormhole_sdk::emitter(program_id),
+        Some(seeds) => {
+            let (emitter, bump) = Pubkey::find_program_address(seeds, program_id);
+            (emitter, seeds.to_vec(), bump)
+        }
+    };
+    let bump = &[bump];
+    emitter_seeds.push(bump);
+
+    invoke_signed(
+        &instructions::post_message(
+            config.wormhole,
+            *payer,
+            emitter,
+            *message,
+            nonce,
+            payload,
+            consistency.into(),
+        )
+        .unwrap(),
+        accounts,
+        &[&emitter_seeds, message_seeds],
+    )
+}
@@ -0,0 +1,115 @@
+//! Helpers for other programs sending messages through the Messenger by CPI.
+//!
+//! A calling program depends on this crate with the `no-entrypoint` feature, passes the accounts
+//! listed on `Instruction::SendProgramMessage` through to its own instruction, and calls
+//! `send_message`. The Messenger records the calling program's id as the message sender.
+//!
+//! ```ignore
+//! let accounts = cpi::SendMessageAccounts::new(program_id, &accs[..17])?;
+//! cpi::send_message(&accounts, payload, nonce, None, target_chain)?;
+//! ```
+//!
+//! Programs receiving routed messages use `parse_callback` to check a callback really came from
+//! the Messenger before acting on it.
+
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use messenger_common::Message;
+
+use crate::error::MessengerError;
+use crate::route::Callback;
+use crate::state::{
+    self,
+    Consistency,
+};
+
+
+/// Accounts needed to send a message from a calling program, in the order the Messenger expects.
+pub struct SendMessageAccounts<'a, 'b> {
+    /// The program sending the message, this must be the currently executing program.
+    pub caller: Pubkey,
+
+    /// The Messenger program.
+    pub messenger: &'a AccountInfo<'b>,
+
+    /// Every account listed on `Instruction::SendProgramMessage`, payer first.
+    pub accounts: &'a [AccountInfo<'b>],
+}
+
+impl<'a, 'b> SendMessageAccounts<'a, 'b> {
+    /// Wrap the Messenger program account followed by the accounts for `SendProgramMessage`.
+    pub fn new(caller: Pubkey, accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let (messenger, accounts) = accounts.split_first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if accounts.len() < 16 {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        Ok(SendMessageAccounts {
+            caller,
+            messenger,
+            accounts,
+        })
+    }
+}
+
+
+/// Send `payload` to `target_chain` through the Messenger, signing as the calling program.
+/// `consistency` falls back to the Messenger's configured default when `None`. The destination
+/// account passed in must be the one for `target_chain`. The payer is charged the protocol fee.
+pub fn send_message(
+    accounts: &SendMessageAccounts,
+    payload: Message,
+    nonce: u32,
+    consistency: Option<Consistency>,
+    target_chain: u16,
+) -> ProgramResult {
+    let program_id = *accounts.messenger.key;
+    let bump = state::program_sender(&accounts.caller).1;
+    let wormhole = *accounts.accounts[13].key;
+    let count = state::load::<state::Sender>(&program_id, &accounts.accounts[3])
+        .map(|sender| sender.count)
+        .unwrap_or(0);
+
+    let instruction = crate::instruction::send_program_message(
+        program_id,
+        wormhole,
+        *accounts.accounts[0].key,
+        accounts.caller,
+        count,
+        payload,
+        nonce,
+        consistency,
+        target_chain,
+    );
+
+    // The runtime needs the program being invoked alongside the instruction's accounts.
+    let mut infos = accounts.accounts.to_vec();
+    infos.push(accounts.messenger.clone());
+    invoke_signed(&instruction, &infos, &[&[b"messenger_sender", &[bump]]])
+}
+
+
+/// Decode a routed message delivered to a target program, checking the first account is the
+/// `messenger` program's callback authority and that it signed. Returns `None` when `data` is not
+/// a callback, so targets can fall through to their own instructions.
+pub fn parse_callback(
+    messenger: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> Result<Option<Callback>, ProgramError> {
+    let callback = match Callback::parse(data) {
+        Some(callback) => callback,
+        None => return Ok(None),
+    };
+
+    let authority = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if !authority.is_signer || *authority.key != state::callback_authority(messenger).0 {
+        return Err(MessengerError::InvalidCallback.into());
+    }
+
+    Ok(Some(callback))
+}
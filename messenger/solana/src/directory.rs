@@ -0,0 +1,93 @@
+//! The nickname directory.
+//!
+//! Nicknames in messages are free text, so the directory binds each registered nick to the
+//! address that registered it. RegisterNick adds a nick registered here and posts a
+//! `Registration` so other chains can add it too. RecvRegistration copies in a registration from
+//! another chain, provided it comes from an emitter the admin has registered as a route to the
+//! Messenger itself, that is another Messenger deployment. When two registrations for the same
+//! nick meet the first registered wins, see `Claim::precedes`.
+//!
+//! Received messages whose nick matches the directory entry for their sender are shown as
+//! verified, provided they too come from another Messenger deployment. Any other emitter could
+//! put whatever sender it liked in its envelopes.
+
+use solana_program::account_info::AccountInfo;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use messenger_common::{
+    Claim,
+    Envelope,
+};
+
+use crate::bridge;
+use crate::events::Event;
+use crate::route;
+use crate::state::{
+    self,
+    Nick,
+};
+
+
+/// Claim `nick` for `claim`'s owner, creating its directory entry if needed. Returns `false`,
+/// leaving the entry untouched, if an existing claim wins over the new one.
+pub fn claim<'a>(
+    id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system: &AccountInfo<'a>,
+    nick: &str,
+    claim: Claim,
+) -> Result<bool, ProgramError> {
+    let (key, bump) = state::nick(id, nick);
+    state::check_address(account, &key)?;
+
+    if **account.lamports.borrow() == 0 {
+        state::create(id, payer, account, system, &[b"nick", nick.as_bytes(), &[bump]], Nick::SIZE)?;
+    } else {
+        let existing: Nick = state::load(id, account)?;
+        if !claim.precedes(&existing.claim) {
+            return Ok(false);
+        }
+    }
+
+    let data = Nick {
+        nick: nick.to_string(),
+        claim,
+    };
+    state::save(account, &data)?;
+
+    Event::NickRegistered {
+        nick:  data.nick,
+        chain: claim.chain,
+        owner: claim.owner,
+    }
+    .emit()?;
+    Ok(true)
+}
+
+/// Whether a received message's nick is registered to its sender on `emitter_chain`, and the
+/// message was emitted by a Messenger there. The directory entry and the route registering the
+/// emitter are located by key, messages received without them are not verified.
+pub fn verified(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    emitter_chain: u16,
+    emitter_address: &[u8; 32],
+    envelope: &Envelope,
+) -> bool {
+    if !messenger_common::valid_nick(&envelope.message.nick) {
+        return false;
+    }
+
+    if !route::is_messenger(id, accounts, emitter_chain, emitter_address) {
+        return false;
+    }
+
+    let key = state::nick(id, &envelope.message.nick).0;
+    let entry = bridge::find(accounts, &key).and_then(|account| state::load::<Nick>(id, account));
+    match entry {
+        Ok(entry) => entry.claim.chain == emitter_chain && entry.claim.owner == envelope.sender,
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,213 @@
+//! Errors returned by the Messenger program. These are surfaced to clients as
+//! `ProgramError::Custom(n)` where `n` is the discriminant of the variant.
+
+use solana_program::program_error::ProgramError;
+use thiserror::Error;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq)]
+pub enum MessengerError {
+    /// The config account has already been initialised.
+    #[error("Config already initialised")]
+    AlreadyInitialized,
+
+    /// An account passed in did not match the PDA we expected to derive.
+    #[error("Invalid account address")]
+    InvalidAccountAddress,
+
+    /// An account passed in is not owned by the program we expected.
+    #[error("Invalid account owner")]
+    InvalidAccountOwner,
+
+    /// The Wormhole bridge config account could not be read.
+    #[error("Invalid bridge config")]
+    InvalidBridgeConfig,
+
+    /// The signer is not the admin recorded in the config account.
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    /// The requested consistency level is not allowed by the config.
+    #[error("Consistency level not allowed")]
+    ConsistencyNotAllowed,
+
+    /// The VAA account is not owned by the configured Wormhole bridge.
+    #[error("Invalid SignedVAA owner")]
+    InvalidSignedVAAOwner,
+
+    /// A sponsor tried to withdraw more than they have in the fee vault.
+    #[error("Insufficient sponsor funds")]
+    InsufficientSponsorFunds,
+
+    /// A batch was empty or held more messages than fit in a single instruction.
+    #[error("Invalid batch size")]
+    InvalidBatchSize,
+
+    /// A message was routed to a program that has not registered its emitter.
+    #[error("Emitter not registered for target")]
+    UnregisteredEmitter,
+
+    /// A routed or room message has already been delivered to its target or inbox.
+    #[error("Message already received")]
+    AlreadyReceived,
+
+    /// Callback data or accounts passed to a target program were not from the Messenger.
+    #[error("Invalid callback")]
+    InvalidCallback,
+
+    /// The sender has used up their messages for the current rate limit window.
+    #[error("Sender rate limit exceeded")]
+    RateLimited,
+
+    /// The cap on messages to the destination chain has been reached for the current window.
+    #[error("Destination chain cap reached")]
+    DestinationCapReached,
+
+    /// A channel name was empty or too long to derive an emitter from.
+    #[error("Invalid channel")]
+    InvalidChannel,
+
+    /// The guardian set that signed a received VAA has expired.
+    #[error("Guardian set expired")]
+    GuardianSetExpired,
+
+    /// A received VAA was emitted at a lower consistency level than its emitter's policy allows.
+    #[error("Insufficient consistency level")]
+    InsufficientConsistency,
+
+    /// A routed message was emitted before its emitter's chain considers it final.
+    #[error("Message not final")]
+    NotFinal,
+
+    /// A routed message was included in a RecvBatch, routed messages must be received alone.
+    #[error("Routed message in batch")]
+    RoutedInBatch,
+
+    /// A room account already exists for the id given to CreateRoom.
+    #[error("Room already exists")]
+    RoomExists,
+
+    /// A room, or its inbox, has not been created.
+    #[error("Unknown room")]
+    UnknownRoom,
+
+    /// The sender is not a member of the room they tried to post to.
+    #[error("Not a room member")]
+    NotRoomMember,
+
+    /// The sender is a member of the room but has not been allowed to post.
+    #[error("Not allowed to post in room")]
+    CannotPost,
+
+    /// The room already has `state::MAX_MEMBERS` members.
+    #[error("Room full")]
+    RoomFull,
+
+    /// A room message was included in a RecvBatch, room messages must be received alone.
+    #[error("Room message in batch")]
+    RoomInBatch,
+
+    /// A nickname was empty or longer than `MAX_NICK_LEN`.
+    #[error("Invalid nickname")]
+    InvalidNick,
+
+    /// The nickname is already registered in the directory.
+    #[error("Nickname taken")]
+    NickTaken,
+
+    /// A VAA passed to RecvRegistration did not carry a nickname registration.
+    #[error("Invalid registration")]
+    InvalidRegistration,
+
+    /// A block list entry was removed that does not exist.
+    #[error("Sender not blocked")]
+    NotBlocked,
+
+    /// The admin has paused sending.
+    #[error("Sending paused")]
+    SendingPaused,
+
+    /// The admin has paused receiving.
+    #[error("Receiving paused")]
+    ReceivingPaused,
+
+    /// The governance emitter has already been set, it can't be changed by the admin.
+    #[error("Governance already set")]
+    GovernanceAlreadySet,
+
+    /// A VAA passed as governance was not from the governance emitter, or not an upgrade for
+    /// this chain and buffer.
+    #[error("Invalid governance VAA")]
+    InvalidGovernanceVAA,
+
+    /// A scheduled message with the id given to ScheduleMessage already exists.
+    #[error("Scheduled message exists")]
+    ScheduleExists,
+
+    /// The scheduled message has not been created, or was already posted or cancelled.
+    #[error("Unknown scheduled message")]
+    UnknownSchedule,
+
+    /// A crank was turned for a scheduled message before it was due.
+    #[error("Scheduled message not due")]
+    NotDue,
+
+    /// A multisig was created with no signers, more than `MAX_SIGNERS`, a repeated signer, or a
+    /// threshold of zero or above the number of signers.
+    #[error("Invalid multisig")]
+    InvalidMultisig,
+
+    /// The multisig has already been created.
+    #[error("Multisig exists")]
+    MultisigExists,
+
+    /// The signer is not one of the multisig's signers.
+    #[error("Not a multisig signer")]
+    NotMultisigSigner,
+
+    /// The proposal has not been created, or was already posted or cancelled.
+    #[error("Unknown proposal")]
+    UnknownProposal,
+
+    /// The proposal expired before reaching its threshold.
+    #[error("Proposal expired")]
+    ProposalExpired,
+
+    /// The signer has already approved the proposal.
+    #[error("Already approved")]
+    AlreadyApproved,
+
+    /// An account's header names a different kind of account than the one expected.
+    #[error("Invalid account kind")]
+    InvalidAccountKind,
+
+    /// An account was written in a layout this version of the program can't read, either by a
+    /// newer version or an older one it has no upgrade from.
+    #[error("Unsupported account version")]
+    UnsupportedVersion,
+
+    /// A received message is meant for a chain other than this one.
+    #[error("Wrong target chain")]
+    WrongTargetChain,
+
+    /// A rate limit has a limit but no window to count it over.
+    #[error("Invalid rate limit")]
+    InvalidRateLimit,
+
+    /// A direct message was included in a RecvBatch, direct messages must be received alone.
+    #[error("Direct message in batch")]
+    RecipientInBatch,
+
+    /// A governance VAA was signed by a guardian set other than the bridge's current one.
+    #[error("Guardian set not current")]
+    GuardianSetNotCurrent,
+
+    /// A scheduled message's escrow no longer covers the current fees and its reward.
+    #[error("Insufficient escrow")]
+    InsufficientEscrow,
+}
+
+impl From<MessengerError> for ProgramError {
+    fn from(e: MessengerError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
@@ -0,0 +1,270 @@
+//! Structured events emitted by the Messenger, for indexers and other off-chain tools.
+//!
+//! Each event is logged as a single `Program data: <base64>` line, the format `sol_log_data`
+//! produces, where the decoded bytes are `EVENT_VERSION` followed by a borsh encoded `Event`.
+//! solana-program 1.7, which the bridge SDK pins us to, predates `sol_log_data` so for now the
+//! line is written with `msg!` and shows up behind the usual `Program log: ` prefix. `decode_logs`
+//! accepts both forms, so tools keep working when we move to `sol_log_data`.
+
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
+use solana_program::msg;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::state::Consistency;
+
+
+/// Version of the event schema below. New variants may be appended without bumping this, any
+/// change to an existing variant's layout must bump it.
+pub const EVENT_VERSION: u8 = 1;
+
+/// Prefix `sol_log_data` gives each line it logs.
+const DATA_PREFIX: &str = "Program data: ";
+
+/// Prefix `msg!` gives each line it logs.
+const LOG_PREFIX: &str = "Program log: ";
+
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A message was posted to Wormhole.
+    MessageSent {
+        /// Sender recorded in the envelope, a user key or the calling program's id.
+        sender:      [u8; 32],
+        message:     Pubkey,
+        count:       u64,
+        nonce:       u32,
+        consistency: Consistency,
+    },
+
+    /// A verified message was received, and forwarded if it had a target.
+    MessageReceived {
+        emitter_chain:   u16,
+        emitter_address: [u8; 32],
+        sequence:        u64,
+        sender:          [u8; 32],
+        target:          Option<[u8; 32]>,
+    },
+
+    /// An emitter on a foreign chain was registered to route messages to `program`.
+    ChainRegistered {
+        program: Pubkey,
+        chain:   u16,
+        emitter: [u8; 32],
+    },
+
+    /// A received message was refused. `error` is the `ProgramError` the instruction failed
+    /// with, as a `u64`, for `MessengerError`s that is the error's discriminant.
+    Rejected {
+        emitter_chain:   u16,
+        emitter_address: [u8; 32],
+        sequence:        u64,
+        error:           u64,
+    },
+
+    /// A VAA in a RecvBatch was not received, the rest of the batch carried on. `error` is as
+    /// for `Rejected`.
+    Skipped {
+        vaa:   Pubkey,
+        error: u64,
+    },
+
+    /// A nickname was added to the directory, or passed to an earlier registration from another
+    /// chain. `owner` is the padded address on `chain` it is registered to.
+    NickRegistered {
+        nick:  String,
+        chain: u16,
+        owner: [u8; 32],
+    },
+
+    /// A received message was dropped because `recipient` has blocked its sender. The receive
+    /// itself succeeds, so relayers aren't penalised for delivering it.
+    Dropped {
+        emitter_chain:   u16,
+        emitter_address: [u8; 32],
+        sequence:        u64,
+        sender:          [u8; 32],
+        recipient:       Pubkey,
+    },
+
+    /// The admin paused or resumed sending and receiving.
+    PauseChanged {
+        send_paused:    bool,
+        receive_paused: bool,
+    },
+
+    /// The program was upgraded to `buffer` by a governance VAA.
+    ContractUpgraded {
+        buffer: Pubkey,
+    },
+
+    /// A scheduled message was posted by `cranker`, who was paid `reward` lamports on top of the
+    /// fees they paid.
+    Cranked {
+        sender:  Pubkey,
+        id:      u64,
+        cranker: Pubkey,
+        reward:  u64,
+    },
+
+    /// A multisig signer proposed a message, which is `proposal` in the multisig's count.
+    Proposed {
+        multisig: Pubkey,
+        proposal: u64,
+        proposer: Pubkey,
+        expiry:   i64,
+    },
+
+    /// A multisig signer approved a proposal, which now has `votes` approvals.
+    Approved {
+        multisig: Pubkey,
+        proposal: u64,
+        signer:   Pubkey,
+        votes:    u8,
+    },
+
+    /// A proposal reached its threshold and was posted as the multisig's `count`th message.
+    ProposalExecuted {
+        multisig: Pubkey,
+        proposal: u64,
+        count:    u64,
+    },
+
+    /// A proposal was cancelled, or closed after it expired.
+    ProposalCancelled {
+        multisig: Pubkey,
+        proposal: u64,
+    },
+
+    /// An account written in layout version `from` was rewritten in the current layout `to`.
+    Migrated {
+        account: Pubkey,
+        from:    u8,
+        to:      u8,
+    },
+}
+
+impl Event {
+    /// Versioned encoding of the event, as carried in a log line.
+    pub fn encode(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![EVENT_VERSION];
+        self.serialize(&mut data)?;
+        Ok(data)
+    }
+
+    /// Decode an event from its versioned encoding. Returns `None` for other schema versions or
+    /// data that is not an event.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        match data.split_first() {
+            Some((&EVENT_VERSION, event)) => Event::try_from_slice(event).ok(),
+            _ => None,
+        }
+    }
+
+    /// Log the event.
+    pub fn emit(&self) -> Result<(), ProgramError> {
+        msg!("{}{}", DATA_PREFIX, base64::encode(self.encode()?));
+        Ok(())
+    }
+}
+
+
+/// Decode the events `program_id` emitted from a transaction's log messages. Lines logged by
+/// other programs, including ones we invoke, are skipped by following the runtime's
+/// `invoke`/`success`/`failed` lines.
+pub fn decode_logs(program_id: &Pubkey, logs: &[String]) -> Vec<Event> {
+    let program_id = program_id.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        // Runtime lines look like `Program <id> invoke [1]`, program output starts with
+        // `Program log:` or `Program data:` instead of an id.
+        let mut words = line.split(' ');
+        match (words.next(), words.next(), words.next()) {
+            (Some("Program"), Some("log:"), _) | (Some("Program"), Some("data:"), _) => {}
+            (Some("Program"), Some(program), Some("invoke")) => {
+                stack.push(program);
+                continue;
+            }
+            (Some("Program"), Some(_), Some("success")) | (Some("Program"), Some(_), Some("failed:")) => {
+                stack.pop();
+                continue;
+            }
+            _ => {}
+        }
+
+        if stack.last() != Some(&program_id.as_str()) {
+            continue;
+        }
+
+        let data = line.strip_prefix(LOG_PREFIX).unwrap_or(line);
+        if let Some(event) = data
+            .strip_prefix(DATA_PREFIX)
+            .and_then(|data| base64::decode(data).ok())
+            .and_then(|data| Event::decode(&data))
+        {
+            events.push(event);
+        }
+    }
+
+    events
+}
+
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn line(event: &Event) -> String {
+        format!("{}{}", DATA_PREFIX, base64::encode(event.encode().unwrap()))
+    }
+
+    #[test]
+    fn test_decode_logs() {
+        let id = Pubkey::new_from_array([2; 32]);
+        let other = Pubkey::new_from_array([3; 32]);
+        let event = Event::ChainRegistered {
+            program: other,
+            chain:   2,
+            emitter: [4; 32],
+        };
+
+        let logs = vec![
+            format!("Program {} invoke [1]", id),
+            format!("{}{}", LOG_PREFIX, line(&event)),
+            format!("Program {} invoke [2]", other),
+            line(&event),
+            format!("Program {} success", other),
+            line(&event),
+            "Program log: Message: Alice: Hello".to_string(),
+            format!("{}Message: {}", LOG_PREFIX, line(&event)),
+            "Program log: success".to_string(),
+            format!("Program {} success", id),
+            line(&event),
+        ];
+
+        // Only the two lines logged by our program at the top of the stack are decoded, messages
+        // shown with an event as their text are not.
+        assert_eq!(decode_logs(&id, &logs), vec![event.clone(), event]);
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut data = Event::Rejected {
+            emitter_chain:   1,
+            emitter_address: [0; 32],
+            sequence:        0,
+            error:           0,
+        }
+        .encode()
+        .unwrap();
+        assert!(Event::decode(&data).is_some());
+
+        data[0] = EVENT_VERSION + 1;
+        assert!(Event::decode(&data).is_none());
+    }
+}
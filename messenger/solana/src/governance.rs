@@ -0,0 +1,160 @@
+//! Upgrades authorised by governance VAAs.
+//!
+//! As with the core bridge's `Governance.sol`, the Messenger trusts a single governance emitter,
+//! set once by the admin, and acts on `ContractUpgrade` payloads it signs for Solana. The program's
+//! upgrade authority must be handed to the PDA derived from `[b"upgrade"]`, after which only a
+//! governance VAA can replace the program, with a buffer written by anyone. Each governance VAA
+//! is consumed by a receipt so it can't be replayed. Governance VAAs must also be signed by the
+//! bridge's current guardian set.
+//!
+//! Before the hand over, the deployer's upgrade authority is also what approves Initialize, see
+//! `check_upgrade_authority`.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::bpf_loader_upgradeable::{
+    self,
+    UpgradeableLoaderState,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use messenger_common::{
+    ContractUpgrade,
+    CHAIN_ID_SOLANA,
+};
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::policy;
+use crate::state::{
+    self,
+    Config,
+};
+
+
+/// Set the emitter governance VAAs must come from. Admin only, and only once so the admin can't
+/// route around governance afterwards.
+pub fn set_governance(id: &Pubkey, accs: &[AccountInfo], chain: u16, emitter: [u8; 32]) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let mut data: Config = state::load(id, config)?;
+    data.check_admin(admin)?;
+
+    if data.governance_chain != 0 {
+        return Err(MessengerError::GovernanceAlreadySet.into());
+    }
+    if chain == 0 {
+        return Err(MessengerError::InvalidGovernanceVAA.into());
+    }
+
+    data.governance_chain   = chain;
+    data.governance_emitter = emitter;
+    state::save(config, &data)
+}
+
+/// Upgrade the program to the buffer named in a governance VAA.
+pub fn upgrade(id: &Pubkey, accs: &[AccountInfo]) -> ProgramResult {
+    let accounts      = &mut accs.iter();
+    let payer         = next_account_info(accounts)?;
+    let vaa           = next_account_info(accounts)?;
+    let config        = next_account_info(accounts)?;
+    let signature_set = next_account_info(accounts)?;
+    let guardian_set  = next_account_info(accounts)?;
+    let policy        = next_account_info(accounts)?;
+    let receipt       = next_account_info(accounts)?;
+    let authority     = next_account_info(accounts)?;
+    let program_data  = next_account_info(accounts)?;
+    let program       = next_account_info(accounts)?;
+    let buffer        = next_account_info(accounts)?;
+    let rent          = next_account_info(accounts)?;
+    let clock         = next_account_info(accounts)?;
+    let system        = next_account_info(accounts)?;
+    let loader        = next_account_info(accounts)?;
+    let bridge_config = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+
+    // Governance must be signed by the current guardian set, and from the governance emitter.
+    let posted = crate::read_posted(&config, vaa)?;
+    policy::check(id, &config, &posted, true, signature_set, guardian_set, policy)?;
+    let signatures: bridge::SignatureSet = bridge::load(&config, signature_set)?;
+    if signatures.guardian_set_index != bridge::current_guardian_set(&config, bridge_config)? {
+        return Err(MessengerError::GuardianSetNotCurrent.into());
+    }
+    if config.governance_chain == 0
+        || posted.emitter_chain != config.governance_chain
+        || posted.emitter_address != config.governance_emitter
+    {
+        return Err(MessengerError::InvalidGovernanceVAA.into());
+    }
+
+    let upgrade = ContractUpgrade::parse(&posted.payload).ok_or(MessengerError::InvalidGovernanceVAA)?;
+    if upgrade.chain != CHAIN_ID_SOLANA || upgrade.new_contract != buffer.key.to_bytes() {
+        return Err(MessengerError::InvalidGovernanceVAA.into());
+    }
+
+    // As for routed messages, a receipt marks the VAA consumed.
+    let (key, bump) = state::receipt(id, vaa.key);
+    state::check_address(receipt, &key)?;
+    if **receipt.lamports.borrow() != 0 {
+        return Err(MessengerError::AlreadyReceived.into());
+    }
+    state::create(id, payer, receipt, system, &[b"receipt", vaa.key.as_ref(), &[bump]], 0)?;
+
+    let (key, bump) = state::upgrade_authority(id);
+    state::check_address(authority, &key)?;
+    state::check_address(program_data, &state::program_data(id))?;
+    state::check_address(program, id)?;
+
+    // The loader checks the program data account belongs to the program, and that we are its
+    // upgrade authority. The buffer's rent is refunded to the payer.
+    invoke_signed(
+        &bpf_loader_upgradeable::upgrade(id, buffer.key, authority.key, payer.key),
+        &[
+            program_data.clone(),
+            program.clone(),
+            buffer.clone(),
+            payer.clone(),
+            rent.clone(),
+            clock.clone(),
+            authority.clone(),
+            loader.clone(),
+        ],
+        &[&[b"upgrade", &[bump]]],
+    )?;
+
+    Event::ContractUpgraded { buffer: *buffer.key }.emit()
+}
+
+/// Check `authority` signed and is the upgrade authority the loader has recorded for the program
+/// in its program data account.
+pub fn check_upgrade_authority(id: &Pubkey, authority: &AccountInfo, program_data: &AccountInfo) -> ProgramResult {
+    state::check_address(program_data, &state::program_data(id))?;
+    if *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(MessengerError::InvalidAccountOwner.into());
+    }
+
+    // The program's code follows the metadata, which bincode ignores.
+    let upgrade_authority = match bincode::deserialize(&program_data.data.borrow()) {
+        Ok(UpgradeableLoaderState::ProgramData {
+            upgrade_authority_address,
+            ..
+        }) => upgrade_authority_address,
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+
+    if !authority.is_signer || upgrade_authority != Some(*authority.key) {
+        return Err(MessengerError::Unauthorized.into());
+    }
+    Ok(())
+}
@@ -3,13 +3,13 @@ use solana_program::instruction::{
     AccountMeta,
     Instruction,
 };
+use solana_program::bpf_loader_upgradeable;
 use solana_program::pubkey::Pubkey;
 use solana_program::system_program;
 use solana_program::sysvar::rent;
 use solana_program::sysvar::clock;
 
 use wormhole_sdk::{
-    id,
     config,
     fee_collector,
     sequence,
@@ -17,92 +17,1008 @@ use wormhole_sdk::{
 
 use messenger_common::Message;
 
+use crate::state::{
+    BlockMode,
+    Consistency,
+    Due,
+    RateLimit,
+};
 use crate::Instruction::{
+    Approve,
+    CancelProposal,
+    CancelScheduled,
+    Crank,
+    CreateMultisig,
+    CreateRoom,
+    Deposit,
+    Initialize,
+    Migrate,
+    Propose,
+    RecvBatch,
     RecvMessage,
+    RecvRegistration,
+    RegisterNick,
+    RegisterRoute,
+    RemoveRoomMember,
+    RemoveRoute,
+    ScheduleMessage,
+    SendBatch,
     SendMessage,
+    SendMessageRaw,
+    SendProgramMessage,
+    SendRoomMessage,
+    SetBlock,
+    SetConfig,
+    SetDestinationLimit,
+    SetEmitterPolicy,
+    SetExempt,
+    SetGovernance,
+    SetPaused,
+    SetProtocolFee,
+    SetRateLimit,
+    SetRoomMember,
+    Sponsor,
+    UpgradeContract,
+    Withdraw,
+    WithdrawTreasury,
 };
 
 
-/// Create a RecvMessage instruction.
-pub fn recv_message(program_id: Pubkey, payer: Pubkey, vaa: Pubkey) -> Instruction {
+/// Create an Initialize instruction, the payer becomes the config admin. `authority` is the
+/// program's upgrade authority, which must also sign.
+pub fn initialize(
+    program_id: Pubkey,
+    payer: Pubkey,
+    authority: Pubkey,
+    wormhole: Pubkey,
+    default_consistency: Consistency,
+    allowed_consistency: Vec<Consistency>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(crate::state::config(&program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new_readonly(crate::state::program_data(&program_id), false),
+        ],
+        data: Initialize(wormhole, default_consistency, allowed_consistency)
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+
+/// Create a SetConfig instruction.
+pub fn set_config(
+    program_id: Pubkey,
+    admin: Pubkey,
+    wormhole: Pubkey,
+    default_consistency: Consistency,
+    allowed_consistency: Vec<Consistency>,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new(crate::state::config(&program_id).0, false),
+        ],
+        data: SetConfig(wormhole, default_consistency, allowed_consistency)
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Create a SetPaused instruction.
+pub fn set_paused(program_id: Pubkey, admin: Pubkey, send: bool, receive: bool) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new(crate::state::config(&program_id).0, false),
+        ],
+        data: SetPaused(send, receive).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a Deposit instruction, moving `amount` lamports from the sponsor into the fee vault.
+pub fn deposit(program_id: Pubkey, sponsor: Pubkey, amount: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(sponsor, true),
+            AccountMeta::new(crate::state::sponsor(&program_id, &sponsor).0, false),
+            AccountMeta::new(crate::state::fee_vault(&program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: Deposit(amount).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a Withdraw instruction, returning `amount` unspent lamports to the sponsor.
+pub fn withdraw(program_id: Pubkey, sponsor: Pubkey, amount: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(sponsor, true),
+            AccountMeta::new(crate::state::sponsor(&program_id, &sponsor).0, false),
+            AccountMeta::new(crate::state::fee_vault(&program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: Withdraw(amount).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a Sponsor instruction granting `user` a quota of sponsored messages.
+pub fn sponsor(program_id: Pubkey, sponsor: Pubkey, user: Pubkey, quota: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(sponsor, true),
+            AccountMeta::new(crate::state::sponsorship(&program_id, &sponsor, &user).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: Sponsor(user, quota).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Append the accounts needed to have a SendMessage instruction's bridge fee paid by `sponsor`
+/// out of the fee vault. The payer must have been granted a sponsorship by `sponsor`.
+pub fn sponsored(mut instruction: Instruction, sponsor: Pubkey) -> Instruction {
+    let program_id = instruction.program_id;
+    let payer = instruction.accounts[0].pubkey;
+    instruction.accounts.extend(vec![
+        AccountMeta::new(crate::state::sponsorship(&program_id, &sponsor, &payer).0, false),
+        AccountMeta::new(crate::state::sponsor(&program_id, &sponsor).0, false),
+        AccountMeta::new(crate::state::fee_vault(&program_id).0, false),
+    ]);
+    instruction
+}
+
+
+/// Accounts the receive policy needs for a posted VAA, see `policy`. `signature_set` is the
+/// PostedVAA's `vaa_signature_account` and `guardian_set` the bridge's account for the guardian set
+/// that signed it.
+fn policy_accounts(
+    program_id: Pubkey,
+    signature_set: Pubkey,
+    guardian_set: Pubkey,
+    emitter_chain: u16,
+    emitter_address: [u8; 32],
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new_readonly(signature_set, false),
+        AccountMeta::new_readonly(guardian_set, false),
+        AccountMeta::new_readonly(
+            crate::state::emitter_policy(&program_id, emitter_chain, &emitter_address).0,
+            false,
+        ),
+    ]
+}
+
+/// Create a RecvMessage instruction for a VAA from `emitter_address` on `emitter_chain`, posted
+/// with `signature_set` and signed by `guardian_set`.
+pub fn recv_message(
+    program_id: Pubkey,
+    payer: Pubkey,
+    vaa: Pubkey,
+    signature_set: Pubkey,
+    guardian_set: Pubkey,
+    emitter_chain: u16,
+    emitter_address: [u8; 32],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(payer, true),
+        AccountMeta::new_readonly(vaa, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+    ];
+    accounts.extend(policy_accounts(program_id, signature_set, guardian_set, emitter_chain, emitter_address));
+
     Instruction {
         program_id,
         data: RecvMessage.try_to_vec().unwrap(),
+        accounts,
+    }
+}
+
+
+/// Append the accounts a RecvMessage instruction needs to deliver message `sequence`, posted to
+/// `room`, owned by `owner`, from `sender` via the Messenger at `emitter_address` on
+/// `emitter_chain`: the room, its inbox, the route to ourselves registering the emitter, the
+/// owner's block list entry for the sender, the delivery receipt and the system program.
+pub fn to_inbox(
+    mut instruction: Instruction,
+    room: u64,
+    owner: &Pubkey,
+    emitter_chain: u16,
+    emitter_address: &[u8; 32],
+    sequence: u64,
+    sender: &[u8; 32],
+) -> Instruction {
+    let program_id = instruction.program_id;
+    instruction.accounts.extend(vec![
+        AccountMeta::new_readonly(crate::state::room(&program_id, room).0, false),
+        AccountMeta::new(crate::state::inbox(&program_id, room).0, false),
+        AccountMeta::new_readonly(crate::state::route(&program_id, &program_id, emitter_chain, emitter_address).0, false),
+        AccountMeta::new_readonly(crate::state::block(&program_id, owner, emitter_chain, sender).0, false),
+        AccountMeta::new(crate::state::delivery(&program_id, emitter_chain, emitter_address, sequence).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]);
+    instruction
+}
+
+/// Append `recipient`'s block list entry for `sender` on `emitter_chain` to a RecvMessage
+/// instruction for a direct message, so the recipient's list can be applied.
+pub fn to_recipient(mut instruction: Instruction, recipient: &Pubkey, emitter_chain: u16, sender: &[u8; 32]) -> Instruction {
+    let program_id = instruction.program_id;
+    instruction.accounts.push(AccountMeta::new_readonly(
+        crate::state::block(&program_id, recipient, emitter_chain, sender).0,
+        false,
+    ));
+    instruction
+}
+
+/// Append the directory entry for `nick` to a RecvMessage instruction, along with the route to
+/// ourselves registering the Messenger at `emitter_address` on `emitter_chain`, so a message sent
+/// under that nick is shown as verified if it is registered to the sender. Nicks that can't be
+/// registered are left out.
+pub fn with_nick(mut instruction: Instruction, nick: &str, emitter_chain: u16, emitter_address: &[u8; 32]) -> Instruction {
+    if !messenger_common::valid_nick(nick) {
+        return instruction;
+    }
+
+    let program_id = instruction.program_id;
+    instruction.accounts.extend(vec![
+        AccountMeta::new_readonly(crate::state::nick(&program_id, nick).0, false),
+        AccountMeta::new_readonly(crate::state::route(&program_id, &program_id, emitter_chain, emitter_address).0, false),
+    ]);
+    instruction
+}
+
+/// Create a RecvRegistration instruction for a nickname registration from `emitter_address` on
+/// `emitter_chain`. The signature and guardian sets are as for `recv_message`.
+#[allow(clippy::too_many_arguments)]
+pub fn recv_registration(
+    program_id: Pubkey,
+    payer: Pubkey,
+    vaa: Pubkey,
+    signature_set: Pubkey,
+    guardian_set: Pubkey,
+    emitter_chain: u16,
+    emitter_address: [u8; 32],
+    nick: &str,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(payer, true),
+        AccountMeta::new_readonly(vaa, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+    ];
+    accounts.extend(policy_accounts(program_id, signature_set, guardian_set, emitter_chain, emitter_address));
+    accounts.extend(vec![
+        AccountMeta::new_readonly(
+            crate::state::route(&program_id, &program_id, emitter_chain, &emitter_address).0,
+            false,
+        ),
+        AccountMeta::new(crate::state::nick(&program_id, nick).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]);
+
+    Instruction {
+        program_id,
+        data: RecvRegistration.try_to_vec().unwrap(),
+        accounts,
+    }
+}
+
+
+/// Create a SetGovernance instruction.
+pub fn set_governance(program_id: Pubkey, admin: Pubkey, chain: u16, emitter: [u8; 32]) -> Instruction {
+    Instruction {
+        program_id,
         accounts: vec![
-            AccountMeta::new_readonly(payer, true),
-            AccountMeta::new_readonly(vaa, false),
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new(crate::state::config(&program_id).0, false),
         ],
+        data: SetGovernance(chain, emitter).try_to_vec().unwrap(),
     }
 }
 
+/// Create an UpgradeContract instruction upgrading the program to `buffer`, as authorised by the
+/// posted governance VAA `vaa`. The signature and guardian sets are as for `recv_message`.
+pub fn upgrade_contract(program_id: Pubkey, wormhole: Pubkey, payer: Pubkey, vaa: &PostedVaa, buffer: Pubkey) -> Instruction {
+    let program_data = crate::state::program_data(&program_id);
 
-/// Create a SendMessage instruction.
-pub fn send_message(
+    let mut accounts = vec![
+        AccountMeta::new(payer, true),
+        AccountMeta::new_readonly(vaa.vaa, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+    ];
+    accounts.extend(policy_accounts(
+        program_id,
+        vaa.signature_set,
+        vaa.guardian_set,
+        vaa.emitter_chain,
+        vaa.emitter_address,
+    ));
+    accounts.extend(vec![
+        AccountMeta::new(crate::state::receipt(&program_id, &vaa.vaa).0, false),
+        AccountMeta::new_readonly(crate::state::upgrade_authority(&program_id).0, false),
+        AccountMeta::new(program_data, false),
+        AccountMeta::new(program_id, false),
+        AccountMeta::new(buffer, false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(bpf_loader_upgradeable::id(), false),
+        AccountMeta::new_readonly(config(&wormhole), false),
+    ]);
+
+    Instruction {
+        program_id,
+        data: UpgradeContract.try_to_vec().unwrap(),
+        accounts,
+    }
+}
+
+
+/// A posted VAA and what the receive policy needs to know about it, see `recv_message`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostedVaa {
+    pub vaa:             Pubkey,
+    pub signature_set:   Pubkey,
+    pub guardian_set:    Pubkey,
+    pub emitter_chain:   u16,
+    pub emitter_address: [u8; 32],
+    pub sequence:        u64,
+}
+
+/// Create a RecvBatch instruction receiving every VAA in `vaas`, none of which may be routed.
+pub fn recv_batch(program_id: Pubkey, payer: Pubkey, vaas: &[PostedVaa]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(payer, true),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+    ];
+    for posted in vaas {
+        accounts.push(AccountMeta::new_readonly(posted.vaa, false));
+        accounts.extend(policy_accounts(
+            program_id,
+            posted.signature_set,
+            posted.guardian_set,
+            posted.emitter_chain,
+            posted.emitter_address,
+        ));
+    }
+
+    Instruction {
+        program_id,
+        data: RecvBatch.try_to_vec().unwrap(),
+        accounts,
+    }
+}
+
+
+/// Create a RecvMessage instruction for a message routed to `target`. `emitter_chain` and
+/// `emitter_address` identify the VAA's emitter, and `forwarded` are passed on to the target after
+/// the callback authority. The signature and guardian sets are as for `recv_message`.
+#[allow(clippy::too_many_arguments)]
+pub fn recv_routed(
     program_id: Pubkey,
     payer: Pubkey,
-    emitter: Pubkey,
-    message: Pubkey,
-    payload: Message,
-    nonce: u32,
+    vaa: Pubkey,
+    signature_set: Pubkey,
+    guardian_set: Pubkey,
+    target: Pubkey,
+    emitter_chain: u16,
+    emitter_address: [u8; 32],
+    forwarded: Vec<AccountMeta>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(payer, true),
+        AccountMeta::new_readonly(vaa, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+    ];
+    accounts.extend(policy_accounts(program_id, signature_set, guardian_set, emitter_chain, emitter_address));
+    accounts.extend(vec![
+        AccountMeta::new_readonly(
+            crate::state::route(&program_id, &target, emitter_chain, &emitter_address).0,
+            false,
+        ),
+        AccountMeta::new(crate::state::receipt(&program_id, &vaa).0, false),
+        AccountMeta::new_readonly(crate::state::callback_authority(&program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(target, false),
+    ]);
+    accounts.extend(forwarded);
+
+    Instruction {
+        program_id,
+        data: RecvMessage.try_to_vec().unwrap(),
+        accounts,
+    }
+}
+
+
+/// Create a RegisterRoute instruction allowing `emitter` on `chain` to route messages to
+/// `program`.
+pub fn register_route(
+    program_id: Pubkey,
+    admin: Pubkey,
+    program: Pubkey,
+    chain: u16,
+    emitter: [u8; 32],
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(admin, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::route(&program_id, &program, chain, &emitter).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: RegisterRoute(program, chain, emitter).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a RemoveRoute instruction.
+pub fn remove_route(
+    program_id: Pubkey,
+    admin: Pubkey,
+    program: Pubkey,
+    chain: u16,
+    emitter: [u8; 32],
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(admin, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::route(&program_id, &program, chain, &emitter).0, false),
+        ],
+        data: RemoveRoute(program, chain, emitter).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a SetRateLimit instruction.
+pub fn set_rate_limit(program_id: Pubkey, admin: Pubkey, limit: RateLimit) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new(crate::state::config(&program_id).0, false),
+        ],
+        data: SetRateLimit(limit).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a SetDestinationLimit instruction capping messages to `chain`.
+pub fn set_destination_limit(program_id: Pubkey, admin: Pubkey, chain: u16, limit: RateLimit) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(admin, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::destination(&program_id, chain).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: SetDestinationLimit(chain, limit).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a SetExempt instruction for `sender`, a user key or calling program id.
+pub fn set_exempt(program_id: Pubkey, admin: Pubkey, sender: Pubkey, exempt: bool) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(admin, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::sender(&program_id, &sender).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: SetExempt(sender, exempt).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a SetProtocolFee instruction.
+pub fn set_protocol_fee(program_id: Pubkey, admin: Pubkey, fee: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new(crate::state::config(&program_id).0, false),
+        ],
+        data: SetProtocolFee(fee).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a WithdrawTreasury instruction paying `amount` of the collected fees to `recipient`.
+pub fn withdraw_treasury(program_id: Pubkey, admin: Pubkey, recipient: Pubkey, amount: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(admin, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::treasury(&program_id).0, false),
+            AccountMeta::new(recipient, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: WithdrawTreasury(amount).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a SetEmitterPolicy instruction for `emitter` on `chain`. `finality` is the consistency
+/// level routed messages from the emitter must reach, if any.
+pub fn set_emitter_policy(
+    program_id: Pubkey,
+    admin: Pubkey,
+    chain: u16,
+    emitter: [u8; 32],
+    min_consistency: u8,
+    finality: Option<u8>,
 ) -> Instruction {
-    let wormhole = id();
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(admin, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::emitter_policy(&program_id, chain, &emitter).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: SetEmitterPolicy(chain, emitter, min_consistency, finality).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Create a CreateRoom instruction for room `room`, owned by `owner`.
+pub fn create_room(program_id: Pubkey, owner: Pubkey, room: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(owner, true),
+            AccountMeta::new(crate::state::room(&program_id, room).0, false),
+            AccountMeta::new(crate::state::inbox(&program_id, room).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: CreateRoom(room).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a SetRoomMember instruction adding `member` to `room`, or changing whether they can
+/// post.
+pub fn set_room_member(program_id: Pubkey, owner: Pubkey, room: u64, member: Pubkey, can_post: bool) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(owner, true),
+            AccountMeta::new(crate::state::room(&program_id, room).0, false),
+        ],
+        data: SetRoomMember(room, member, can_post).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a RemoveRoomMember instruction, signed by the room's owner or `member` themselves.
+pub fn remove_room_member(program_id: Pubkey, signer: Pubkey, room: u64, member: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(crate::state::room(&program_id, room).0, false),
+        ],
+        data: RemoveRoomMember(room, member).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a SetBlock instruction listing `sender` on `chain` in `owner`'s block list, or taking
+/// them off it if `mode` is `None`.
+pub fn set_block(program_id: Pubkey, owner: Pubkey, chain: u16, sender: [u8; 32], mode: Option<BlockMode>) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(owner, true),
+            AccountMeta::new(crate::state::block(&program_id, &owner, chain, &sender).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: SetBlock(chain, sender, mode).try_to_vec().unwrap(),
+    }
+}
+
+
+/// Bridge accounts needed to post messages from our emitter, or a channel's, shared by every send
+/// instruction.
+fn wormhole_accounts(program_id: Pubkey, wormhole: Pubkey, channel: Option<&str>) -> Vec<AccountMeta> {
+    let emitter = crate::state::emitter(&program_id, channel);
     let config = config(&wormhole);
     let fee_collector = fee_collector(&wormhole);
     let sequence = sequence(&wormhole, &emitter);
 
+    vec![
+        AccountMeta::new(fee_collector, false),
+        AccountMeta::new(config, false),
+        AccountMeta::new_readonly(emitter, false),
+        AccountMeta::new(sequence, false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new_readonly(rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(wormhole, false),
+    ]
+}
+
+/// Accounts shared by both SendMessage variants. `count` is the payer's current message count,
+/// read from their Sender account (or 0 if it doesn't exist yet).
+fn send_accounts(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    payer: Pubkey,
+    count: u64,
+    target_chain: u16,
+    channel: Option<&str>,
+) -> Vec<AccountMeta> {
+    let message = crate::state::message(&program_id, &payer, count).0;
+
     // Note that accounts are passed in in order of useful-ness. The payer and message accounts are
     // used to invoke Wormhole. Many of the example send_message* instruction handlers will only
     // pop off as many accounts as required.
+    let mut accounts = vec![
+        AccountMeta::new(payer, true),
+        AccountMeta::new(message, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+        AccountMeta::new(crate::state::sender(&program_id, &payer).0, false),
+    ];
+    accounts.extend(wormhole_accounts(program_id, wormhole, channel));
+    accounts.extend(limit_accounts(program_id, target_chain));
+    accounts
+}
+
+/// The rate limit account for `target_chain` and the treasury, which every send instruction
+/// passes last.
+fn limit_accounts(program_id: Pubkey, target_chain: u16) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(crate::state::destination(&program_id, target_chain).0, false),
+        AccountMeta::new(crate::state::treasury(&program_id).0, false),
+    ]
+}
+
+/// Create a SendMessage instruction. `wormhole` must match the bridge stored in the config
+/// account, and `consistency` falls back to the config default when `None`. The message is posted
+/// to `state::message(program_id, payer, count)`, addressed to `target_chain` (0 for any chain),
+/// from the emitter for `channel` if one is given.
+#[allow(clippy::too_many_arguments)]
+pub fn send_message(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    payer: Pubkey,
+    count: u64,
+    payload: Message,
+    nonce: u32,
+    consistency: Option<Consistency>,
+    target_chain: u16,
+    channel: Option<&str>,
+) -> Instruction {
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(payer, true),
-            AccountMeta::new(message, true),
-            AccountMeta::new(fee_collector, false),
-            AccountMeta::new(config, false),
-            AccountMeta::new_readonly(emitter, false),
-            AccountMeta::new(sequence, false),
-            AccountMeta::new_readonly(clock::id(), false),
-            AccountMeta::new_readonly(rent::id(), false),
-            AccountMeta::new_readonly(system_program::id(), false),
-            AccountMeta::new_readonly(wormhole, false),
-        ],
-        data: SendMessage(payload, nonce).try_to_vec().unwrap(),
+        accounts: send_accounts(program_id, wormhole, payer, count, target_chain, channel),
+        data: SendMessage(payload, nonce, consistency, target_chain, channel.map(str::to_string))
+            .try_to_vec()
+            .unwrap(),
     }
 }
 
 /// Create a SendMessageRaw instruction. This does the same as SendMessage however the instruction
 /// handler does not use the Wormhole SDK helper API.
+#[allow(clippy::too_many_arguments)]
 pub fn send_message_raw(
     program_id: Pubkey,
+    wormhole: Pubkey,
     payer: Pubkey,
-    emitter: Pubkey,
-    message: Pubkey,
+    count: u64,
     payload: Message,
     nonce: u32,
+    consistency: Option<Consistency>,
+    target_chain: u16,
+    channel: Option<&str>,
 ) -> Instruction {
-    let wormhole = id();
-    let config = config(&wormhole);
-    let fee_collector = fee_collector(&wormhole);
-    let sequence = sequence(&wormhole, &emitter);
+    Instruction {
+        program_id,
+        accounts: send_accounts(program_id, wormhole, payer, count, target_chain, channel),
+        data: SendMessageRaw(payload, nonce, consistency, target_chain, channel.map(str::to_string))
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Create a SendRoomMessage instruction posting to `room`, otherwise the same as `send_message`
+/// without a channel.
+#[allow(clippy::too_many_arguments)]
+pub fn send_room_message(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    payer: Pubkey,
+    count: u64,
+    room: u64,
+    payload: Message,
+    nonce: u32,
+    consistency: Option<Consistency>,
+    target_chain: u16,
+) -> Instruction {
+    let mut accounts = send_accounts(program_id, wormhole, payer, count, target_chain, None);
+    accounts.push(AccountMeta::new_readonly(crate::state::room(&program_id, room).0, false));
+
+    Instruction {
+        program_id,
+        accounts,
+        data: SendRoomMessage(room, payload, nonce, consistency, target_chain)
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Create a ScheduleMessage instruction scheduling `payload` as `sender`'s scheduled message
+/// `schedule`, to be posted once `due` with `reward` lamports for the cranker.
+#[allow(clippy::too_many_arguments)]
+pub fn schedule_message(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    sender: Pubkey,
+    schedule: u64,
+    payload: Message,
+    nonce: u32,
+    due: Due,
+    target_chain: u16,
+    reward: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(sender, true),
+            AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+            AccountMeta::new(crate::state::scheduled(&program_id, &sender, schedule).0, false),
+            AccountMeta::new_readonly(config(&wormhole), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: ScheduleMessage(schedule, payload, nonce, due, target_chain, reward)
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Create a Crank instruction posting `sender`'s scheduled message `schedule`, addressed to
+/// `target_chain`, as their `count`th message. The cranker pays the fees and is paid them and the
+/// reward from the escrow, the rest is refunded to `sender`.
+pub fn crank(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    cranker: Pubkey,
+    sender: Pubkey,
+    count: u64,
+    schedule: u64,
+    target_chain: u16,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(cranker, true),
+        AccountMeta::new(crate::state::message(&program_id, &sender, count).0, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+        AccountMeta::new(crate::state::sender(&program_id, &sender).0, false),
+    ];
+    accounts.extend(wormhole_accounts(program_id, wormhole, None));
+    accounts.extend(limit_accounts(program_id, target_chain));
+    accounts.push(AccountMeta::new(crate::state::scheduled(&program_id, &sender, schedule).0, false));
+    accounts.push(AccountMeta::new(sender, false));
 
-    // Note that accounts are passed in in order of useful-ness. The payer and message accounts are
-    // used to invoke Wormhole. Many of the example send_message* instruction handlers will only
-    // pop off as many accounts as required.
+    Instruction {
+        program_id,
+        accounts,
+        data: Crank(sender, schedule).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a CancelScheduled instruction, refunding `sender`'s scheduled message `schedule`.
+pub fn cancel_scheduled(program_id: Pubkey, sender: Pubkey, schedule: u64) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(sender, true),
+            AccountMeta::new(crate::state::scheduled(&program_id, &sender, schedule).0, false),
+        ],
+        data: CancelScheduled(schedule).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a CreateMultisig instruction creating multisig `multisig`, which posts messages once
+/// `threshold` of `signers` approve them.
+pub fn create_multisig(
+    program_id: Pubkey,
+    payer: Pubkey,
+    multisig: u64,
+    signers: Vec<Pubkey>,
+    threshold: u8,
+) -> Instruction {
     Instruction {
         program_id,
         accounts: vec![
             AccountMeta::new(payer, true),
-            AccountMeta::new(message, true),
-            AccountMeta::new(fee_collector, false),
-            AccountMeta::new(config, false),
-            AccountMeta::new_readonly(emitter, false),
-            AccountMeta::new(sequence, false),
-            AccountMeta::new_readonly(clock::id(), false),
-            AccountMeta::new_readonly(rent::id(), false),
+            AccountMeta::new(crate::state::multisig(&program_id, multisig).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: CreateMultisig(multisig, signers, threshold).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a Propose instruction proposing `payload` as `multisig`'s proposal `proposal`, which
+/// must be the multisig's current proposal count.
+#[allow(clippy::too_many_arguments)]
+pub fn propose(
+    program_id: Pubkey,
+    proposer: Pubkey,
+    multisig: u64,
+    proposal: u64,
+    payload: Message,
+    nonce: u32,
+    target_chain: u16,
+    expiry: i64,
+) -> Instruction {
+    let multisig = crate::state::multisig(&program_id, multisig).0;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(proposer, true),
+            AccountMeta::new(multisig, false),
+            AccountMeta::new(crate::state::proposal(&program_id, &multisig, proposal).0, false),
             AccountMeta::new_readonly(system_program::id(), false),
-            AccountMeta::new_readonly(wormhole, false),
         ],
-        data: SendMessage(payload, nonce).try_to_vec().unwrap(),
+        data: Propose(payload, nonce, target_chain, expiry).try_to_vec().unwrap(),
+    }
+}
+
+/// Create an Approve instruction approving `multisig`'s proposal `proposal`, made by `proposer`
+/// and addressed to `target_chain`. Should this approval meet the threshold the message is posted
+/// as the multisig's `count`th message, paid for by the signer.
+#[allow(clippy::too_many_arguments)]
+pub fn approve(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    signer: Pubkey,
+    multisig: u64,
+    proposal: u64,
+    proposer: Pubkey,
+    count: u64,
+    target_chain: u16,
+) -> Instruction {
+    let sender = crate::state::multisig(&program_id, multisig).0;
+    let mut accounts = vec![
+        AccountMeta::new(signer, true),
+        AccountMeta::new(crate::state::message(&program_id, &sender, count).0, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+        AccountMeta::new(crate::state::sender(&program_id, &sender).0, false),
+    ];
+    accounts.extend(wormhole_accounts(program_id, wormhole, None));
+    accounts.extend(limit_accounts(program_id, target_chain));
+    accounts.push(AccountMeta::new_readonly(sender, false));
+    accounts.push(AccountMeta::new(crate::state::proposal(&program_id, &sender, proposal).0, false));
+    accounts.push(AccountMeta::new(proposer, false));
+
+    Instruction {
+        program_id,
+        accounts,
+        data: Approve(multisig, proposal).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a CancelProposal instruction closing `multisig`'s proposal `proposal` and refunding
+/// `proposer`.
+pub fn cancel_proposal(
+    program_id: Pubkey,
+    caller: Pubkey,
+    proposer: Pubkey,
+    multisig: u64,
+    proposal: u64,
+) -> Instruction {
+    let multisig = crate::state::multisig(&program_id, multisig).0;
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(caller, true),
+            AccountMeta::new(proposer, false),
+            AccountMeta::new_readonly(multisig, false),
+            AccountMeta::new(crate::state::proposal(&program_id, &multisig, proposal).0, false),
+        ],
+        data: CancelProposal(proposal).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a RegisterNick instruction registering `nick` to `payer`. The registration is posted as
+/// the payer's `count`th message.
+pub fn register_nick(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    payer: Pubkey,
+    count: u64,
+    nick: &str,
+    nonce: u32,
+) -> Instruction {
+    let mut accounts = send_accounts(program_id, wormhole, payer, count, 0, None);
+    accounts.push(AccountMeta::new(crate::state::nick(&program_id, nick).0, false));
+
+    Instruction {
+        program_id,
+        accounts,
+        data: RegisterNick(nick.to_string(), nonce).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a SendProgramMessage instruction for `caller`, a program which must invoke it and sign
+/// with its `state::program_sender` PDA. `count` is the caller's current message count.
+#[allow(clippy::too_many_arguments)]
+pub fn send_program_message(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    payer: Pubkey,
+    caller: Pubkey,
+    count: u64,
+    payload: Message,
+    nonce: u32,
+    consistency: Option<Consistency>,
+    target_chain: u16,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(payer, true),
+        AccountMeta::new(crate::state::message(&program_id, &caller, count).0, false),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+        AccountMeta::new(crate::state::sender(&program_id, &caller).0, false),
+        AccountMeta::new_readonly(crate::state::program_sender(&caller).0, true),
+        AccountMeta::new_readonly(caller, false),
+    ];
+    accounts.extend(wormhole_accounts(program_id, wormhole, None));
+    accounts.extend(limit_accounts(program_id, target_chain));
+
+    Instruction {
+        program_id,
+        accounts,
+        data: SendProgramMessage(payload, nonce, consistency, target_chain).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a SendBatch instruction posting every payload under the same nonce. The messages are
+/// posted to consecutive message PDAs starting at the payer's current `count`.
+pub fn send_batch(
+    program_id: Pubkey,
+    wormhole: Pubkey,
+    payer: Pubkey,
+    count: u64,
+    payloads: Vec<Message>,
+    nonce: u32,
+    target_chain: u16,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(payer, true),
+        AccountMeta::new_readonly(crate::state::config(&program_id).0, false),
+        AccountMeta::new(crate::state::sender(&program_id, &payer).0, false),
+    ];
+    accounts.extend(wormhole_accounts(program_id, wormhole, None));
+    accounts.extend((count..count + payloads.len() as u64).map(|count| {
+        AccountMeta::new(crate::state::message(&program_id, &payer, count).0, false)
+    }));
+    accounts.extend(limit_accounts(program_id, target_chain));
+
+    Instruction {
+        program_id,
+        accounts,
+        data: SendBatch(payloads, nonce, target_chain).try_to_vec().unwrap(),
+    }
+}
+
+/// Create a Migrate instruction rewriting `account` in the current layout for its kind.
+pub fn migrate(program_id: Pubkey, account: Pubkey) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![AccountMeta::new(account, false)],
+        data: Migrate.try_to_vec().unwrap(),
     }
 }
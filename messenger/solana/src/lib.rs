@@ -14,163 +14,1283 @@ use solana_program::account_info::{
 };
 use solana_program::entrypoint::ProgramResult;
 use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
-use solana_program::{
-    entrypoint,
-    msg,
-};
+use solana_program::msg;
+use solana_program::sysvar::Sysvar;
+
+#[cfg(not(feature = "no-entrypoint"))]
+use solana_program::entrypoint;
 
 // Import Solana Wormhole SDK.
-use wormhole_sdk::{
-    instructions::post_message,
-    ConsistencyLevel,
-    VAA,
-};
+use wormhole_sdk::instructions::post_message;
+use wormhole_sdk::PostedVAAData;
 
 // Our Payload, defined in a common library.
-pub use messenger_common::Message;
+pub use messenger_common::{
+    Claim,
+    ContractUpgrade,
+    Envelope,
+    Message,
+    Registration,
+};
 
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::state::{
+    BlockMode,
+    Config,
+    Consistency,
+    Due,
+    RateLimit,
+    Sender,
+};
+
+pub mod blocks;
+pub mod bridge;
+#[cfg(feature = "no-entrypoint")]
+pub mod cpi;
+pub mod directory;
+pub mod error;
+pub mod events;
+pub mod governance;
 pub mod instruction;
+pub mod limits;
+pub mod migrate;
+pub mod multisig;
+pub mod policy;
+pub mod rooms;
+pub mod route;
+pub mod schedule;
+pub mod state;
+pub mod treasury;
+pub mod vault;
 
 #[derive(BorshSerialize, BorshDeserialize, Clone)]
 pub enum Instruction {
+    /// Create the program config account. The payer becomes the admin allowed to update it. The
+    /// program's upgrade authority must sign too, so nobody else can initialise a fresh deployment
+    /// with a bridge of their choosing before its deployer does.
+    ///
+    /// 0: Payer             [Signer]
+    /// 1: Config            [PDA]
+    /// 2: System            [Program]     -- Needed to create the config account.
+    /// 3: Upgrade Authority [Signer]
+    /// 4: Program Data      [Account]     -- The loader's record of the upgrade authority.
+    Initialize(Pubkey, Consistency, Vec<Consistency>),
+
+    /// Update the bridge id and consistency policy stored in the config account.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    SetConfig(Pubkey, Consistency, Vec<Consistency>),
+
+    /// Pause or resume sending and receiving. While paused every send or receive instruction,
+    /// including nickname registrations, fails with `SendingPaused` or `ReceivingPaused`.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    SetPaused(bool, bool),
+
+    /// Set the chain and address of the emitter whose governance VAAs may upgrade the program,
+    /// see `governance`. Admin only, and only once.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    SetGovernance(u16, [u8; 32]),
+
+    /// Upgrade the program to a buffer, as authorised by a governance VAA naming it.
+    ///
+    /// 0:  Payer             [Signer]      -- Refunded the buffer's rent.
+    /// 1:  VAA               [PDA]
+    /// 2:  Config            [PDA]
+    /// 3:  Signature Set     [Account]
+    /// 4:  Guardian Set      [PDA]
+    /// 5:  Emitter Policy    [PDA]
+    /// 6:  Receipt           [PDA]         -- Marks the VAA consumed.
+    /// 7:  Upgrade Authority [PDA]
+    /// 8:  Program Data      [Account]
+    /// 9:  Program           [Program]     -- This program.
+    /// 10: Buffer            [Account]
+    /// 11: Rent              [Program]
+    /// 12: Clock             [Program]
+    /// 13: System            [Program]
+    /// 14: BPF Loader        [Program]     -- The upgradeable loader.
+    /// 15: Bridge Config     [PDA]         -- The bridge's, for its current guardian set.
+    UpgradeContract,
+
+    /// Deposit lamports into the fee vault to sponsor other users' messages.
+    ///
+    /// 0: Sponsor         [Signer]
+    /// 1: Sponsor Balance [PDA]
+    /// 2: Fee Vault       [PDA]
+    /// 3: System          [Program]
+    Deposit(u64),
+
+    /// Withdraw unspent lamports previously deposited by the sponsor.
+    ///
+    /// 0: Sponsor         [Signer]
+    /// 1: Sponsor Balance [PDA]
+    /// 2: Fee Vault       [PDA]
+    /// 3: System          [Program]
+    Withdraw(u64),
+
+    /// Cover the bridge fee for the next `quota` messages sent by a user. A quota of 0 revokes
+    /// the sponsorship. Each sponsor's grant to a user is a separate PDA, see
+    /// `state::sponsorship`.
+    ///
+    /// 0: Sponsor     [Signer]
+    /// 1: Sponsorship [PDA]
+    /// 2: System      [Program]
+    Sponsor(Pubkey, u64),
+
+    /// Allow an emitter to route messages to a target program, see `route`. Admin only.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    /// 2: Route  [PDA]
+    /// 3: System [Program]
+    RegisterRoute(Pubkey, u16, [u8; 32]),
+
+    /// Remove a route registered with RegisterRoute, refunding its rent to the admin.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    /// 2: Route  [PDA]
+    RemoveRoute(Pubkey, u16, [u8; 32]),
+
+    /// Set how many messages each sender may post per window, see `limits`. Admin only.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    SetRateLimit(RateLimit),
+
+    /// Cap the messages sent to a destination chain per window. Admin only.
+    ///
+    /// 0: Admin       [Signer]
+    /// 1: Config      [PDA]
+    /// 2: Destination [PDA]
+    /// 3: System      [Program]
+    SetDestinationLimit(u16, RateLimit),
+
+    /// Exempt a sender, a user key or calling program id, from rate limits. Admin only.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    /// 2: Sender [PDA]
+    /// 3: System [Program]
+    SetExempt(Pubkey, bool),
+
+    /// Set the receive policy for an emitter: its chain, address, minimum consistency level and
+    /// optionally the level its chain is final at, see `policy`. Admin only.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    /// 2: Policy [PDA]
+    /// 3: System [Program]
+    SetEmitterPolicy(u16, [u8; 32], u8, Option<u8>),
+
+    /// Set the protocol fee in lamports charged per message, see `treasury`. Admin only.
+    ///
+    /// 0: Admin  [Signer]
+    /// 1: Config [PDA]
+    SetProtocolFee(u64),
+
+    /// Withdraw collected protocol fees from the treasury. Admin only.
+    ///
+    /// 0: Admin     [Signer]
+    /// 1: Config    [PDA]
+    /// 2: Treasury  [PDA]
+    /// 3: Recipient
+    /// 4: System    [Program]
+    WithdrawTreasury(u64),
+
+    /// Create a room with the given id, and its inbox, see `rooms`. The owner becomes its first
+    /// member.
+    ///
+    /// 0: Owner  [Signer]
+    /// 1: Room   [PDA]
+    /// 2: Inbox  [PDA]
+    /// 3: System [Program]
+    CreateRoom(u64),
+
+    /// Add a member to a room, or change whether they can post. Owner only.
+    ///
+    /// 0: Owner [Signer]
+    /// 1: Room  [PDA]
+    SetRoomMember(u64, Pubkey, bool),
+
+    /// Remove a member from a room. Signed by the owner or the member leaving.
+    ///
+    /// 0: Owner or Member [Signer]
+    /// 1: Room            [PDA]
+    RemoveRoomMember(u64, Pubkey),
+
+    /// Block or mute a sender, identified by chain and padded address, in the signer's block
+    /// list, or take them off it with `None`, see `blocks`.
+    ///
+    /// 0: Owner  [Signer]
+    /// 1: Block  [PDA]
+    /// 2: System [Program]
+    SetBlock(u16, [u8; 32], Option<BlockMode>),
+
     /// This instruction is used to send a message to another chain by emitting it as a wormhole
-    /// message targetting another users key.
-    ///
-    /// 0: Payer         [Signer]
-    /// 1: Message       [Signer]
-    /// 2: Worm Config   [PDA]
-    /// 3: Worm Fee      [PDA]
-    /// 4: Worm Sequence [PDA]
-    /// 5: Emitter       [PDA, Signer]
-    /// 6: Clock         [Program]         -- Needed for wormhole to take block times.
-    /// 7: Rent          [Program]         -- Needed for wormhole fee calculation on the message account.
-    /// 8: System        [Program]         -- Needed for wormhole to take fees.
-    /// 9: Wormhole      [Program]         -- Needed for wormhole invoke_signed.
-    SendMessage(Message, u32),
+    /// message targetting another users key. The consistency level is optional, when not given
+    /// the config default is used. It is followed by the destination chain, 0 for any chain, and
+    /// optionally a channel to post on, see `state::channel_emitter`.
+    ///
+    /// If the payer has been sponsored, the Sponsorship, Sponsor Balance and Fee Vault accounts
+    /// can be appended to the accounts below and the bridge fee will be paid from the vault.
+    ///
+    /// The message account is a PDA derived from the payer and the count stored in their Sender
+    /// account, see `state::message`.
+    ///
+    /// 0:  Payer         [Signer]
+    /// 1:  Message       [PDA]
+    /// 2:  Config        [PDA]
+    /// 3:  Sender        [PDA]             -- Created on the payer's first message.
+    /// 4:  Worm Fee      [PDA]
+    /// 5:  Worm Config   [PDA]
+    /// 6:  Emitter       [PDA, Signer]     -- The channel's emitter, if a channel is given.
+    /// 7:  Worm Sequence [PDA]
+    /// 8:  Clock         [Program]         -- Needed for wormhole to take block times.
+    /// 9:  Rent          [Program]         -- Needed for wormhole fee calculation on the message account.
+    /// 10: System        [Program]         -- Needed for wormhole to take fees.
+    /// 11: Wormhole      [Program]         -- Needed for wormhole invoke_signed.
+    /// 12: Destination   [PDA]             -- Cap for the destination chain, see `limits`.
+    /// 13: Treasury      [PDA]             -- Collects the protocol fee, see `treasury`.
+    SendMessage(Message, u32, Option<Consistency>, u16, Option<String>),
 
     /// This is the same as the above message, but the example handler is more low level.
-    SendMessageRaw(Message, u32),
+    SendMessageRaw(Message, u32, Option<Consistency>, u16, Option<String>),
+
+    /// Send a message on behalf of another program, which must invoke us and sign with its sender
+    /// PDA, see `state::program_sender`. The posted payload records the calling program as the
+    /// sender. Programs should use the helpers in `cpi` rather than building this directly.
+    ///
+    /// 0:  Payer          [Signer]
+    /// 1:  Message        [PDA]
+    /// 2:  Config         [PDA]
+    /// 3:  Sender         [PDA]             -- Derived from the Caller.
+    /// 4:  Program Signer [PDA, Signer]     -- The calling program's sender PDA.
+    /// 5:  Caller         [Program]
+    /// 6:  Worm Fee       [PDA]
+    /// 7:  Worm Config    [PDA]
+    /// 8:  Emitter        [PDA, Signer]
+    /// 9:  Worm Sequence  [PDA]
+    /// 10: Clock          [Program]
+    /// 11: Rent           [Program]
+    /// 12: System         [Program]
+    /// 13: Wormhole       [Program]
+    /// 14: Destination    [PDA]
+    /// 15: Treasury       [PDA]
+    SendProgramMessage(Message, u32, Option<Consistency>, u16),
+
+    /// Send up to `MAX_BATCH` messages in one instruction. Each is posted as its own Wormhole
+    /// message, paying its own bridge fee, but all share the same nonce so they can be picked up
+    /// together. Messages use the config's default consistency level.
+    ///
+    /// 0:  Payer         [Signer]
+    /// 1:  Config        [PDA]
+    /// 2:  Sender        [PDA]
+    /// 3:  Worm Fee      [PDA]
+    /// 4:  Worm Config   [PDA]
+    /// 5:  Emitter       [PDA, Signer]
+    /// 6:  Worm Sequence [PDA]
+    /// 7:  Clock         [Program]
+    /// 8:  Rent          [Program]
+    /// 9:  System        [Program]
+    /// 10: Wormhole      [Program]
+    /// 11: Message 0     [PDA]             -- One message account per payload, in order.
+    /// ..: Destination   [PDA]             -- After the last message account.
+    /// ..: Treasury      [PDA]
+    SendBatch(Vec<Message>, u32, u16),
+
+    /// Send a message to a room, given by its id. This takes the same accounts as SendMessage
+    /// with the Room account after them, and is refused unless the payer is a member of the room
+    /// allowed to post.
+    ///
+    /// ..: The SendMessage accounts.
+    /// 14: Room          [PDA]
+    SendRoomMessage(u64, Message, u32, Option<Consistency>, u16),
+
+    /// Register a nickname to the payer in the directory, see `directory`, and post the
+    /// registration with the given nonce so other chains add it too. The registration is posted
+    /// like a message, taking the same accounts as SendMessage with the directory entry after
+    /// them.
+    ///
+    /// ..: The SendMessage accounts.
+    /// 14: Nick          [PDA]
+    RegisterNick(String, u32),
+
+    /// Schedule a message to be posted once due, by slot or unix time, by anyone turning the
+    /// Crank, see `schedule`. Takes the sender's id for the scheduled message, then the message,
+    /// nonce, due time, destination chain and the lamports to reward the cranker with. The bridge
+    /// and protocol fees are escrowed along with the reward.
+    ///
+    /// 0: Sender        [Signer]
+    /// 1: Config        [PDA]
+    /// 2: Scheduled     [PDA]
+    /// 3: Worm Config   [PDA]         -- For the bridge fee.
+    /// 4: System        [Program]
+    ScheduleMessage(u64, Message, u32, Due, u16, u64),
+
+    /// Post a due scheduled message, given by its sender and id, and pay the cranker. This takes
+    /// the SendMessage accounts, with the cranker as the payer but the message and sender
+    /// accounts of the scheduled message's sender, and the Scheduled account and the sender
+    /// after them.
+    ///
+    /// ..: The SendMessage accounts.
+    /// 14: Scheduled     [PDA]
+    /// 15: Origin        [Account]     -- The scheduled message's sender, refunded its rent.
+    Crank(Pubkey, u64),
+
+    /// Cancel a scheduled message before it is posted, refunding the escrow to the sender.
+    ///
+    /// 0: Sender    [Signer]
+    /// 1: Scheduled [PDA]
+    CancelScheduled(u64),
+
+    /// Create an M-of-N multisig, given its id, signers and threshold, see `multisig`.
+    ///
+    /// 0: Payer    [Signer]
+    /// 1: Multisig [PDA]
+    /// 2: System   [Program]
+    CreateMultisig(u64, Vec<Pubkey>, u8),
+
+    /// Propose a message for a multisig to send, given the message, nonce, destination chain and
+    /// the unix time the proposal expires at. It takes the multisig's next proposal id.
+    ///
+    /// 0: Proposer [Signer]          -- One of the multisig's signers.
+    /// 1: Multisig [PDA]
+    /// 2: Proposal [PDA]
+    /// 3: System   [Program]
+    Propose(Message, u32, u16, i64),
+
+    /// Approve a proposal, given by its multisig and proposal ids. As the approval meeting the
+    /// threshold posts the message, this takes the SendMessage accounts with the signer as the
+    /// payer but the message and sender accounts of the multisig.
+    ///
+    /// ..: The SendMessage accounts.
+    /// 14: Multisig      [PDA]
+    /// 15: Proposal      [PDA]
+    /// 16: Proposer                    -- Refunded the proposal's rent once posted.
+    Approve(u64, u64),
+
+    /// Cancel a proposal, given by its id, returning its rent to the proposer.
+    ///
+    /// 0: Caller   [Signer]          -- Any of the signers, or anyone once expired.
+    /// 1: Proposer
+    /// 2: Multisig [PDA]
+    /// 3: Proposal [PDA]
+    CancelProposal(u64),
 
     /// This instruction receives a message by processing an incoming VAA containing a message
     /// intended for a receiver on Solana. Note that the simple existence of the VAA account is
     /// enough to verify it as the account is only created by the bridge if the guardians had
-    /// successfull signed it.
+    /// successfull signed it. The VAA must also pass the receive policy, see `policy`.
+    ///
+    /// If the message names a target program it is also forwarded there by CPI, see `route`. In
+    /// that case the following accounts are required, followed by any accounts the target needs.
+    /// Room messages are delivered into the room's inbox, which must be passed after every other
+    /// account along with the room, the route to ourselves registering the emitter, the room
+    /// owner's block list entry for the sender, the delivery receipt and the system program, see
+    /// `rooms`. Direct messages likewise need their
+    /// recipient's block list entry for the sender, see `blocks`. The directory entry for the
+    /// message's nick may be passed there too, with the same route, to have the nick shown as
+    /// verified, see `directory`.
     ///
-    /// 0: VAA [PDA]
+    /// 0:  Payer              [Signer]
+    /// 1:  VAA                [PDA]
+    /// 2:  Config             [PDA]
+    /// 3:  Signature Set      [Account]     -- The bridge's signature set the VAA was posted with.
+    /// 4:  Guardian Set       [PDA]         -- The bridge's guardian set that signed the VAA.
+    /// 5:  Emitter Policy     [PDA]         -- Need not exist for emitters without a policy.
+    /// 6:  Route              [PDA]         -- Only for targeted messages.
+    /// 7:  Receipt            [PDA]
+    /// 8:  Callback Authority [PDA]
+    /// 9:  System             [Program]
+    /// 10: Target             [Program]
     RecvMessage,
+
+    /// Receive several VAAs in one instruction, for relayers catching up on a backlog. Each VAA
+    /// is passed with the accounts its receive policy needs and handled as RecvMessage would,
+    /// except that routed, room and direct messages must still be received individually. A VAA that
+    /// fails is reported with a `Skipped` event rather than failing the batch.
+    ///
+    /// 0: Payer          [Signer]
+    /// 1: Config         [PDA]
+    /// 2: VAA 0          [PDA]
+    /// 3: Signature Set  [Account]
+    /// 4: Guardian Set   [PDA]
+    /// 5: Emitter Policy [PDA]
+    /// ..: The same four accounts for every further VAA.
+    RecvBatch,
+
+    /// Receive a nickname registration posted by another Messenger deployment and add it to the
+    /// directory, unless the nick was registered first elsewhere, see `directory`. The emitter
+    /// must be registered as a route to this program.
+    ///
+    /// 0: Payer          [Signer]
+    /// 1: VAA            [PDA]
+    /// 2: Config         [PDA]
+    /// 3: Signature Set  [Account]
+    /// 4: Guardian Set   [PDA]
+    /// 5: Emitter Policy [PDA]
+    /// 6: Route          [PDA]         -- The emitter's route to this program.
+    /// 7: Nick           [PDA]
+    /// 8: System         [Program]
+    RecvRegistration,
+
+    /// Rewrite an account written by an older version of the program in its current layout, see
+    /// `migrate`. Anyone may migrate any account.
+    ///
+    /// 0: Account [PDA]
+    Migrate,
 }
 
 
+/// The most messages a single SendBatch can post. Each post is a CPI into the bridge which creates
+/// an account, `benches/costs.rs` checks a full batch stays inside the default compute budget.
+pub const MAX_BATCH: usize = 5;
+
+/// Number of fixed accounts at the start of a SendBatch, the message accounts follow them.
+const BATCH_ACCOUNTS: usize = 11;
+
+
+#[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
 /// The Solana entrypoint, here we deserialize our Borsh encoded Instruction and dispatch to our
 /// program handlers.
 pub fn process_instruction(id: &Pubkey, accs: &[AccountInfo], data: &[u8]) -> ProgramResult {
-    match BorshDeserialize::try_from_slice(data).unwrap() {
+    let instruction = BorshDeserialize::try_from_slice(data).unwrap();
+    check_paused(id, accs, &instruction)?;
+
+    match instruction {
+        // Configuration.
+        Instruction::Initialize(wormhole, default, allowed) => initialize(id, accs, wormhole, default, allowed),
+        Instruction::SetConfig(wormhole, default, allowed)  => set_config(id, accs, wormhole, default, allowed),
+        Instruction::SetPaused(send, receive)               => set_paused(id, accs, send, receive),
+
+        // Governance.
+        Instruction::SetGovernance(chain, emitter) => governance::set_governance(id, accs, chain, emitter),
+        Instruction::UpgradeContract               => governance::upgrade(id, accs),
+
+        // Fee sponsorship.
+        Instruction::Deposit(amount)        => vault::deposit(id, accs, amount),
+        Instruction::Withdraw(amount)       => vault::withdraw(id, accs, amount),
+        Instruction::Sponsor(user, quota)   => vault::sponsor(id, accs, user, quota),
+
+        // Routing received messages to other programs.
+        Instruction::RegisterRoute(program, chain, emitter) => route::register_route(id, accs, program, chain, emitter),
+        Instruction::RemoveRoute(program, chain, emitter)   => route::remove_route(id, accs, program, chain, emitter),
+
+        // Outbound rate limits.
+        Instruction::SetRateLimit(limit)               => limits::set_rate_limit(id, accs, limit),
+        Instruction::SetDestinationLimit(chain, limit) => limits::set_destination_limit(id, accs, chain, limit),
+        Instruction::SetExempt(sender, exempt)         => limits::set_exempt(id, accs, sender, exempt),
+
+        // Receive policy.
+        Instruction::SetEmitterPolicy(chain, emitter, min, finality) => policy::set_emitter_policy(id, accs, chain, emitter, min, finality),
+
+        // Protocol fees.
+        Instruction::SetProtocolFee(fee)        => treasury::set_protocol_fee(id, accs, fee),
+        Instruction::WithdrawTreasury(amount)   => treasury::withdraw(id, accs, amount),
+
+        // Rooms.
+        Instruction::CreateRoom(room)                      => rooms::create_room(id, accs, room),
+        Instruction::SetRoomMember(room, member, can_post) => rooms::set_member(id, accs, room, member, can_post),
+        Instruction::RemoveRoomMember(room, member)        => rooms::remove_member(id, accs, room, member),
+
+        // Block lists.
+        Instruction::SetBlock(chain, sender, mode) => blocks::set_block(id, accs, chain, sender, mode),
+
         // Send Message Variants. Check the source of each to see various ways to invoke Wormhole.
-        Instruction::SendMessage(msg, nonce)    => send_message(id, accs, msg, nonce),
-        Instruction::SendMessageRaw(msg, nonce) => send_message_raw(id, accs, msg, nonce),
+        Instruction::SendMessage(msg, nonce, level, chain, channel)    => send_message(id, accs, msg, nonce, level, chain, channel),
+        Instruction::SendMessageRaw(msg, nonce, level, chain, channel) => send_message_raw(id, accs, msg, nonce, level, chain, channel),
+        Instruction::SendBatch(msgs, nonce, chain)                     => send_batch(id, accs, msgs, nonce, chain),
+        Instruction::SendRoomMessage(room, msg, nonce, level, chain)   => send_room_message(id, accs, room, msg, nonce, level, chain),
+
+        // Nickname directory.
+        Instruction::RegisterNick(nick, nonce) => register_nick(id, accs, nick, nonce),
+        Instruction::RecvRegistration          => recv_registration(id, accs),
+
+        // Scheduled messages.
+        Instruction::ScheduleMessage(schedule, msg, nonce, due, chain, reward) => schedule::schedule(id, accs, schedule, msg, nonce, due, chain, reward),
+        Instruction::Crank(sender, schedule)                                   => schedule::crank(id, accs, sender, schedule),
+        Instruction::CancelScheduled(schedule)                                 => schedule::cancel(id, accs, schedule),
+
+        // Multisig messages.
+        Instruction::CreateMultisig(multisig, signers, threshold) => multisig::create_multisig(id, accs, multisig, signers, threshold),
+        Instruction::Propose(msg, nonce, chain, expiry)           => multisig::propose(id, accs, msg, nonce, chain, expiry),
+        Instruction::Approve(multisig, proposal)                  => multisig::approve(id, accs, multisig, proposal),
+        Instruction::CancelProposal(proposal)                     => multisig::cancel(id, accs, proposal),
+
+        // Messages sent by other programs through CPI.
+        Instruction::SendProgramMessage(msg, nonce, level, chain) => send_program_message(id, accs, msg, nonce, level, chain),
 
         // RecvMessage shows an example of safely processing a VAA.
         Instruction::RecvMessage         => recv_message(id, accs),
+        Instruction::RecvBatch           => recv_batch(id, accs),
+
+        // Account layout upgrades.
+        Instruction::Migrate => migrate::migrate(id, accs),
     }?;
     Ok(())
 }
 
+/// Refuse sends and receives while the admin has paused them. Every send and receive instruction
+/// takes the config, which is located by key.
+fn check_paused(id: &Pubkey, accounts: &[AccountInfo], instruction: &Instruction) -> ProgramResult {
+    let (send, receive) = match instruction {
+        Instruction::SendMessage(..)
+        | Instruction::SendMessageRaw(..)
+        | Instruction::SendProgramMessage(..)
+        | Instruction::SendBatch(..)
+        | Instruction::SendRoomMessage(..)
+        | Instruction::RegisterNick(..)
+        | Instruction::ScheduleMessage(..)
+        | Instruction::Crank(..)
+        | Instruction::Approve(..) => (true, false),
+        Instruction::RecvMessage | Instruction::RecvBatch | Instruction::RecvRegistration => (false, true),
+        _ => return Ok(()),
+    };
+
+    let config: Config = state::load(id, bridge::find(accounts, &state::config(id).0)?)?;
+    if send && config.send_paused {
+        return Err(MessengerError::SendingPaused.into());
+    }
+    if receive && config.receive_paused {
+        return Err(MessengerError::ReceivingPaused.into());
+    }
+    Ok(())
+}
+
+/// Create the config account, recording the payer as the admin. Only the program's upgrade
+/// authority can approve this.
+fn initialize(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    wormhole: Pubkey,
+    default_consistency: Consistency,
+    allowed_consistency: Vec<Consistency>,
+) -> ProgramResult {
+    let accounts     = &mut accs.iter();
+    let payer        = next_account_info(accounts)?;
+    let config       = next_account_info(accounts)?;
+    let system       = next_account_info(accounts)?;
+    let authority    = next_account_info(accounts)?;
+    let program_data = next_account_info(accounts)?;
+
+    governance::check_upgrade_authority(id, authority, program_data)?;
+
+    let (key, bump) = state::config(id);
+    state::check_address(config, &key)?;
+    if **config.lamports.borrow() != 0 {
+        return Err(MessengerError::AlreadyInitialized.into());
+    }
+
+    let data = Config {
+        admin: *payer.key,
+        wormhole,
+        default_consistency,
+        allowed_consistency,
+        sender_limit:   RateLimit::UNLIMITED,
+        protocol_fee:   0,
+        send_paused:    false,
+        receive_paused: false,
+
+        governance_chain:   0,
+        governance_emitter: [0; 32],
+    };
+    data.check()?;
+
+    state::create(id, payer, config, system, &[b"config", &[bump]], Config::SIZE)?;
+    state::save(config, &data)
+}
+
+/// Replace the bridge id and consistency policy. Only the admin may do this.
+fn set_config(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    wormhole: Pubkey,
+    default_consistency: Consistency,
+    allowed_consistency: Vec<Consistency>,
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let mut data: Config = state::load(id, config)?;
+    data.check_admin(admin)?;
+
+    data.wormhole            = wormhole;
+    data.default_consistency = default_consistency;
+    data.allowed_consistency = allowed_consistency;
+    data.check()?;
+
+    state::save(config, &data)
+}
+
+/// Pause or resume sending and receiving, see `check_paused`. Only the admin may do this.
+fn set_paused(id: &Pubkey, accs: &[AccountInfo], send: bool, receive: bool) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let mut data: Config = state::load(id, config)?;
+    data.check_admin(admin)?;
+
+    data.send_paused    = send;
+    data.receive_paused = receive;
+    state::save(config, &data)?;
+
+    Event::PauseChanged {
+        send_paused:    send,
+        receive_paused: receive,
+    }
+    .emit()
+}
+
 /// Send a Message from this chain to a user on a remote target chain.
 ///
 /// This method is a reference example of emitting messages via Wormhole using the ergonomic API
 /// methods. This is the easiest way to use Wormhole.
-fn send_message(id: &Pubkey, accounts: &[AccountInfo], payload: Message, nonce: u32) -> ProgramResult {
+fn send_message(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    payload: Message,
+    nonce: u32,
+    level: Option<Consistency>,
+    target_chain: u16,
+    channel: Option<String>,
+) -> ProgramResult {
+    let iter    = &mut accounts.iter();
+    let payer   = next_account_info(iter)?;
+    let message = next_account_info(iter)?;
+    let config  = next_account_info(iter)?;
+    let sender  = next_account_info(iter)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    let level = config.consistency(level)?;
+
+    // The payer is the sender, so the payload records their key.
+    let envelope = Envelope {
+        sender: payer.key.to_bytes(),
+        target_chain,
+        target: None,
+        recipient: None,
+        room: None,
+        message: payload,
+    };
+
+    let channel = channel.as_deref();
+    post(id, accounts, &config, payer, payer.key, sender, message, &envelope, nonce, level, channel, true)
+}
+
+/// Send a Message to a room. This is `send_message` with the payer's posting rights checked and
+/// the room recorded in the envelope.
+fn send_room_message(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    room: u64,
+    payload: Message,
+    nonce: u32,
+    level: Option<Consistency>,
+    target_chain: u16,
+) -> ProgramResult {
+    let iter    = &mut accounts.iter();
+    let payer   = next_account_info(iter)?;
+    let message = next_account_info(iter)?;
+    let config  = next_account_info(iter)?;
+    let sender  = next_account_info(iter)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    let level = config.consistency(level)?;
+    rooms::check_poster(id, accounts, room, payer.key)?;
+
+    let envelope = Envelope {
+        sender: payer.key.to_bytes(),
+        target_chain,
+        target: None,
+        recipient: None,
+        room: Some(room),
+        message: payload,
+    };
+
+    post(id, accounts, &config, payer, payer.key, sender, message, &envelope, nonce, level, None, true)
+}
+
+/// Send a Message on behalf of another program. The calling program signs with its sender PDA,
+/// `state::program_sender`, which proves the call came from that program, and the payload records
+/// the calling program's id as the sender.
+fn send_program_message(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    payload: Message,
+    nonce: u32,
+    level: Option<Consistency>,
+    target_chain: u16,
+) -> ProgramResult {
     let iter    = &mut accounts.iter();
     let payer   = next_account_info(iter)?;
     let message = next_account_info(iter)?;
+    let config  = next_account_info(iter)?;
+    let sender  = next_account_info(iter)?;
+    let signer  = next_account_info(iter)?;
+    let caller  = next_account_info(iter)?;
 
-    // This helper method will take care of all of the following for you:
+    // Only the calling program can sign for its own sender PDA.
+    state::check_address(signer, &state::program_sender(caller.key).0)?;
+    if !signer.is_signer {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    let level = config.consistency(level)?;
+
+    let envelope = Envelope {
+        sender: caller.key.to_bytes(),
+        target_chain,
+        target: None,
+        recipient: None,
+        room: None,
+        message: payload,
+    };
+
+    post(id, accounts, &config, payer, caller.key, sender, message, &envelope, nonce, level, None, true)
+}
+
+/// Register a nickname to the payer and post the registration for other chains.
+fn register_nick(id: &Pubkey, accounts: &[AccountInfo], nick: String, nonce: u32) -> ProgramResult {
+    let iter    = &mut accounts.iter();
+    let payer   = next_account_info(iter)?;
+    let message = next_account_info(iter)?;
+    let config  = next_account_info(iter)?;
+    let sender  = next_account_info(iter)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    if !messenger_common::valid_nick(&nick) {
+        return Err(MessengerError::InvalidNick.into());
+    }
+
+    // Anything already in the directory was registered before now.
+    let claim = Claim {
+        chain: messenger_common::CHAIN_ID_SOLANA,
+        owner: payer.key.to_bytes(),
+        time:  solana_program::clock::Clock::get()?.unix_timestamp as u64,
+    };
+    let entry = bridge::find(accounts, &state::nick(id, &nick).0)?;
+    let system = bridge::find(accounts, &solana_program::system_program::id())?;
+    if !directory::claim(id, payer, entry, system, &nick, claim)? {
+        return Err(MessengerError::NickTaken.into());
+    }
+
+    // Registrations are only acted on once final, so are always posted finalized.
+    let registration = Registration {
+        owner: claim.owner,
+        nick,
+    };
+    let payload = registration.payload();
+    let level = Consistency::Finalized;
+    post_payload(id, accounts, &config, payer, payer.key, sender, message, 0, payload, nonce, level, None, true)
+}
+
+/// Post an Envelope as the next message from `origin`, see `post_payload`.
+#[allow(clippy::too_many_arguments)]
+fn post<'a>(
+    id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    config: &Config,
+    payer: &AccountInfo<'a>,
+    origin: &Pubkey,
+    sender: &AccountInfo<'a>,
+    message: &AccountInfo<'a>,
+    envelope: &Envelope,
+    nonce: u32,
+    level: Consistency,
+    channel: Option<&str>,
+    sponsored: bool,
+) -> ProgramResult {
+    let payload = envelope.try_to_vec()?;
+    let target_chain = envelope.target_chain;
+    post_payload(id, accounts, config, payer, origin, sender, message, target_chain, payload, nonce, level, channel, sponsored)
+}
+
+/// Post a payload as the next message from `origin`, whose Sender account is `sender`. The payer
+/// funds the message account and the bridge fee, unless `sponsored` and holding a grant. Messages
+/// on a `channel` are posted from that channel's emitter rather than the default one.
+#[allow(clippy::too_many_arguments)]
+fn post_payload<'a>(
+    id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    config: &Config,
+    payer: &AccountInfo<'a>,
+    origin: &Pubkey,
+    sender: &AccountInfo<'a>,
+    message: &AccountInfo<'a>,
+    target_chain: u16,
+    payload: Vec<u8>,
+    nonce: u32,
+    level: Consistency,
+    channel: Option<&str>,
+    sponsored: bool,
+) -> ProgramResult {
+    // Message accounts are PDA's, so we sign for them ourselves rather than the client having to
+    // generate and sign with a fresh keypair.
+    let (count, bump) = claim_message(id, config, payer, origin, sender, message, target_chain, accounts)?;
+    let count_bytes = count.to_be_bytes();
+    let message_seeds: &[&[u8]] = &[b"message", origin.as_ref(), &count_bytes, &[bump]];
+
+    // These helpers take care of the following for you, using the bridge named in our config:
     //
-    // - Derives a reasonable emitter PDA for your program.
-    // - Pays the Bridge (Payer Key)
+    // - Pays the Bridge (Fee Vault if the payer is sponsored, otherwise Payer Key)
+    // - Pays our protocol fee into the treasury (always the Payer Key)
+    // - Derives a reasonable emitter PDA for your program, or one for the channel.
     // - Emits a Message
-    wormhole_sdk::post_message(
-        *id,
-        *payer.key,
-        *message.key,
-        payload.try_to_vec()?,
-        ConsistencyLevel::Finalized,
-        None,
+    let channel_seeds: [&[u8]; 2];
+    let emitter_seeds = match channel {
+        Some(channel) => {
+            state::check_channel(channel)?;
+            channel_seeds = [b"channel", channel.as_bytes()];
+            Some(&channel_seeds[..])
+        }
+        None => None,
+    };
+
+    pay_fees(id, config, payer.key, sponsored, accounts)?;
+    bridge::post_message(
+        config,
+        id,
+        payer.key,
+        message.key,
+        message_seeds,
+        payload,
+        level,
+        emitter_seeds,
         accounts,
         nonce,
     )?;
 
-    Ok(())
+    Event::MessageSent {
+        sender: origin.to_bytes(),
+        message: *message.key,
+        count,
+        nonce,
+        consistency: level,
+    }
+    .emit()
+}
+
+/// Pay the bridge fee, from the Fee Vault if `sponsored` and the payer holds a grant, then our
+/// protocol fee, which is never sponsored. Every sending path pays through here so fees are always
+/// moved in the same order.
+fn pay_fees(id: &Pubkey, config: &Config, payer: &Pubkey, sponsored: bool, accounts: &[AccountInfo]) -> ProgramResult {
+    if !(sponsored && vault::pay_fee(id, config, payer, accounts)?) {
+        bridge::pay_fee(config, payer, accounts)?;
+    }
+    treasury::collect(id, config, payer, accounts)
 }
 
 /// Send a Message from this chain to a user on a remote target chain.
 ///
 /// This method is a reference example of emitting messages via Wormhole using the most low level
-/// interface provided by the SDK. You must handle the emitter, payment, and invoking yourself.
-fn send_message_raw(id: &Pubkey, accs: &[AccountInfo], payload: Message, nonce: u32) -> ProgramResult {
+/// interface provided by the SDK. You must handle the emitter and invoking yourself, fees are paid
+/// through the same `pay_fees` as every other send.
+fn send_message_raw(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    payload: Message,
+    nonce: u32,
+    level: Option<Consistency>,
+    target_chain: u16,
+    channel: Option<String>,
+) -> ProgramResult {
     let accounts      = &mut accs.iter();
     let payer         = next_account_info(accounts)?;
     let message       = next_account_info(accounts)?;
-    let fee_collector = next_account_info(accounts)?;
     let config        = next_account_info(accounts)?;
+    let sender        = next_account_info(accounts)?;
+    let fee_collector = next_account_info(accounts)?;
+    let bridge        = next_account_info(accounts)?;
 
-    // Deserialize Bridge Config, used to figure out what the fee is so we can pay the bridge
-    // programatically.
-    let config = wormhole_sdk::read_config(config).unwrap();
+    // Our own config tells us which bridge to talk to and which consistency levels are allowed.
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    let level = config.consistency(level)?;
+    state::check_address(fee_collector, &wormhole_sdk::fee_collector(&config.wormhole))?;
+    state::check_address(bridge, &wormhole_sdk::config(&config.wormhole))?;
 
-    // Pay Fee to the Wormhole.
-    invoke_signed(
-        &solana_program::system_instruction::transfer(payer.key, fee_collector.key, config.fee),
-        accs,
-        &[],
-    )?;
+    // Claim the next message PDA for this sender, we'll need its seeds to sign for it below.
+    let (count, message_bump) = claim_message(id, &config, payer, payer.key, sender, message, target_chain, accs)?;
+    let count_bytes = count.to_be_bytes();
+    let message_bump = &[message_bump];
+    let message_seeds: &[&[u8]] = &[b"message", payer.key.as_ref(), &count_bytes, message_bump];
+
+    // Sponsored payers have the bridge fee paid from the vault, everyone else pays it themselves.
+    // The protocol fee is never sponsored. Fees move exactly as they do for every other send.
+    pay_fees(id, &config, payer.key, true, accs)?;
 
     // Create an Emitter to emit messages from, this helper method is producing the emitter from
-    // the _current_ program's ID.
-    let (emitter, mut seeds, bump) = wormhole_sdk::emitter(id);
+    // the _current_ program's ID. Channels derive their own emitter from the channel name instead.
+    let (emitter, mut seeds, bump) = match channel.as_deref() {
+        Some(channel) => {
+            state::check_channel(channel)?;
+            let seeds: Vec<&[u8]> = vec![b"channel", channel.as_bytes()];
+            let (emitter, bump) = Pubkey::find_program_address(&seeds, id);
+            (emitter, seeds, bump)
+        }
+        None => wormhole_sdk::emitter(id),
+    };
     let bump = &[bump];
     seeds.push(bump);
 
-    // Invoke the Wormhole post_message endpoint to create an on-chain message.
+    // Wrap the message so receivers know who sent it.
+    let envelope = Envelope {
+        sender: payer.key.to_bytes(),
+        target_chain,
+        target: None,
+        recipient: None,
+        room: None,
+        message: payload,
+    };
+    let sender = envelope.sender;
+
+    // Invoke the Wormhole post_message endpoint to create an on-chain message. Both the emitter
+    // and the message account are our PDA's so we sign for both.
     invoke_signed(
         &post_message(
-            wormhole_sdk::id(),
+            config.wormhole,
             *payer.key,
             emitter,
             *message.key,
             nonce,
-            payload.try_to_vec()?,
-            ConsistencyLevel::Finalized,
+            envelope.try_to_vec()?,
+            level.into(),
         )
         .unwrap(),
         accs,
-        &[&seeds],
+        &[&seeds, message_seeds],
     )?;
 
+    // Log a structured event for indexers, see `events`.
+    Event::MessageSent {
+        sender,
+        message: *message.key,
+        count,
+        nonce,
+        consistency: level,
+    }
+    .emit()?;
+
+    Ok(())
+}
+
+/// Send a batch of Messages under a shared nonce. This works the same as `send_message` once per
+/// payload, with each message taking the next message PDA for the payer.
+fn send_batch(
+    id: &Pubkey,
+    accounts: &[AccountInfo],
+    payloads: Vec<Message>,
+    nonce: u32,
+    target_chain: u16,
+) -> ProgramResult {
+    if payloads.is_empty() || payloads.len() > MAX_BATCH {
+        return Err(MessengerError::InvalidBatchSize.into());
+    }
+
+    let iter   = &mut accounts.iter();
+    let payer  = next_account_info(iter)?;
+    let config = next_account_info(iter)?;
+    let sender = next_account_info(iter)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+
+    // Message accounts follow the fixed accounts, one per payload.
+    let messages = accounts
+        .get(BATCH_ACCOUNTS..BATCH_ACCOUNTS + payloads.len())
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    for (payload, message) in payloads.into_iter().zip(messages) {
+        let envelope = Envelope {
+            sender: payer.key.to_bytes(),
+            target_chain,
+            target: None,
+            recipient: None,
+            room: None,
+            message: payload,
+        };
+        let level = config.default_consistency;
+        post(id, accounts, &config, payer, payer.key, sender, message, &envelope, nonce, level, None, true)?;
+    }
+
     Ok(())
 }
 
+/// Check `message` is the next message PDA for `origin` and advance their message count, creating
+/// their Sender account (funded by `payer`) if this is their first message. The message is counted
+/// against the sender's and `target_chain`'s rate limits. Returns the count and bump used to
+/// derive the message account so the caller can sign for it.
+#[allow(clippy::too_many_arguments)]
+fn claim_message<'a>(
+    id: &Pubkey,
+    config: &Config,
+    payer: &AccountInfo<'a>,
+    origin: &Pubkey,
+    sender: &AccountInfo<'a>,
+    message: &AccountInfo<'a>,
+    target_chain: u16,
+    accounts: &[AccountInfo<'a>],
+) -> Result<(u64, u8), ProgramError> {
+    let (key, bump) = state::sender(id, origin);
+    state::check_address(sender, &key)?;
+
+    let mut data = if **sender.lamports.borrow() == 0 {
+        let system = bridge::find(accounts, &solana_program::system_program::id())?;
+        let seeds: &[&[u8]] = &[b"sender", origin.as_ref(), &[bump]];
+        state::create(id, payer, sender, system, seeds, Sender::SIZE)?;
+        Sender::default()
+    } else {
+        state::load(id, sender)?
+    };
+    limits::consume(id, config, &mut data, target_chain, accounts)?;
+
+    let count = data.count;
+    let (key, bump) = state::message(id, origin, count);
+    state::check_address(message, &key)?;
+
+    data.count += 1;
+    state::save(sender, &data)?;
+    Ok((count, bump))
+}
+
 
 /// Receives a VAA containing a message from a foreign chain, and parses/verifies the VAA to
 /// validate the message has been safely attested by the guardian set. Prints the message in
 /// validator logs.
 fn recv_message(id: &Pubkey, accs: &[AccountInfo]) -> ProgramResult {
-    // We must verify the VAA is legitimately signed by the guardians. We do this by deriving the
-    // expected PDA derived by the bridge, as long as we produce the same account we can trust the
-    // contents of the VAA.
-    let accounts = &mut accs.iter();
-    let payer    = next_account_info(accounts)?;
-    let vaa      = next_account_info(accounts)?;
+    // We must verify the VAA is legitimately signed by the guardians. We do this by checking the
+    // account is owned by the bridge, only the bridge can create accounts it owns and it will only
+    // do so for VAA's the guardians have signed.
+    let accounts      = &mut accs.iter();
+    let payer         = next_account_info(accounts)?;
+    let vaa           = next_account_info(accounts)?;
+    let config        = next_account_info(accounts)?;
+    let signature_set = next_account_info(accounts)?;
+    let guardian_set  = next_account_info(accounts)?;
+    let policy        = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
 
     // If we want to avoid processing a message twice we need to track whether we have already
     // processed a VAA manually. There are several ways to do this in Solana but in this example
-    // we will simply reprocess VAA's.
-    let vaa = wormhole_sdk::read_vaa(vaa).unwrap();
-    let msg = Message::try_from_slice(&vaa.payload)?;
-    msg!("{}: {}", msg.nick, msg.text);
+    // we will simply reprocess VAA's, only routed and room messages are tracked with receipts, see
+    // `route::forward` and `rooms::deliver`.
+    let (posted, envelope) = verify(id, &config, vaa, signature_set, guardian_set, policy)?;
 
+    // Messages from senders the recipient has blocked are dropped, but still received so the
+    // relayer's transaction succeeds. Direct messages are filtered by their recipient's own list,
+    // room messages by the room owner's as they are delivered.
+    let mut quarantined = false;
+    if let Some(recipient) = envelope.recipient {
+        let recipient = Pubkey::new_from_array(recipient);
+        match blocks::check(id, accs, &recipient, &posted, &envelope.sender)? {
+            Some(BlockMode::Block) => return Ok(()),
+            mode => quarantined = mode == Some(BlockMode::Mute),
+        }
+    }
+    if let Some(room) = envelope.room {
+        if !rooms::deliver(id, payer, accs, room, vaa, &posted, &envelope)? {
+            return Ok(());
+        }
+    }
+    show(id, accs, &posted, &envelope, quarantined);
+
+    if let Some(target) = envelope.target {
+        let callback = route::Callback {
+            emitter_chain:   posted.emitter_chain,
+            emitter_address: posted.emitter_address,
+            sequence:        posted.sequence,
+            sender:          envelope.sender,
+            message:         envelope.message.clone(),
+        };
+
+        // Failed transactions keep their logs, so indexers can still see why delivery failed.
+        let target = Pubkey::new_from_array(target);
+        if let Err(error) = route::forward(id, payer, vaa, accounts.as_slice(), &target, &callback) {
+            Event::Rejected {
+                emitter_chain:   posted.emitter_chain,
+                emitter_address: posted.emitter_address,
+                sequence:        posted.sequence,
+                error:           error.clone().into(),
+            }
+            .emit()?;
+            return Err(error);
+        }
+    }
+
+    Event::MessageReceived {
+        emitter_chain:   posted.emitter_chain,
+        emitter_address: posted.emitter_address,
+        sequence:        posted.sequence,
+        sender:          envelope.sender,
+        target:          envelope.target,
+    }
+    .emit()
+}
+
+/// Receive a batch of untargeted messages, skipping any VAA that fails rather than the batch.
+fn recv_batch(id: &Pubkey, accs: &[AccountInfo]) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let _payer   = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+
+    let vaas = accounts.as_slice();
+    if vaas.is_empty() || vaas.len() % 4 != 0 {
+        return Err(MessengerError::InvalidBatchSize.into());
+    }
+
+    for group in vaas.chunks(4) {
+        let (vaa, signature_set, guardian_set, policy) = (&group[0], &group[1], &group[2], &group[3]);
+        let received = verify(id, &config, vaa, signature_set, guardian_set, policy);
+        let received = received.and_then(|(posted, envelope)| {
+            // Routed messages need their target's accounts, which can't be passed per VAA here.
+            if envelope.target.is_some() {
+                return Err(MessengerError::RoutedInBatch.into());
+            }
+
+            // Likewise the room's inbox, or the recipient's block list entry.
+            if envelope.room.is_some() {
+                return Err(MessengerError::RoomInBatch.into());
+            }
+            if envelope.recipient.is_some() {
+                return Err(MessengerError::RecipientInBatch.into());
+            }
+
+            show(id, accs, &posted, &envelope, false);
+            Event::MessageReceived {
+                emitter_chain:   posted.emitter_chain,
+                emitter_address: posted.emitter_address,
+                sequence:        posted.sequence,
+                sender:          envelope.sender,
+                target:          None,
+            }
+            .emit()
+        });
+
+        if let Err(error) = received {
+            Event::Skipped {
+                vaa:   *vaa.key,
+                error: error.into(),
+            }
+            .emit()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a received message in the validator logs, marking messages from muted senders as
+/// quarantined and nicks the directory doesn't have registered to the sender as unverified. Lines
+/// start with a fixed prefix so a sender can't pass their nick and text off as an event, see
+/// `events::decode_logs`.
+fn show(id: &Pubkey, accounts: &[AccountInfo], posted: &PostedVAAData, envelope: &Envelope, quarantined: bool) {
+    let message = &envelope.message;
+    let mark = if quarantined {
+        " (quarantined)"
+    } else if directory::verified(id, accounts, posted.emitter_chain, &posted.emitter_address, envelope) {
+        ""
+    } else {
+        " (unverified)"
+    };
+    msg!("Message: {}{}: {}", message.nick, mark, message.text);
+}
+
+/// Receive a nickname registration from another Messenger deployment into the directory. A
+/// registration that loses to an earlier one is not an error, so relayers can deliver every
+/// registration they see.
+fn recv_registration(id: &Pubkey, accs: &[AccountInfo]) -> ProgramResult {
+    let accounts      = &mut accs.iter();
+    let payer         = next_account_info(accounts)?;
+    let vaa           = next_account_info(accounts)?;
+    let config        = next_account_info(accounts)?;
+    let signature_set = next_account_info(accounts)?;
+    let guardian_set  = next_account_info(accounts)?;
+    let policy        = next_account_info(accounts)?;
+    let route         = next_account_info(accounts)?;
+    let entry         = next_account_info(accounts)?;
+    let system        = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+
+    let posted = read_posted(&config, vaa)?;
+    let registration = Registration::parse(&posted.payload).ok_or(MessengerError::InvalidRegistration)?;
+    policy::check(id, &config, &posted, true, signature_set, guardian_set, policy)?;
+
+    // Only other Messenger deployments, registered as routes to ourselves, can fill the directory.
+    let route_key = state::route(id, id, posted.emitter_chain, &posted.emitter_address).0;
+    state::check_address(route, &route_key)?;
+    if state::load::<state::Route>(id, route).is_err() {
+        return Err(MessengerError::UnregisteredEmitter.into());
+    }
+
+    if !messenger_common::valid_nick(&registration.nick) {
+        return Err(MessengerError::InvalidNick.into());
+    }
+
+    let claim = Claim {
+        chain: posted.emitter_chain,
+        owner: registration.owner,
+        time:  u64::from(posted.vaa_time),
+    };
+    if !directory::claim(id, payer, entry, system, &registration.nick, claim)? {
+        msg!("Nick {} was registered first elsewhere", registration.nick);
+    }
     Ok(())
 }
+
+/// Read a PostedVAA account, checking it was created by our bridge and passes the receive policy,
+/// and decode the envelope it carries.
+fn verify(
+    id: &Pubkey,
+    config: &Config,
+    vaa: &AccountInfo,
+    signature_set: &AccountInfo,
+    guardian_set: &AccountInfo,
+    policy: &AccountInfo,
+) -> Result<(PostedVAAData, Envelope), ProgramError> {
+    let posted = read_posted(config, vaa)?;
+    let envelope = Envelope::try_from_slice(&posted.payload)?;
+
+    // Messages for another chain would otherwise get past that chain's destination cap.
+    if envelope.target_chain != 0 && envelope.target_chain != messenger_common::CHAIN_ID_SOLANA {
+        return Err(MessengerError::WrongTargetChain.into());
+    }
+
+    // Expired guardian sets and emitters' consistency requirements are checked before the message
+    // is used at all.
+    let routed = envelope.target.is_some();
+    policy::check(id, config, &posted, routed, signature_set, guardian_set, policy)?;
+    Ok((posted, envelope))
+}
+
+/// Read a PostedVAA account, checking it was created by our bridge.
+fn read_posted(config: &Config, vaa: &AccountInfo) -> Result<PostedVAAData, ProgramError> {
+    if *vaa.owner != config.wormhole {
+        return Err(MessengerError::InvalidSignedVAAOwner.into());
+    }
+    wormhole_sdk::read_vaa(vaa).map_err(|_| ProgramError::InvalidAccountData)
+}
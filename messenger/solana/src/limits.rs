@@ -0,0 +1,145 @@
+//! Rate limits on outbound messages, so a single sender can't flood the guardians and relayers
+//! through our emitter.
+//!
+//! Each sender may post `Config::sender_limit` messages per window, counted in their Sender
+//! account. Separately each destination chain can be capped by the admin with a Destination
+//! account, which every send must pass so the cap can't be skipped. Messages for any chain are
+//! capped as chain 0, capping another chain does not limit them. Senders the admin marks as exempt
+//! are subject to neither limit.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::state::{
+    self,
+    Config,
+    Destination,
+    RateLimit,
+    Sender,
+    Usage,
+};
+
+
+/// Replace the per-sender rate limit. Admin only.
+pub fn set_rate_limit(id: &Pubkey, accs: &[AccountInfo], limit: RateLimit) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let mut data: Config = state::load(id, config)?;
+    data.check_admin(admin)?;
+
+    if !limit.is_valid() {
+        return Err(MessengerError::InvalidRateLimit.into());
+    }
+
+    data.sender_limit = limit;
+    state::save(config, &data)
+}
+
+/// Set the cap on messages to `chain`, creating its Destination account if needed. A limit of 0
+/// lifts the cap. Admin only.
+pub fn set_destination_limit(id: &Pubkey, accs: &[AccountInfo], chain: u16, limit: RateLimit) -> ProgramResult {
+    let accounts    = &mut accs.iter();
+    let admin       = next_account_info(accounts)?;
+    let config      = next_account_info(accounts)?;
+    let destination = next_account_info(accounts)?;
+    let system      = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    config.check_admin(admin)?;
+
+    if !limit.is_valid() {
+        return Err(MessengerError::InvalidRateLimit.into());
+    }
+
+    let (key, bump) = state::destination(id, chain);
+    state::check_address(destination, &key)?;
+
+    let mut data = if **destination.lamports.borrow() == 0 {
+        let chain_bytes = chain.to_be_bytes();
+        let seeds: &[&[u8]] = &[b"destination", &chain_bytes, &[bump]];
+        state::create(id, admin, destination, system, seeds, Destination::SIZE)?;
+        Destination {
+            chain,
+            limit,
+            usage: Usage::default(),
+        }
+    } else {
+        state::load(id, destination)?
+    };
+
+    data.limit = limit;
+    state::save(destination, &data)
+}
+
+/// Exempt a sender from, or return them to, rate limiting. Their Sender account is created if
+/// they haven't sent anything yet. Admin only.
+pub fn set_exempt(id: &Pubkey, accs: &[AccountInfo], sender: Pubkey, exempt: bool) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    config.check_admin(admin)?;
+
+    let (key, bump) = state::sender(id, &sender);
+    state::check_address(account, &key)?;
+
+    let mut data = if **account.lamports.borrow() == 0 {
+        let seeds: &[&[u8]] = &[b"sender", sender.as_ref(), &[bump]];
+        state::create(id, admin, account, system, seeds, Sender::SIZE)?;
+        Sender::default()
+    } else {
+        state::load(id, account)?
+    };
+
+    data.exempt = exempt;
+    state::save(account, &data)
+}
+
+
+/// Count one message from `sender` to `target_chain` against both limits, failing if either is
+/// used up. The caller saves `sender`, the Destination account is found by key and saved here.
+pub fn consume(
+    id: &Pubkey,
+    config: &Config,
+    sender: &mut Sender,
+    target_chain: u16,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if sender.exempt {
+        return Ok(());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    if !sender.usage.consume(&config.sender_limit, now) {
+        return Err(MessengerError::RateLimited.into());
+    }
+
+    // Uncapped chains have no Destination account, but it must still be passed so a capped chain
+    // can't be reached by leaving its account out.
+    let destination = bridge::find(accounts, &state::destination(id, target_chain).0)?;
+    if **destination.lamports.borrow() == 0 {
+        return Ok(());
+    }
+
+    let mut data: Destination = state::load(id, destination)?;
+    if !data.usage.consume(&data.limit, now) {
+        return Err(MessengerError::DestinationCapReached.into());
+    }
+    state::save(destination, &data)
+}
@@ -0,0 +1,83 @@
+//! Account migrations.
+//!
+//! Accounts written by an older version of the program are upgraded whenever they are read, see
+//! `state::Versioned`, but only rewritten when next saved. Migrate rewrites any account in its
+//! current layout straight away, so old layouts can eventually be dropped. The runtime does not
+//! let programs resize their accounts, so a migrated layout must fit in the account as allocated,
+//! `state::RESERVED` bytes are left spare at creation for this. Migrating changes nothing the
+//! account's owner could object to, so anyone may do it.
+
+use borsh::BorshDeserialize;
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::state::{
+    self,
+    AccountKind,
+    Block,
+    Config,
+    Destination,
+    EmitterPolicy,
+    Header,
+    Inbox,
+    Multisig,
+    Nick,
+    Proposal,
+    Room,
+    Route,
+    Scheduled,
+    Sender,
+    Sponsor,
+    Sponsorship,
+    Versioned,
+};
+
+
+/// Rewrite a program owned account in the current layout for its kind.
+pub fn migrate(id: &Pubkey, accs: &[AccountInfo]) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let account  = next_account_info(accounts)?;
+
+    if account.owner != id {
+        return Err(MessengerError::InvalidAccountOwner.into());
+    }
+    let header = Header::deserialize(&mut &account.data.borrow()[..])?;
+
+    let version = match header.kind {
+        AccountKind::Config        => rewrite::<Config>(id, account),
+        AccountKind::Sender        => rewrite::<Sender>(id, account),
+        AccountKind::Destination   => rewrite::<Destination>(id, account),
+        AccountKind::EmitterPolicy => rewrite::<EmitterPolicy>(id, account),
+        AccountKind::Route         => rewrite::<Route>(id, account),
+        AccountKind::Sponsor       => rewrite::<Sponsor>(id, account),
+        AccountKind::Sponsorship   => rewrite::<Sponsorship>(id, account),
+        AccountKind::Room          => rewrite::<Room>(id, account),
+        AccountKind::Inbox         => rewrite::<Inbox>(id, account),
+        AccountKind::Nick          => rewrite::<Nick>(id, account),
+        AccountKind::Block         => rewrite::<Block>(id, account),
+        AccountKind::Scheduled     => rewrite::<Scheduled>(id, account),
+        AccountKind::Multisig      => rewrite::<Multisig>(id, account),
+        AccountKind::Proposal      => rewrite::<Proposal>(id, account),
+    }?;
+
+    Event::Migrated {
+        account: *account.key,
+        from:    header.version,
+        to:      version,
+    }
+    .emit()
+}
+
+/// Read an account through any upgrade and save it back, returning the version written.
+fn rewrite<T: Versioned>(id: &Pubkey, account: &AccountInfo) -> Result<u8, ProgramError> {
+    let data: T = state::load(id, account)?;
+    state::save(account, &data)?;
+    Ok(T::VERSION)
+}
@@ -0,0 +1,233 @@
+//! M-of-N multisig messages.
+//!
+//! A multisig is a PDA holding a fixed set of signers and a threshold. Any signer can Propose a
+//! message, which is stored in a proposal PDA until the threshold of signers Approve it. The
+//! approval that meets the threshold posts the message with the multisig PDA as its sender, so
+//! receivers see one sender for the group, and closes the proposal. Proposals carry an expiry
+//! after which they can't be approved, and can be cancelled by any signer or, once expired, by
+//! anyone. Proposal rent is always returned to the proposer.
+
+use borsh::BorshSerialize;
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use messenger_common::{
+    Envelope,
+    Message,
+};
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::state::{
+    self,
+    Config,
+    Multisig,
+    Proposal,
+    Sender,
+    MAX_SIGNERS,
+};
+
+
+/// Create multisig `multisig` with the given signers, `threshold` of whom must approve a message.
+pub fn create_multisig(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    multisig: u64,
+    signers: Vec<Pubkey>,
+    threshold: u8,
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let payer    = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    let duplicate = signers.iter().enumerate().any(|(i, key)| signers[..i].contains(key));
+    if signers.is_empty() || signers.len() > MAX_SIGNERS || duplicate {
+        return Err(MessengerError::InvalidMultisig.into());
+    }
+    if threshold == 0 || threshold as usize > signers.len() {
+        return Err(MessengerError::InvalidMultisig.into());
+    }
+
+    let (key, bump) = state::multisig(id, multisig);
+    state::check_address(account, &key)?;
+    if **account.lamports.borrow() != 0 {
+        return Err(MessengerError::MultisigExists.into());
+    }
+
+    let multisig_bytes = multisig.to_be_bytes();
+    state::create(id, payer, account, system, &[b"multisig", &multisig_bytes, &[bump]], Multisig::SIZE)?;
+    let data = Multisig {
+        id: multisig,
+        signers,
+        threshold,
+        proposals: 0,
+    };
+    state::save(account, &data)
+}
+
+/// Propose a message as the multisig's next proposal. Proposing does not count as an approval,
+/// the proposer approves like any other signer.
+pub fn propose(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    message: Message,
+    nonce: u32,
+    target_chain: u16,
+    expiry: i64,
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let proposer = next_account_info(accounts)?;
+    let multisig = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    let mut data: Multisig = state::load(id, multisig)?;
+    state::check_address(multisig, &state::multisig(id, data.id).0)?;
+    check_signer(&data, proposer)?;
+
+    let (key, bump) = state::proposal(id, multisig.key, data.proposals);
+    state::check_address(account, &key)?;
+
+    let proposal = Proposal {
+        multisig: *multisig.key,
+        id: data.proposals,
+        proposer: *proposer.key,
+        nonce,
+        target_chain,
+        message,
+        expiry,
+        approvals: 0,
+    };
+
+    // Proposals vary in size with their text, so are allocated to fit.
+    let proposal_bytes = data.proposals.to_be_bytes();
+    let seeds: &[&[u8]] = &[b"proposal", multisig.key.as_ref(), &proposal_bytes, &[bump]];
+    state::create(id, proposer, account, system, seeds, proposal.try_to_vec()?.len())?;
+    state::save(account, &proposal)?;
+
+    data.proposals += 1;
+    state::save(multisig, &data)?;
+    Event::Proposed {
+        multisig: *multisig.key,
+        proposal: proposal.id,
+        proposer: *proposer.key,
+        expiry,
+    }
+    .emit()
+}
+
+/// Approve a proposal. The approval that meets the threshold posts the message, with the signer
+/// as the payer of the SendMessage accounts, and closes the proposal to the proposer, who is
+/// located by key along with the multisig and proposal.
+pub fn approve(id: &Pubkey, accs: &[AccountInfo], multisig: u64, proposal: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let payer    = next_account_info(accounts)?;
+    let message  = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let sender   = next_account_info(accounts)?;
+
+    let origin = state::multisig(id, multisig).0;
+    let data: Multisig = state::load(id, bridge::find(accs, &origin)?)?;
+    let index = check_signer(&data, payer)?;
+
+    let account = bridge::find(accs, &state::proposal(id, &origin, proposal).0)?;
+    let mut pending = load(id, account)?;
+    if Clock::get()?.unix_timestamp >= pending.expiry {
+        return Err(MessengerError::ProposalExpired.into());
+    }
+    if pending.approvals & (1 << index) != 0 {
+        return Err(MessengerError::AlreadyApproved.into());
+    }
+
+    pending.approvals |= 1 << index;
+    Event::Approved {
+        multisig: origin,
+        proposal,
+        signer:   *payer.key,
+        votes:    pending.votes(),
+    }
+    .emit()?;
+
+    if pending.votes() < data.threshold {
+        return state::save(account, &pending);
+    }
+
+    // Posted as the multisig's next message, at the config's default consistency.
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    let envelope = Envelope {
+        sender:       origin.to_bytes(),
+        target_chain: pending.target_chain,
+        target:       None,
+        recipient:    None,
+        room:         None,
+        message:      pending.message,
+    };
+    let level = config.default_consistency;
+    crate::post(id, accs, &config, payer, &origin, sender, message, &envelope, pending.nonce, level, None, true)?;
+
+    let proposer = bridge::find(accs, &pending.proposer)?;
+    state::close(account, proposer);
+    let count = state::load::<Sender>(id, sender)?.count - 1;
+    Event::ProposalExecuted {
+        multisig: origin,
+        proposal,
+        count,
+    }
+    .emit()
+}
+
+/// Cancel a proposal, returning its rent to the proposer. Any signer can cancel a proposal, and
+/// once expired anyone can.
+pub fn cancel(id: &Pubkey, accs: &[AccountInfo], proposal: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let caller   = next_account_info(accounts)?;
+    let proposer = next_account_info(accounts)?;
+    let multisig = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+
+    let data: Multisig = state::load(id, multisig)?;
+    state::check_address(multisig, &state::multisig(id, data.id).0)?;
+    state::check_address(account, &state::proposal(id, multisig.key, proposal).0)?;
+    let pending = load(id, account)?;
+    state::check_address(proposer, &pending.proposer)?;
+
+    if Clock::get()?.unix_timestamp < pending.expiry {
+        check_signer(&data, caller)?;
+    }
+
+    state::close(account, proposer);
+    Event::ProposalCancelled {
+        multisig: *multisig.key,
+        proposal,
+    }
+    .emit()
+}
+
+
+/// Check `account` signed and is one of the multisig's signers, returning its index.
+fn check_signer(multisig: &Multisig, account: &AccountInfo) -> Result<usize, ProgramError> {
+    if !account.is_signer {
+        return Err(MessengerError::Unauthorized.into());
+    }
+    multisig
+        .signer(account.key)
+        .ok_or_else(|| MessengerError::NotMultisigSigner.into())
+}
+
+/// Read a proposal, checking it exists.
+fn load(id: &Pubkey, account: &AccountInfo) -> Result<Proposal, ProgramError> {
+    if **account.lamports.borrow() == 0 {
+        return Err(MessengerError::UnknownProposal.into());
+    }
+    state::load(id, account)
+}
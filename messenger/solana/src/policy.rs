@@ -0,0 +1,114 @@
+//! Policy applied to every received VAA before the message is accepted.
+//!
+//! The bridge only checks a guardian set has not expired when a VAA is posted, so a PostedVAA can
+//! outlive the set that signed it. Receives therefore look up the signature set the bridge
+//! recorded for the VAA and refuse it once that guardian set has expired.
+//!
+//! The admin can also register a policy for an emitter, stored in a PDA derived from its chain
+//! and address. A policy sets the lowest consistency level messages from the emitter are accepted
+//! at, and optionally the level at which the emitter's chain is final. Routed messages, which
+//! target programs act on and so may carry value, and nickname registrations must meet the
+//! finality level, so a message observed at a lower level on a fork that is later rolled back
+//! can't be treated as final.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use wormhole_sdk::PostedVAAData;
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::state::{
+    self,
+    Config,
+    EmitterPolicy,
+};
+
+
+/// Register or replace the receive policy for `emitter` on `chain`. Admin only.
+pub fn set_emitter_policy(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    chain: u16,
+    emitter: [u8; 32],
+    min_consistency: u8,
+    finality: Option<u8>,
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let policy   = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    config.check_admin(admin)?;
+
+    let (key, bump) = state::emitter_policy(id, chain, &emitter);
+    state::check_address(policy, &key)?;
+
+    if **policy.lamports.borrow() == 0 {
+        let chain_bytes = chain.to_be_bytes();
+        let seeds: &[&[u8]] = &[b"policy", &chain_bytes, &emitter, &[bump]];
+        state::create(id, admin, policy, system, seeds, EmitterPolicy::SIZE)?;
+    }
+
+    let data = EmitterPolicy {
+        chain,
+        emitter,
+        min_consistency,
+        finality,
+    };
+    state::save(policy, &data)
+}
+
+
+/// Check a posted VAA against the receive policy, given the bridge's signature set and guardian
+/// set accounts for it and the emitter's policy account. `final_only` VAAs must also meet the
+/// emitter's finality level.
+pub fn check(
+    id: &Pubkey,
+    config: &Config,
+    posted: &PostedVAAData,
+    final_only: bool,
+    signature_set: &AccountInfo,
+    guardian_set: &AccountInfo,
+    policy: &AccountInfo,
+) -> ProgramResult {
+    // The signature set tells us which guardian set signed the VAA.
+    state::check_address(signature_set, &posted.vaa_signature_account)?;
+    let signatures: bridge::SignatureSet = bridge::load(config, signature_set)?;
+
+    state::check_address(guardian_set, &bridge::guardian_set(config, signatures.guardian_set_index))?;
+    let guardians: bridge::GuardianSet = bridge::load(config, guardian_set)?;
+    let now = Clock::get()?.unix_timestamp;
+    if guardians.expiration_time != 0 && i64::from(guardians.expiration_time) < now {
+        return Err(MessengerError::GuardianSetExpired.into());
+    }
+
+    // Emitters without a registered policy are accepted at any level, but the account must still
+    // be passed so a registered policy can't be skipped.
+    let key = state::emitter_policy(id, posted.emitter_chain, &posted.emitter_address).0;
+    state::check_address(policy, &key)?;
+    if **policy.lamports.borrow() == 0 {
+        return Ok(());
+    }
+
+    let policy: EmitterPolicy = state::load(id, policy)?;
+    if posted.consistency_level < policy.min_consistency {
+        return Err(MessengerError::InsufficientConsistency.into());
+    }
+
+    match policy.finality {
+        Some(finality) if final_only && posted.consistency_level < finality => {
+            Err(MessengerError::NotFinal.into())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,185 @@
+//! Group rooms.
+//!
+//! A room has an owner, who manages its member list, and members who may each be allowed to post.
+//! Messages sent with SendRoomMessage carry the room id in their envelope and are refused unless
+//! the sender is a member allowed to post. Received room messages are delivered into the room's
+//! inbox on the receiving chain, which members read to find the messages posted to the room.
+//! Only other Messenger deployments, registered as routes to ourselves, can deliver into an inbox.
+//! The room owner's block list applies to the inbox, see `blocks`. Each message is delivered at
+//! most once, marked by a receipt, so replaying a VAA can't push real messages out of the inbox.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use wormhole_sdk::PostedVAAData;
+
+use messenger_common::Envelope;
+
+use crate::blocks;
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::route;
+use crate::state::{
+    self,
+    BlockMode,
+    Inbox,
+    InboxEntry,
+    Member,
+    Room,
+    MAX_MEMBERS,
+};
+
+
+/// Create room `room` and its inbox, with the payer as owner.
+pub fn create_room(id: &Pubkey, accs: &[AccountInfo], room: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let owner    = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+    let inbox    = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    let (key, bump) = state::room(id, room);
+    state::check_address(account, &key)?;
+    if **account.lamports.borrow() != 0 {
+        return Err(MessengerError::RoomExists.into());
+    }
+
+    let room_bytes = room.to_be_bytes();
+    state::create(id, owner, account, system, &[b"room", &room_bytes, &[bump]], Room::SIZE)?;
+    let data = Room {
+        id:      room,
+        owner:   *owner.key,
+        members: vec![Member {
+            key:      *owner.key,
+            can_post: true,
+        }],
+    };
+    state::save(account, &data)?;
+
+    // Inboxes are created alongside their room, so a room that exists can always be delivered to.
+    let (key, bump) = state::inbox(id, room);
+    state::check_address(inbox, &key)?;
+    state::create(id, owner, inbox, system, &[b"inbox", &room_bytes, &[bump]], Inbox::SIZE)?;
+    let data = Inbox {
+        room,
+        count: 0,
+        entries: Vec::new(),
+    };
+    state::save(inbox, &data)
+}
+
+/// Add `member` to a room, or change whether they can post. Owner only.
+pub fn set_member(id: &Pubkey, accs: &[AccountInfo], room: u64, member: Pubkey, can_post: bool) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let owner    = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+
+    let mut data = load_room(id, account, room)?;
+    if !owner.is_signer || *owner.key != data.owner {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    match data.members.iter_mut().find(|existing| existing.key == member) {
+        Some(existing) => existing.can_post = can_post,
+        None if data.members.len() >= MAX_MEMBERS => return Err(MessengerError::RoomFull.into()),
+        None => data.members.push(Member { key: member, can_post }),
+    }
+    state::save(account, &data)
+}
+
+/// Remove `member` from a room. Either the owner or the member themselves may do this.
+pub fn remove_member(id: &Pubkey, accs: &[AccountInfo], room: u64, member: Pubkey) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let signer   = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+
+    let mut data = load_room(id, account, room)?;
+    if !signer.is_signer || (*signer.key != data.owner && *signer.key != member) {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    data.members.retain(|existing| existing.key != member);
+    state::save(account, &data)
+}
+
+
+/// Check `poster` may post to `room`. The room account is located by key.
+pub fn check_poster(id: &Pubkey, accounts: &[AccountInfo], room: u64, poster: &Pubkey) -> ProgramResult {
+    let account = bridge::find(accounts, &state::room(id, room).0)?;
+    let data = load_room(id, account, room)?;
+    match data.member(poster) {
+        None => Err(MessengerError::NotRoomMember.into()),
+        Some(member) if !member.can_post => Err(MessengerError::CannotPost.into()),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Deliver a received room message into the room's inbox. The room, its inbox, the route marking
+/// the emitter as a Messenger, the owner's block list entry for the sender, the delivery receipt
+/// and the system program are located by key. Returns `false` if the owner has blocked the sender
+/// and the message was dropped instead.
+pub fn deliver<'a>(
+    id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    room: u64,
+    vaa: &AccountInfo,
+    posted: &PostedVAAData,
+    envelope: &Envelope,
+) -> Result<bool, ProgramError> {
+    // Anyone can emit a room envelope, but only a Messenger checks the sender may post.
+    if !route::is_messenger(id, accounts, posted.emitter_chain, &posted.emitter_address) {
+        return Err(MessengerError::UnregisteredEmitter.into());
+    }
+
+    let account = bridge::find(accounts, &state::room(id, room).0)?;
+    let owner = load_room(id, account, room)?.owner;
+    let inbox = bridge::find(accounts, &state::inbox(id, room).0)?;
+    if **inbox.lamports.borrow() == 0 {
+        return Err(MessengerError::UnknownRoom.into());
+    }
+
+    // Entries are a ring, so a replayed VAA would push out real messages. Each is delivered once,
+    // whether or not the owner has blocked its sender.
+    let (key, bump) = state::delivery(id, posted.emitter_chain, &posted.emitter_address, posted.sequence);
+    let receipt = bridge::find(accounts, &key)?;
+    if **receipt.lamports.borrow() != 0 {
+        return Err(MessengerError::AlreadyReceived.into());
+    }
+    let system = bridge::find(accounts, &solana_program::system_program::id())?;
+    let chain_bytes = posted.emitter_chain.to_be_bytes();
+    let sequence_bytes = posted.sequence.to_be_bytes();
+    let seeds: &[&[u8]] = &[b"delivery", &chain_bytes, &posted.emitter_address, &sequence_bytes, &[bump]];
+    state::create(id, payer, receipt, system, seeds, 0)?;
+
+    let mode = blocks::check(id, accounts, &owner, posted, &envelope.sender)?;
+    if mode == Some(BlockMode::Block) {
+        return Ok(false);
+    }
+
+    let mut data: Inbox = state::load(id, inbox)?;
+    data.push(InboxEntry {
+        emitter_chain: posted.emitter_chain,
+        sequence:      posted.sequence,
+        sender:        envelope.sender,
+        vaa:           *vaa.key,
+        quarantined:   mode == Some(BlockMode::Mute),
+    });
+    state::save(inbox, &data)?;
+    Ok(true)
+}
+
+
+/// Read room `room`, checking it is the room's PDA and has been created.
+fn load_room(id: &Pubkey, account: &AccountInfo, room: u64) -> Result<Room, ProgramError> {
+    state::check_address(account, &state::room(id, room).0)?;
+    if **account.lamports.borrow() == 0 {
+        return Err(MessengerError::UnknownRoom.into());
+    }
+    state::load(id, account)
+}
@@ -0,0 +1,201 @@
+//! Routing of received messages into target programs.
+//!
+//! A message whose envelope names a target is forwarded by CPI to that program once the VAA is
+//! verified, provided the admin has registered the emitter for that target. Targets receive
+//! `CALLBACK_TAG` followed by a borsh encoded `Callback`, with the Messenger's callback authority
+//! as the first account, signed, and any extra accounts passed to RecvMessage after it.
+
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::instruction::{
+    AccountMeta,
+    Instruction,
+};
+use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use messenger_common::Message;
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::state::{
+    self,
+    Config,
+    Route,
+};
+
+
+/// Prefix of every callback's instruction data, so targets can tell callbacks apart from their
+/// own instructions. This and the `Callback` layout are stable.
+pub const CALLBACK_TAG: [u8; 8] = *b"msgrrecv";
+
+
+/// Instruction data delivered to a target program for each routed message.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Callback {
+    pub emitter_chain:   u16,
+    pub emitter_address: [u8; 32],
+    pub sequence:        u64,
+
+    /// Sender recorded in the message envelope on the emitting chain.
+    pub sender: [u8; 32],
+
+    pub message: Message,
+}
+
+impl Callback {
+    /// Encode as callback instruction data.
+    pub fn data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = CALLBACK_TAG.to_vec();
+        self.serialize(&mut data)?;
+        Ok(data)
+    }
+
+    /// Decode callback instruction data, returning `None` if it isn't a callback at all.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        match data.strip_prefix(&CALLBACK_TAG[..]) {
+            Some(data) => Callback::try_from_slice(data).ok(),
+            None => None,
+        }
+    }
+}
+
+
+/// Register `emitter` on `chain` as allowed to route messages to `program`. Admin only.
+pub fn register_route(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    program: Pubkey,
+    chain: u16,
+    emitter: [u8; 32],
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let route    = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    config.check_admin(admin)?;
+
+    let (key, bump) = state::route(id, &program, chain, &emitter);
+    state::check_address(route, &key)?;
+
+    // Registering an existing route is a no-op.
+    if **route.lamports.borrow() != 0 {
+        return Ok(());
+    }
+
+    let chain_bytes = chain.to_be_bytes();
+    let seeds: &[&[u8]] = &[b"route", program.as_ref(), &chain_bytes, &emitter, &[bump]];
+    state::create(id, admin, route, system, seeds, Route::SIZE)?;
+    state::save(route, &Route { program, chain, emitter })?;
+    Event::ChainRegistered { program, chain, emitter }.emit()
+}
+
+/// Remove a route, returning its rent to the admin. Admin only.
+pub fn remove_route(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    program: Pubkey,
+    chain: u16,
+    emitter: [u8; 32],
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let route    = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    config.check_admin(admin)?;
+
+    state::check_address(route, &state::route(id, &program, chain, &emitter).0)?;
+    let _: Route = state::load(id, route)?;
+
+    // Draining the lamports lets the runtime reclaim the account at the end of the transaction.
+    let lamports = **route.lamports.borrow();
+    **route.lamports.borrow_mut() = 0;
+    **admin.lamports.borrow_mut() += lamports;
+    route.data.borrow_mut().fill(0);
+    Ok(())
+}
+
+/// Whether `emitter` on `chain` is another Messenger deployment, registered as a route to this
+/// program. Its route is located by key among `accounts`.
+pub fn is_messenger(id: &Pubkey, accounts: &[AccountInfo], chain: u16, emitter: &[u8; 32]) -> bool {
+    bridge::find(accounts, &state::route(id, id, chain, emitter).0)
+        .and_then(|route| state::load::<Route>(id, route))
+        .is_ok()
+}
+
+
+/// Forward a received message to `target`. `accounts` are RecvMessage's accounts after the VAA
+/// and config: route, receipt, callback authority, system, target, then accounts for the target.
+pub fn forward<'a>(
+    id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    vaa: &AccountInfo<'a>,
+    accs: &[AccountInfo<'a>],
+    target: &Pubkey,
+    callback: &Callback,
+) -> ProgramResult {
+    let accounts  = &mut accs.iter();
+    let route     = next_account_info(accounts)?;
+    let receipt   = next_account_info(accounts)?;
+    let authority = next_account_info(accounts)?;
+    let system    = next_account_info(accounts)?;
+    let program   = next_account_info(accounts)?;
+    let forwarded = accounts.as_slice();
+
+    // Only emitters registered for the target may reach it.
+    let route_key = state::route(id, target, callback.emitter_chain, &callback.emitter_address).0;
+    state::check_address(route, &route_key)?;
+    state::check_address(program, target)?;
+    if state::load::<Route>(id, route).is_err() {
+        return Err(MessengerError::UnregisteredEmitter.into());
+    }
+
+    // Targets act on callbacks, so unlike plain receives each VAA is only delivered once.
+    let (key, bump) = state::receipt(id, vaa.key);
+    state::check_address(receipt, &key)?;
+    if **receipt.lamports.borrow() != 0 {
+        return Err(MessengerError::AlreadyReceived.into());
+    }
+    state::create(id, payer, receipt, system, &[b"receipt", vaa.key.as_ref(), &[bump]], 0)?;
+
+    let (key, bump) = state::callback_authority(id);
+    state::check_address(authority, &key)?;
+
+    // Forwarded accounts keep their writability but never our signers' privileges.
+    let mut metas = vec![AccountMeta::new_readonly(key, true)];
+    metas.extend(forwarded.iter().map(|account| AccountMeta {
+        pubkey:      *account.key,
+        is_signer:   false,
+        is_writable: account.is_writable,
+    }));
+
+    let mut infos = vec![authority.clone()];
+    infos.extend(forwarded.iter().cloned());
+    infos.push(program.clone());
+
+    invoke_signed(
+        &Instruction {
+            program_id: *target,
+            accounts:   metas,
+            data:       callback.data()?,
+        },
+        &infos,
+        &[&[b"callback", &[bump]]],
+    )
+}
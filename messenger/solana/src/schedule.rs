@@ -0,0 +1,173 @@
+//! Scheduled messages.
+//!
+//! ScheduleMessage stores a message in a PDA along with the bridge fee and protocol fee at the
+//! time, and a reward of the sender's choosing. Once the message is due anyone can turn the
+//! Crank, which posts it as the sender's next message with the cranker paying the fees and the
+//! message account. The cranker is paid the fees at the bridge's fee when cranked, and the reward,
+//! from the escrow, and the rest, including the PDA's rent, is refunded to the sender. The reward
+//! should cover the message account's rent for cranking to pay. Until then the sender can cancel
+//! and be refunded.
+
+use borsh::BorshSerialize;
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::invoke;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction::transfer;
+use solana_program::sysvar::Sysvar;
+
+use messenger_common::{
+    Envelope,
+    Message,
+};
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::events::Event;
+use crate::state::{
+    self,
+    Config,
+    Due,
+    Scheduled,
+};
+
+
+/// Schedule `message` as the sender's scheduled message `id`, escrowing its fees and `reward`.
+#[allow(clippy::too_many_arguments)]
+pub fn schedule(
+    id: &Pubkey,
+    accs: &[AccountInfo],
+    schedule: u64,
+    message: Message,
+    nonce: u32,
+    due: Due,
+    target_chain: u16,
+    reward: u64,
+) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let sender   = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+
+    let (key, bump) = state::scheduled(id, sender.key, schedule);
+    state::check_address(account, &key)?;
+    if **account.lamports.borrow() != 0 {
+        return Err(MessengerError::ScheduleExists.into());
+    }
+
+    let data = Scheduled {
+        sender: *sender.key,
+        id: schedule,
+        due,
+        nonce,
+        target_chain,
+        message,
+        reward,
+    };
+
+    // Scheduled messages vary in size with their text, so are allocated to fit.
+    let system = bridge::find(accs, &solana_program::system_program::id())?;
+    let schedule_bytes = schedule.to_be_bytes();
+    let seeds: &[&[u8]] = &[b"scheduled", sender.key.as_ref(), &schedule_bytes, &[bump]];
+    state::create(id, sender, account, system, seeds, data.try_to_vec()?.len())?;
+    state::save(account, &data)?;
+
+    let escrow = escrow(&config, accs, reward)?;
+    invoke(&transfer(sender.key, account.key, escrow), accs)
+}
+
+/// Post a due scheduled message and pay the cranker, who is the payer of the SendMessage
+/// accounts. The scheduled message and its sender's account, refunded the rest of the escrow, are
+/// located by key.
+pub fn crank(id: &Pubkey, accs: &[AccountInfo], origin: Pubkey, schedule: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let payer    = next_account_info(accounts)?;
+    let message  = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+    let sender   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+
+    let account = bridge::find(accs, &state::scheduled(id, &origin, schedule).0)?;
+    let data = load(id, account)?;
+    if !data.due.passed(&Clock::get()?) {
+        return Err(MessengerError::NotDue.into());
+    }
+
+    // The bridge fee may have risen since the message was scheduled, in which case the escrow no
+    // longer covers cranking and the sender must cancel and schedule again.
+    let owed = escrow(&config, accs, data.reward)?;
+    let rent = Rent::get()?.minimum_balance(account.data_len());
+    if (**account.lamports.borrow()).saturating_sub(rent) < owed {
+        return Err(MessengerError::InsufficientEscrow.into());
+    }
+    let refund = bridge::find(accs, &origin)?;
+
+    // Posted as if the sender had sent it now, at the config's default consistency. The cranker is
+    // reimbursed from the escrow, so never draws on a sponsor's grant to pay the bridge.
+    let envelope = Envelope {
+        sender:       origin.to_bytes(),
+        target_chain: data.target_chain,
+        target:       None,
+        recipient:    None,
+        room:         None,
+        message:      data.message,
+    };
+    let level = config.default_consistency;
+    crate::post(id, accs, &config, payer, &origin, sender, message, &envelope, data.nonce, level, None, false)?;
+
+    **account.lamports.borrow_mut() -= owed;
+    **payer.lamports.borrow_mut() += owed;
+    state::close(account, refund);
+    Event::Cranked {
+        sender:  origin,
+        id:      schedule,
+        cranker: *payer.key,
+        reward:  data.reward,
+    }
+    .emit()
+}
+
+/// Cancel a scheduled message, refunding everything escrowed for it to the sender.
+pub fn cancel(id: &Pubkey, accs: &[AccountInfo], schedule: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let sender   = next_account_info(accounts)?;
+    let account  = next_account_info(accounts)?;
+
+    if !sender.is_signer {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    state::check_address(account, &state::scheduled(id, sender.key, schedule).0)?;
+    load(id, account)?;
+    state::close(account, sender);
+    Ok(())
+}
+
+
+/// Lamports escrowed for a scheduled message on top of its account's rent: the current bridge
+/// and protocol fees, and the reward.
+fn escrow(config: &Config, accounts: &[AccountInfo], reward: u64) -> Result<u64, ProgramError> {
+    let fee = bridge::fee(config, accounts)?;
+    fee.checked_add(config.protocol_fee)
+        .and_then(|fees| fees.checked_add(reward))
+        .ok_or(ProgramError::InvalidArgument)
+}
+
+/// Read a scheduled message, checking it exists.
+fn load(id: &Pubkey, account: &AccountInfo) -> Result<Scheduled, ProgramError> {
+    if **account.lamports.borrow() == 0 {
+        return Err(MessengerError::UnknownSchedule.into());
+    }
+    state::load(id, account)
+}
@@ -0,0 +1,849 @@
+//! Accounts owned by the Messenger program, along with the helpers used to derive, create, read
+//! and write them.
+//!
+//! Every account holding data starts with a `Header` naming its kind and the version of its
+//! layout. When a layout changes its `Versioned::VERSION` is bumped and `Versioned::upgrade`
+//! taught to read the previous one, so accounts written by older code stay readable and are
+//! rewritten in the new layout the next time they are saved, or by Migrate, see `migrate`.
+
+use std::cmp::Ordering;
+
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
+use solana_program::account_info::AccountInfo;
+use solana_program::bpf_loader_upgradeable;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction;
+use solana_program::sysvar::Sysvar;
+
+use wormhole_sdk::ConsistencyLevel;
+
+use messenger_common::{
+    Claim,
+    Message,
+};
+
+use crate::error::MessengerError;
+
+
+/// The consistency levels a message can be emitted with. This mirrors the SDK's
+/// `ConsistencyLevel` but is `Copy` and comparable so it can be stored in and checked against our
+/// config account.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Consistency {
+    /// Emit once the block is confirmed, lower latency but can be rolled back.
+    Confirmed,
+
+    /// Emit once the block is finalized.
+    Finalized,
+}
+
+impl From<Consistency> for ConsistencyLevel {
+    fn from(level: Consistency) -> Self {
+        match level {
+            Consistency::Confirmed => ConsistencyLevel::Confirmed,
+            Consistency::Finalized => ConsistencyLevel::Finalized,
+        }
+    }
+}
+
+
+/// The kinds of account the program stores data in. New kinds must be added at the end, as the
+/// position is what is stored.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AccountKind {
+    Config,
+    Sender,
+    Destination,
+    EmitterPolicy,
+    Route,
+    Sponsor,
+    Sponsorship,
+    Room,
+    Inbox,
+    Nick,
+    Block,
+    Scheduled,
+    Multisig,
+    Proposal,
+}
+
+/// The first bytes of every account holding data, so one kind of account can't be passed off as
+/// another and layouts can change without stranding existing accounts.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Header {
+    pub kind:    AccountKind,
+    pub version: u8,
+}
+
+impl Header {
+    pub const SIZE: usize = 1 + 1;
+}
+
+/// Spare bytes allocated after every account's data for later versions of its layout to grow
+/// into. The runtime does not let programs resize the accounts they own, so this is all the room
+/// a migrated account has.
+pub const RESERVED: usize = 64;
+
+/// An account layout stored behind a `Header`.
+pub trait Versioned: BorshSerialize + BorshDeserialize {
+    const KIND: AccountKind;
+
+    /// Version of the current layout, bumped whenever it changes.
+    const VERSION: u8 = 1;
+
+    /// Read an account written in the layout of an older `version`. Only layouts that have
+    /// changed need to override this.
+    fn upgrade(version: u8, data: &[u8]) -> Result<Self, ProgramError> {
+        let _ = (version, data);
+        Err(MessengerError::UnsupportedVersion.into())
+    }
+}
+
+impl Versioned for Config        { const KIND: AccountKind = AccountKind::Config; }
+impl Versioned for Sender        { const KIND: AccountKind = AccountKind::Sender; }
+impl Versioned for Destination   { const KIND: AccountKind = AccountKind::Destination; }
+impl Versioned for EmitterPolicy { const KIND: AccountKind = AccountKind::EmitterPolicy; }
+impl Versioned for Route         { const KIND: AccountKind = AccountKind::Route; }
+impl Versioned for Sponsor       { const KIND: AccountKind = AccountKind::Sponsor; }
+impl Versioned for Sponsorship   { const KIND: AccountKind = AccountKind::Sponsorship; }
+impl Versioned for Room          { const KIND: AccountKind = AccountKind::Room; }
+impl Versioned for Inbox         { const KIND: AccountKind = AccountKind::Inbox; }
+impl Versioned for Nick          { const KIND: AccountKind = AccountKind::Nick; }
+impl Versioned for Block         { const KIND: AccountKind = AccountKind::Block; }
+impl Versioned for Scheduled     { const KIND: AccountKind = AccountKind::Scheduled; }
+impl Versioned for Multisig      { const KIND: AccountKind = AccountKind::Multisig; }
+impl Versioned for Proposal      { const KIND: AccountKind = AccountKind::Proposal; }
+
+
+/// Program wide configuration, stored in a single PDA derived from `[b"config"]`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Key allowed to update this config.
+    pub admin: Pubkey,
+
+    /// The Wormhole core bridge this deployment talks to. Storing it here rather than relying on
+    /// the SDK's network feature lets a single binary be deployed to devnet, testnet and mainnet.
+    pub wormhole: Pubkey,
+
+    /// Consistency level used when a sender does not ask for one.
+    pub default_consistency: Consistency,
+
+    /// Consistency levels senders are allowed to ask for.
+    pub allowed_consistency: Vec<Consistency>,
+
+    /// How many messages each sender may post per window, see `limits`.
+    pub sender_limit: RateLimit,
+
+    /// Lamports charged per message on top of the bridge fee, paid into the treasury.
+    pub protocol_fee: u64,
+
+    /// Emergency switches refusing every send or receive instruction while set.
+    pub send_paused:    bool,
+    pub receive_paused: bool,
+
+    /// Emitter upgrades must be signed by, see `governance`. A chain of 0 means none is set.
+    pub governance_chain:   u16,
+    pub governance_emitter: [u8; 32],
+}
+
+impl Config {
+    /// Space reserved for the config account, large enough to hold every consistency level.
+    pub const SIZE: usize = 32 + 32 + 1 + 4 + 2 + RateLimit::SIZE + 8 + 2 + 2 + 32;
+
+    /// Check the consistency policy is usable: the default must be allowed, and the allowed list
+    /// must fit in the space reserved for it.
+    pub fn check(&self) -> ProgramResult {
+        let allowed = &self.allowed_consistency;
+        let unique = allowed.iter().enumerate().all(|(i, level)| !allowed[..i].contains(level));
+        if !unique || allowed.len() > 2 || !allowed.contains(&self.default_consistency) {
+            return Err(MessengerError::ConsistencyNotAllowed.into());
+        }
+        Ok(())
+    }
+
+    /// Check `admin` is the config admin and has signed the transaction.
+    pub fn check_admin(&self, admin: &AccountInfo) -> ProgramResult {
+        if !admin.is_signer || *admin.key != self.admin {
+            return Err(MessengerError::Unauthorized.into());
+        }
+        Ok(())
+    }
+
+    /// Resolve the consistency level for a message, falling back to the default and rejecting any
+    /// level the config does not allow.
+    pub fn consistency(&self, requested: Option<Consistency>) -> Result<Consistency, ProgramError> {
+        let level = requested.unwrap_or(self.default_consistency);
+        if !self.allowed_consistency.contains(&level) {
+            return Err(MessengerError::ConsistencyNotAllowed.into());
+        }
+        Ok(level)
+    }
+}
+
+
+/// A cap of `limit` messages every `window` seconds. A limit of 0 means no cap.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    pub window: u32,
+    pub limit:  u32,
+}
+
+impl RateLimit {
+    pub const SIZE: usize = 4 + 4;
+
+    /// No cap at all, the default for new deployments.
+    pub const UNLIMITED: RateLimit = RateLimit { window: 0, limit: 0 };
+
+    /// Whether the limit can be enforced. A limit over a window of 0 seconds would start a new
+    /// window for every message, and so never apply.
+    pub fn is_valid(&self) -> bool {
+        self.limit == 0 || self.window != 0
+    }
+}
+
+/// Messages counted against a `RateLimit` in the current window, which started at `start` (a unix
+/// timestamp). Windows are fixed rather than sliding so a single counter is enough.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub start: i64,
+    pub used:  u32,
+}
+
+impl Usage {
+    pub const SIZE: usize = 8 + 4;
+
+    /// Count one message sent at `now`, starting a new window if the current one has passed.
+    /// Returns `false`, leaving the usage untouched, if the limit has already been reached.
+    pub fn consume(&mut self, limit: &RateLimit, now: i64) -> bool {
+        if limit.limit == 0 {
+            return true;
+        }
+
+        let mut usage = *self;
+        if now >= usage.start.saturating_add(i64::from(limit.window)) {
+            usage = Usage { start: now, used: 0 };
+        }
+        if usage.used >= limit.limit {
+            return false;
+        }
+
+        usage.used += 1;
+        *self = usage;
+        true
+    }
+}
+
+
+/// Funds deposited by a sponsor into the fee vault, stored in a PDA derived from
+/// `[b"sponsor", sponsor]`. The lamports themselves live in the vault.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Sponsor {
+    pub sponsor: Pubkey,
+
+    /// Lamports this sponsor has in the vault that have not yet been spent or withdrawn.
+    pub balance: u64,
+}
+
+impl Sponsor {
+    pub const SIZE: usize = 32 + 8;
+}
+
+
+/// A grant from a sponsor covering the bridge fee for a user, stored in a PDA derived from
+/// `[b"sponsorship", sponsor, user]`. A user may hold grants from several sponsors.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Sponsorship {
+    pub sponsor: Pubkey,
+
+    /// Number of messages still covered. Sponsors wanting a plain allowlist can use `u64::MAX`.
+    pub remaining: u64,
+}
+
+impl Sponsorship {
+    pub const SIZE: usize = 32 + 8;
+}
+
+
+/// Per-sender state, stored in a PDA derived from `[b"sender", sender]`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct Sender {
+    /// Number of messages sent so far. The next message is posted to the message PDA derived
+    /// from this count, so clients can find any past message from the sender and its index.
+    pub count: u64,
+
+    /// Messages counted against `Config::sender_limit`.
+    pub usage: Usage,
+
+    /// Set by the admin for trusted senders, who are not rate limited at all.
+    pub exempt: bool,
+}
+
+impl Sender {
+    pub const SIZE: usize = 8 + Usage::SIZE + 1;
+}
+
+
+/// Outbound cap for messages to one destination chain, stored in a PDA derived from
+/// `[b"destination", chain]`. Chains without one of these accounts are not capped. Messages for
+/// any chain count against chain 0's cap alone, not every chain's.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Destination {
+    pub chain: u16,
+    pub limit: RateLimit,
+    pub usage: Usage,
+}
+
+impl Destination {
+    pub const SIZE: usize = 2 + RateLimit::SIZE + Usage::SIZE;
+}
+
+
+/// An emitter allowed to route messages to a target program, stored in a PDA derived from
+/// `[b"route", program, chain, emitter]`. The account existing is what permits routing, the
+/// fields are kept so routes can be listed off-chain.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Route {
+    pub program: Pubkey,
+    pub chain:   u16,
+    pub emitter: [u8; 32],
+}
+
+impl Route {
+    pub const SIZE: usize = 32 + 2 + 32;
+}
+
+
+/// Receive policy for an emitter on a foreign chain, stored in a PDA derived from
+/// `[b"policy", chain, emitter]`, see `policy`. Consistency levels are the raw values carried in
+/// VAAs, which mean different things on each chain.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct EmitterPolicy {
+    pub chain:   u16,
+    pub emitter: [u8; 32],
+
+    /// Lowest consistency level messages from this emitter are accepted at.
+    pub min_consistency: u8,
+
+    /// Level at which the emitter's chain is final, required of routed messages if set.
+    pub finality: Option<u8>,
+}
+
+impl EmitterPolicy {
+    pub const SIZE: usize = 2 + 32 + 1 + 2;
+}
+
+
+/// Most members a room can hold, which bounds the size of its account.
+pub const MAX_MEMBERS: usize = 16;
+
+/// Number of messages a room inbox keeps, once full the oldest is overwritten.
+pub const INBOX_LEN: usize = 16;
+
+/// A member of a room, who may read its inbox and, with `can_post`, send to it.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Member {
+    pub key:      Pubkey,
+    pub can_post: bool,
+}
+
+impl Member {
+    pub const SIZE: usize = 32 + 1;
+}
+
+/// A group room, stored in a PDA derived from `[b"room", id]`, see `rooms`. The owner manages
+/// membership and is added as a member that can post when the room is created.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Room {
+    pub id:      u64,
+    pub owner:   Pubkey,
+    pub members: Vec<Member>,
+}
+
+impl Room {
+    pub const SIZE: usize = 8 + 32 + 4 + MAX_MEMBERS * Member::SIZE;
+
+    /// Look up `key`'s membership.
+    pub fn member(&self, key: &Pubkey) -> Option<&Member> {
+        self.members.iter().find(|member| member.key == *key)
+    }
+}
+
+/// A room message delivered from another chain. The message itself stays in the PostedVAA
+/// account, which members read it from.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct InboxEntry {
+    pub emitter_chain: u16,
+    pub sequence:      u64,
+    pub sender:        [u8; 32],
+    pub vaa:           Pubkey,
+
+    /// The room owner has muted the sender, readers hide these entries by default.
+    pub quarantined: bool,
+}
+
+impl InboxEntry {
+    pub const SIZE: usize = 2 + 8 + 32 + 32 + 1;
+}
+
+/// The last `INBOX_LEN` messages received for a room, stored in a PDA derived from
+/// `[b"inbox", id]`. Entries are kept in a ring, `count` being the number ever delivered.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Inbox {
+    pub room:    u64,
+    pub count:   u64,
+    pub entries: Vec<InboxEntry>,
+}
+
+impl Inbox {
+    pub const SIZE: usize = 8 + 8 + 4 + INBOX_LEN * InboxEntry::SIZE;
+
+    /// Deliver a message, overwriting the oldest entry once the inbox is full.
+    pub fn push(&mut self, entry: InboxEntry) {
+        if self.entries.len() < INBOX_LEN {
+            self.entries.push(entry);
+        } else {
+            self.entries[(self.count % INBOX_LEN as u64) as usize] = entry;
+        }
+        self.count += 1;
+    }
+
+    /// Entries from oldest to newest.
+    pub fn messages(&self) -> Vec<InboxEntry> {
+        let start = if self.entries.len() < INBOX_LEN {
+            0
+        } else {
+            (self.count % INBOX_LEN as u64) as usize
+        };
+        let mut entries = self.entries[start..].to_vec();
+        entries.extend_from_slice(&self.entries[..start]);
+        entries
+    }
+}
+
+
+/// A directory entry binding a nickname to its owner, stored in a PDA derived from
+/// `[b"nick", nick]`, see `directory`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Nick {
+    pub nick:  String,
+    pub claim: Claim,
+}
+
+impl Nick {
+    pub const SIZE: usize = 4 + messenger_common::MAX_NICK_LEN + 2 + 32 + 8;
+}
+
+
+/// What happens to messages from a sender a recipient has listed, see `blocks`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BlockMode {
+    /// Drop the sender's messages.
+    Block,
+
+    /// Deliver the sender's messages, but quarantined.
+    Mute,
+}
+
+/// A recipient's entry for `sender` on `chain`, stored in a PDA derived from
+/// `[b"block", owner, chain, sender]`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Block {
+    pub owner:  Pubkey,
+    pub chain:  u16,
+    pub sender: [u8; 32],
+    pub mode:   BlockMode,
+}
+
+impl Block {
+    pub const SIZE: usize = 32 + 2 + 32 + 1;
+}
+
+
+/// When a scheduled message falls due, by slot or by unix time.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Due {
+    Slot(u64),
+    Time(i64),
+}
+
+impl Due {
+    /// Whether the message is due at `clock`.
+    pub fn passed(&self, clock: &Clock) -> bool {
+        match *self {
+            Due::Slot(slot) => clock.slot >= slot,
+            Due::Time(time) => clock.unix_timestamp >= time,
+        }
+    }
+}
+
+/// A message waiting to be posted by a crank, stored in a PDA derived from
+/// `[b"scheduled", sender, id]`, see `schedule`. The account also holds the fees and reward the
+/// crank is paid.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Scheduled {
+    pub sender:       Pubkey,
+    pub id:           u64,
+    pub due:          Due,
+    pub nonce:        u32,
+    pub target_chain: u16,
+    pub message:      Message,
+
+    /// Lamports escrowed for the crank on top of the fees.
+    pub reward: u64,
+}
+
+
+/// Most signers a multisig can have, which bounds the size of its account and lets approvals be
+/// recorded as a bitmap.
+pub const MAX_SIGNERS: usize = 16;
+
+/// An M-of-N multisig, stored in a PDA derived from `[b"multisig", id]`, see `multisig`. The PDA
+/// is the sender of every message its proposals post.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Multisig {
+    pub id:        u64,
+    pub signers:   Vec<Pubkey>,
+    pub threshold: u8,
+
+    /// Proposals created so far, the next proposal takes this as its id.
+    pub proposals: u64,
+}
+
+impl Multisig {
+    pub const SIZE: usize = 8 + 4 + MAX_SIGNERS * 32 + 1 + 8;
+
+    /// Position of `key` among the signers, which is its bit in a proposal's approvals.
+    pub fn signer(&self, key: &Pubkey) -> Option<usize> {
+        self.signers.iter().position(|signer| signer == key)
+    }
+}
+
+/// A message waiting on a multisig's approval, stored in a PDA derived from
+/// `[b"proposal", multisig, id]`. Once approved by the threshold it is posted and the account
+/// closed.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Proposal {
+    pub multisig:     Pubkey,
+    pub id:           u64,
+    pub proposer:     Pubkey,
+    pub nonce:        u32,
+    pub target_chain: u16,
+    pub message:      Message,
+
+    /// Unix time from which the proposal can no longer be approved.
+    pub expiry: i64,
+
+    /// Bitmap of the signers who have approved, indexed as in `Multisig::signers`.
+    pub approvals: u16,
+}
+
+impl Proposal {
+    /// Number of signers who have approved.
+    pub fn votes(&self) -> u8 {
+        self.approvals.count_ones() as u8
+    }
+}
+
+
+/// Longest channel name, as PDA seeds are limited to 32 bytes.
+pub const MAX_CHANNEL_LEN: usize = 32;
+
+/// Check a channel name can be used to derive an emitter.
+pub fn check_channel(channel: &str) -> ProgramResult {
+    if channel.is_empty() || channel.len() > MAX_CHANNEL_LEN {
+        return Err(MessengerError::InvalidChannel.into());
+    }
+    Ok(())
+}
+
+
+/// Derive the address of the config account.
+pub fn config(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config"], program_id)
+}
+
+/// Derive the address of the fee vault. The vault is a system owned account with no data that
+/// only this program can sign for.
+pub fn fee_vault(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"fee_vault"], program_id)
+}
+
+/// Derive the address of the treasury collecting protocol fees. Like the fee vault it is a system
+/// owned account with no data.
+pub fn treasury(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"treasury"], program_id)
+}
+
+/// Derive the address of a sponsor's balance account.
+pub fn sponsor(program_id: &Pubkey, sponsor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"sponsor", sponsor.as_ref()], program_id)
+}
+
+/// Derive the address of the account holding `sponsor`'s grant to `user`.
+pub fn sponsorship(program_id: &Pubkey, sponsor: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"sponsorship", sponsor.as_ref(), user.as_ref()], program_id)
+}
+
+
+/// Derive the address of a sender's state account.
+pub fn sender(program_id: &Pubkey, sender: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"sender", sender.as_ref()], program_id)
+}
+
+/// Derive the address of the outbound cap for messages to `chain`.
+pub fn destination(program_id: &Pubkey, chain: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"destination", &chain.to_be_bytes()], program_id)
+}
+
+/// Derive the sender PDA another program signs with when sending through us by CPI. This is
+/// derived under the calling program's id, not ours, so only that program can sign for it.
+pub fn program_sender(caller: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"messenger_sender"], caller)
+}
+
+/// Derive the emitter messages on `channel` are posted from. Each channel has its own emitter and
+/// so its own Wormhole sequence, which lets receivers allowlist channels individually.
+pub fn channel_emitter(program_id: &Pubkey, channel: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"channel", channel.as_bytes()], program_id)
+}
+
+/// The emitter for a message on `channel`, or the program's default emitter from
+/// `wormhole_sdk::emitter` when there is no channel.
+pub fn emitter(program_id: &Pubkey, channel: Option<&str>) -> Pubkey {
+    match channel {
+        Some(channel) => channel_emitter(program_id, channel).0,
+        None => wormhole_sdk::emitter(program_id).0,
+    }
+}
+
+/// Derive the address of room `id`.
+pub fn room(program_id: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"room", &id.to_be_bytes()], program_id)
+}
+
+/// Derive the address of room `id`'s inbox.
+pub fn inbox(program_id: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"inbox", &id.to_be_bytes()], program_id)
+}
+
+/// Derive the address of the directory entry for `nick`.
+pub fn nick(program_id: &Pubkey, nick: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"nick", nick.as_bytes()], program_id)
+}
+
+/// Derive the address of `owner`'s block list entry for `sender` on `chain`.
+pub fn block(program_id: &Pubkey, owner: &Pubkey, chain: u16, sender: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"block", owner.as_ref(), &chain.to_be_bytes(), sender],
+        program_id,
+    )
+}
+
+/// Derive the address of `sender`'s scheduled message `id`.
+pub fn scheduled(program_id: &Pubkey, sender: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"scheduled", sender.as_ref(), &id.to_be_bytes()], program_id)
+}
+
+/// Derive the address of multisig `id`, which is also the sender its messages are posted as.
+pub fn multisig(program_id: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"multisig", &id.to_be_bytes()], program_id)
+}
+
+/// Derive the address of `multisig`'s proposal `id`.
+pub fn proposal(program_id: &Pubkey, multisig: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"proposal", multisig.as_ref(), &id.to_be_bytes()], program_id)
+}
+
+/// Derive the address of the Wormhole message account for the `count`th message from `sender`.
+pub fn message(program_id: &Pubkey, sender: &Pubkey, count: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"message", sender.as_ref(), &count.to_be_bytes()], program_id)
+}
+
+
+/// Derive the address of the route allowing `emitter` on `chain` to reach `program`.
+pub fn route(program_id: &Pubkey, program: &Pubkey, chain: u16, emitter: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"route", program.as_ref(), &chain.to_be_bytes(), emitter],
+        program_id,
+    )
+}
+
+/// Derive the address of the receive policy for `emitter` on `chain`.
+pub fn emitter_policy(program_id: &Pubkey, chain: u16, emitter: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"policy", &chain.to_be_bytes(), emitter], program_id)
+}
+
+/// Derive the address of the receipt marking a routed VAA as delivered.
+pub fn receipt(program_id: &Pubkey, vaa: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"receipt", vaa.as_ref()], program_id)
+}
+
+/// Derive the address of the receipt marking message `sequence` from `emitter` on `chain` as
+/// delivered into a room's inbox.
+pub fn delivery(program_id: &Pubkey, chain: u16, emitter: &[u8; 32], sequence: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"delivery", &chain.to_be_bytes(), emitter, &sequence.to_be_bytes()],
+        program_id,
+    )
+}
+
+/// Derive the PDA governance upgrades are signed with. The program's upgrade authority must be
+/// set to this for `governance::upgrade` to work.
+pub fn upgrade_authority(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"upgrade"], program_id)
+}
+
+/// Derive the upgradeable loader's program data account for the program, which records its
+/// upgrade authority.
+pub fn program_data(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+/// Derive the PDA the Messenger signs callbacks with. Targets check for this signer to know a
+/// callback came from the Messenger.
+pub fn callback_authority(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"callback"], program_id)
+}
+
+
+/// Check an account is the PDA we expect, so callers can't substitute their own accounts.
+pub fn check_address(account: &AccountInfo, expected: &Pubkey) -> ProgramResult {
+    if account.key != expected {
+        return Err(MessengerError::InvalidAccountAddress.into());
+    }
+    Ok(())
+}
+
+/// Read a program owned account. Accounts are allocated with room to spare so trailing bytes are
+/// ignored rather than treated as an error.
+pub fn load<T: Versioned>(program_id: &Pubkey, account: &AccountInfo) -> Result<T, ProgramError> {
+    if account.owner != program_id {
+        return Err(MessengerError::InvalidAccountOwner.into());
+    }
+    decode(&account.data.borrow())
+}
+
+/// Decode account data behind its header, upgrading it if it was written in an older layout.
+/// Clients reading accounts over RPC should use this too.
+pub fn decode<T: Versioned>(data: &[u8]) -> Result<T, ProgramError> {
+    let mut data = data;
+    let header = Header::deserialize(&mut data)?;
+    if header.kind != T::KIND {
+        return Err(MessengerError::InvalidAccountKind.into());
+    }
+    match header.version.cmp(&T::VERSION) {
+        Ordering::Equal => Ok(T::deserialize(&mut data)?),
+        Ordering::Less => T::upgrade(header.version, data),
+        Ordering::Greater => Err(MessengerError::UnsupportedVersion.into()),
+    }
+}
+
+/// Write a program owned account in the current layout.
+pub fn save<T: Versioned>(account: &AccountInfo, data: &T) -> ProgramResult {
+    let header = Header {
+        kind:    T::KIND,
+        version: T::VERSION,
+    };
+    let mut bytes = account.data.borrow_mut();
+    let buffer = &mut &mut bytes[..];
+    header.serialize(buffer)?;
+    data.serialize(buffer)?;
+    Ok(())
+}
+
+/// Close a program owned account, moving its lamports to `recipient`. The runtime reclaims
+/// accounts left without lamports at the end of the transaction.
+pub fn close(account: &AccountInfo, recipient: &AccountInfo) {
+    let lamports = **account.lamports.borrow();
+    **account.lamports.borrow_mut() = 0;
+    **recipient.lamports.borrow_mut() += lamports;
+    account.data.borrow_mut().fill(0);
+}
+
+/// Create a rent exempt PDA owned by this program, with room for a `Header`, `size` bytes of data
+/// and `RESERVED` bytes more. Markers such as receipts, which hold no data, are created empty.
+/// `seeds` must include the bump.
+pub fn create<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system: &AccountInfo<'a>,
+    seeds: &[&[u8]],
+    size: usize,
+) -> ProgramResult {
+    let size = if size == 0 { 0 } else { Header::SIZE + size + RESERVED };
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            Rent::get()?.minimum_balance(size),
+            size as u64,
+            program_id,
+        ),
+        &[payer.clone(), account.clone(), system.clone()],
+        &[seeds],
+    )
+}
+
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    /// Sender as a later version of the program might lay it out, with a field appended.
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+    struct SenderV2 {
+        sender:    Sender,
+        last_sent: i64,
+    }
+
+    impl Versioned for SenderV2 {
+        const KIND: AccountKind = AccountKind::Sender;
+        const VERSION: u8 = 2;
+
+        fn upgrade(version: u8, data: &[u8]) -> Result<Self, ProgramError> {
+            match version {
+                1 => Ok(SenderV2 {
+                    sender:    Sender::deserialize(&mut &data[..])?,
+                    last_sent: 0,
+                }),
+                _ => Err(MessengerError::UnsupportedVersion.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let id = Pubkey::new_from_array([2; 32]);
+        let key = Pubkey::new_from_array([3; 32]);
+        let mut lamports = 0;
+
+        // Allocated as `create` allocates a v1 Sender.
+        let mut data = vec![0; Header::SIZE + Sender::SIZE + RESERVED];
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &id, false, 0);
+        let v1 = Sender {
+            count: 7,
+            ..Sender::default()
+        };
+        save(&account, &v1).unwrap();
+
+        // v2 code reads the v1 account, and saving rewrites it as v2 in the reserved bytes.
+        let v2: SenderV2 = load(&id, &account).unwrap();
+        assert_eq!(v2, SenderV2 { sender: v1, last_sent: 0 });
+        save(&account, &v2).unwrap();
+        assert_eq!(account.data.borrow()[..Header::SIZE], [AccountKind::Sender as u8, 2]);
+        assert_eq!(load::<SenderV2>(&id, &account).unwrap(), v2);
+
+        // v1 code can't read the newer layout, and no layout reads another kind of account.
+        assert_eq!(load::<Sender>(&id, &account), Err(MessengerError::UnsupportedVersion.into()));
+        assert_eq!(load::<Nick>(&id, &account), Err(MessengerError::InvalidAccountKind.into()));
+    }
+}
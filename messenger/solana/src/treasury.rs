@@ -0,0 +1,70 @@
+//! Protocol fees. Every message pays `Config::protocol_fee` lamports into a program controlled
+//! treasury on top of the bridge fee, to cover the cost of running relayers. The admin sets the
+//! fee and withdraws from the treasury.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::{
+    invoke,
+    invoke_signed,
+};
+use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction::transfer;
+
+use crate::bridge;
+use crate::state::{
+    self,
+    Config,
+};
+
+
+/// Set the protocol fee charged per message. Admin only.
+pub fn set_protocol_fee(id: &Pubkey, accs: &[AccountInfo], fee: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let admin    = next_account_info(accounts)?;
+    let config   = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let mut data: Config = state::load(id, config)?;
+    data.check_admin(admin)?;
+
+    data.protocol_fee = fee;
+    state::save(config, &data)
+}
+
+/// Move collected fees from the treasury to `recipient`. Admin only.
+pub fn withdraw(id: &Pubkey, accs: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts  = &mut accs.iter();
+    let admin     = next_account_info(accounts)?;
+    let config    = next_account_info(accounts)?;
+    let treasury  = next_account_info(accounts)?;
+    let recipient = next_account_info(accounts)?;
+    let system    = next_account_info(accounts)?;
+
+    state::check_address(config, &state::config(id).0)?;
+    let config: Config = state::load(id, config)?;
+    config.check_admin(admin)?;
+
+    let (key, bump) = state::treasury(id);
+    state::check_address(treasury, &key)?;
+
+    invoke_signed(
+        &transfer(treasury.key, recipient.key, amount),
+        &[treasury.clone(), recipient.clone(), system.clone()],
+        &[&[b"treasury", &[bump]]],
+    )
+}
+
+/// Charge `payer` the protocol fee for one message. The treasury is located by key, and is only
+/// needed while the fee is non-zero.
+pub fn collect(id: &Pubkey, config: &Config, payer: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    if config.protocol_fee == 0 {
+        return Ok(());
+    }
+
+    let treasury = bridge::find(accounts, &state::treasury(id).0)?;
+    invoke(&transfer(payer, treasury.key, config.protocol_fee), accounts)
+}
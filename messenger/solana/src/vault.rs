@@ -0,0 +1,158 @@
+//! Sponsored fees. Sponsors deposit lamports into a program controlled vault and grant users a
+//! quota of messages. When a sponsored user sends a message the bridge fee is paid out of the
+//! vault rather than by the user.
+
+use solana_program::account_info::{
+    next_account_info,
+    AccountInfo,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::{
+    invoke,
+    invoke_signed,
+};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction::transfer;
+
+use crate::bridge;
+use crate::error::MessengerError;
+use crate::state::{
+    self,
+    Config,
+    Sponsor,
+    Sponsorship,
+};
+
+
+/// Move lamports from a sponsor into the vault, creating their balance account on first deposit.
+pub fn deposit(id: &Pubkey, accs: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let sponsor  = next_account_info(accounts)?;
+    let balance  = next_account_info(accounts)?;
+    let vault    = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    let (key, bump) = state::sponsor(id, sponsor.key);
+    state::check_address(balance, &key)?;
+    state::check_address(vault, &state::fee_vault(id).0)?;
+
+    let mut data = if **balance.lamports.borrow() == 0 {
+        let seeds: &[&[u8]] = &[b"sponsor", sponsor.key.as_ref(), &[bump]];
+        state::create(id, sponsor, balance, system, seeds, Sponsor::SIZE)?;
+        Sponsor {
+            sponsor: *sponsor.key,
+            balance: 0,
+        }
+    } else {
+        state::load(id, balance)?
+    };
+
+    invoke(
+        &transfer(sponsor.key, vault.key, amount),
+        &[sponsor.clone(), vault.clone(), system.clone()],
+    )?;
+
+    data.balance = data.balance.checked_add(amount).ok_or(ProgramError::InvalidArgument)?;
+    state::save(balance, &data)
+}
+
+/// Return unspent lamports from the vault to a sponsor.
+pub fn withdraw(id: &Pubkey, accs: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts = &mut accs.iter();
+    let sponsor  = next_account_info(accounts)?;
+    let balance  = next_account_info(accounts)?;
+    let vault    = next_account_info(accounts)?;
+    let system   = next_account_info(accounts)?;
+
+    if !sponsor.is_signer {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    let (vault_key, vault_bump) = state::fee_vault(id);
+    state::check_address(balance, &state::sponsor(id, sponsor.key).0)?;
+    state::check_address(vault, &vault_key)?;
+
+    let mut data: Sponsor = state::load(id, balance)?;
+    data.balance = data
+        .balance
+        .checked_sub(amount)
+        .ok_or(MessengerError::InsufficientSponsorFunds)?;
+
+    invoke_signed(
+        &transfer(vault.key, sponsor.key, amount),
+        &[vault.clone(), sponsor.clone(), system.clone()],
+        &[&[b"fee_vault", &[vault_bump]]],
+    )?;
+
+    state::save(balance, &data)
+}
+
+/// Grant, update or revoke (with a quota of 0) the signer's sponsorship of a user. Each sponsor's
+/// grant is its own account, so one sponsor can't take over or block another's.
+pub fn sponsor(id: &Pubkey, accs: &[AccountInfo], user: Pubkey, quota: u64) -> ProgramResult {
+    let accounts    = &mut accs.iter();
+    let sponsor     = next_account_info(accounts)?;
+    let sponsorship = next_account_info(accounts)?;
+    let system      = next_account_info(accounts)?;
+
+    if !sponsor.is_signer {
+        return Err(MessengerError::Unauthorized.into());
+    }
+
+    let (key, bump) = state::sponsorship(id, sponsor.key, &user);
+    state::check_address(sponsorship, &key)?;
+
+    if **sponsorship.lamports.borrow() == 0 {
+        let seeds: &[&[u8]] = &[b"sponsorship", sponsor.key.as_ref(), user.as_ref(), &[bump]];
+        state::create(id, sponsor, sponsorship, system, seeds, Sponsorship::SIZE)?;
+    }
+
+    state::save(
+        sponsorship,
+        &Sponsorship {
+            sponsor:   *sponsor.key,
+            remaining: quota,
+        },
+    )
+}
+
+/// Pay the bridge fee for `payer` out of the vault if one of their sponsorships passed in
+/// `accounts` has quota left and its sponsor can cover the fee. Returns `false` without paying
+/// anything otherwise, in which case the caller should pay the fee itself.
+///
+/// A sponsorship is recognised by its address, derived from the sponsor it names and the payer,
+/// and the sponsor balance and vault accounts are located by key, so instructions for unsponsored
+/// users don't need to include them at all.
+pub fn pay_fee(id: &Pubkey, config: &Config, payer: &Pubkey, accounts: &[AccountInfo]) -> Result<bool, ProgramError> {
+    let found = accounts.iter().find_map(|account| {
+        let grant: Sponsorship = state::load(id, account).ok()?;
+        let key = state::sponsorship(id, &grant.sponsor, payer).0;
+        (*account.key == key && grant.remaining != 0).then(|| (account, grant))
+    });
+
+    let (sponsorship, mut grant) = match found {
+        Some(found) => found,
+        None => return Ok(false),
+    };
+
+    let balance = bridge::find(accounts, &state::sponsor(id, &grant.sponsor).0)?;
+    let mut funds: Sponsor = state::load(id, balance)?;
+    let fee = bridge::fee(config, accounts)?;
+    if funds.balance < fee {
+        return Ok(false);
+    }
+
+    let (vault, vault_bump) = state::fee_vault(id);
+    invoke_signed(
+        &transfer(&vault, &wormhole_sdk::fee_collector(&config.wormhole), fee),
+        accounts,
+        &[&[b"fee_vault", &[vault_bump]]],
+    )?;
+
+    funds.balance -= fee;
+    grant.remaining -= 1;
+    state::save(balance, &funds)?;
+    state::save(sponsorship, &grant)?;
+    Ok(true)
+}
@@ -0,0 +1,323 @@
+//! Programs sending messages through the Messenger by CPI, and receiving routed messages. The
+//! `cpi` helpers are only built for dependents, so these tests need the feature they use:
+//!
+//! ```
+//! $ cargo test --features no-entrypoint --test test_cpi
+//! ```
+//!
+//! See `test_messages.rs` for how to get the `bridge.so` these tests need.
+
+#![cfg(feature = "no-entrypoint")]
+
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::instruction::AccountMeta;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{
+    processor,
+    tokio,
+    ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use wormhole_sdk::bridge_entrypoint;
+use wormhole_sdk::MessageData;
+
+use messenger::cpi;
+use messenger::instruction::{
+    initialize,
+    recv_routed,
+    register_route,
+    send_message,
+    send_program_message,
+};
+use messenger::state::{
+    Consistency,
+    Route,
+    Sender,
+    Versioned,
+};
+use messenger::{
+    Envelope,
+    Message,
+};
+use messenger_test_support::{
+    add_program_data,
+    initialize_bridge,
+    message_vaa,
+    post_vaa,
+    process,
+    Guardians,
+};
+
+
+const ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
+
+/// A second program that sends messages through the Messenger by CPI.
+const CALLER: Pubkey = Pubkey::new_from_array([3u8; 32]);
+
+
+/// Start the bridge with `guardians`, an initialised Messenger and the `CALLER` program.
+async fn start(guardians: &Guardians) -> ProgramTestContext {
+    let deployer = Keypair::new();
+    let mut test = ProgramTest::default();
+    test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
+    test.add_program("messenger", ID, processor!(messenger::process_instruction));
+    test.add_program("caller", CALLER, processor!(caller_entrypoint));
+    add_program_data(&mut test, ID, deployer.pubkey());
+    let mut context = test.start_with_context().await;
+
+    initialize_bridge(&mut context, guardians, 50, 2_000_000_000).await.unwrap();
+    let payer = context.payer.pubkey();
+    let messenger = initialize(
+        ID,
+        payer,
+        deployer.pubkey(),
+        wormhole_sdk::id(),
+        Consistency::Finalized,
+        vec![Consistency::Finalized],
+    );
+    process(&mut context, &[messenger], &[&deployer]).await.unwrap();
+    context
+}
+
+/// Fetch and decode a Messenger owned account.
+async fn read_account<T: Versioned>(context: &mut ProgramTestContext, key: Pubkey) -> T {
+    let account = context.banks_client.get_account(key).await.unwrap().unwrap();
+    messenger::state::decode(&account.data).unwrap()
+}
+
+/// Emulate the guardians observing a message, signing it, and posting the VAA.
+async fn simulate_guardians(context: &mut ProgramTestContext, guardians: &Guardians, message: &MessageData) -> Pubkey {
+    let mut vaa = message_vaa(message);
+    guardians.sign(&mut vaa);
+    post_vaa(context, guardians, &vaa).await.unwrap()
+}
+
+
+#[tokio::test]
+pub async fn test_program_message() {
+    let mut context = start(&Guardians::new(0, 1)).await;
+
+    // The caller receives the Messenger program followed by the SendProgramMessage accounts. Its
+    // sender PDA can't sign the outer transaction, the caller signs for it when it invokes us.
+    let mut accounts = vec![AccountMeta::new_readonly(ID, false)];
+    accounts.extend(
+        send_program_message(
+            ID,
+            wormhole_sdk::id(),
+            context.payer.pubkey(),
+            CALLER,
+            0,
+            Message {
+                nick: "Caller".to_string(),
+                text: String::new(),
+            },
+            0,
+            None,
+            0,
+        )
+        .accounts
+        .into_iter()
+        .map(|mut account| {
+            account.is_signer &= account.pubkey == context.payer.pubkey();
+            account
+        }),
+    );
+
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[solana_program::instruction::Instruction {
+                program_id: CALLER,
+                accounts,
+                data: vec![],
+            }],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    // The message is stored under the caller's sender, and the envelope names the caller.
+    let message = messenger::state::message(&ID, &CALLER, 0).0;
+    let message = MessageData::try_from_slice(
+        &context
+            .banks_client
+            .get_account(message)
+            .await
+            .unwrap()
+            .unwrap()
+            .data[3..]
+    ).unwrap();
+
+    let envelope = Envelope::try_from_slice(&message.payload).unwrap();
+    assert_eq!(envelope.sender, CALLER.to_bytes());
+    assert_eq!(envelope.message.nick, "Caller");
+
+    let sender = read_account::<Sender>(&mut context, messenger::state::sender(&ID, &CALLER).0).await;
+    assert_eq!(sender.count, 1);
+
+    // Without the caller's signature the Messenger refuses to send on its behalf.
+    let mut forged = send_program_message(
+        ID,
+        wormhole_sdk::id(),
+        context.payer.pubkey(),
+        CALLER,
+        1,
+        Message {
+            nick: "Mallory".to_string(),
+            text: String::new(),
+        },
+        0,
+        None,
+        0,
+    );
+    forged.accounts[4].is_signer = false;
+    assert!(context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[forged],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+pub async fn test_routed_message() {
+    let guardians = Guardians::new(0, 1);
+    let mut context = start(&guardians).await;
+    let emitter = wormhole_sdk::emitter(&ID).0.to_bytes();
+
+    // Post an ordinary message, then rewrite its payload as though it was targeted at the caller
+    // before handing it to the guardians.
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[send_message(
+                ID,
+                wormhole_sdk::id(),
+                context.payer.pubkey(),
+                0,
+                Message {
+                    nick: "Alice".to_string(),
+                    text: "Ping".to_string(),
+                },
+                0,
+                None,
+                0,
+                None,
+            )],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let message = messenger::state::message(&ID, &context.payer.pubkey(), 0).0;
+    let mut message = MessageData::try_from_slice(
+        &context
+            .banks_client
+            .get_account(message)
+            .await
+            .unwrap()
+            .unwrap()
+            .data[3..]
+    ).unwrap();
+
+    let mut envelope = Envelope::try_from_slice(&message.payload).unwrap();
+    envelope.target = Some(CALLER.to_bytes());
+    message.payload = envelope.try_to_vec().unwrap();
+    let vaa = simulate_guardians(&mut context, &guardians, &message).await;
+
+    let messenger = messenger_client::Messenger::new(ID, wormhole_sdk::id());
+    let (signature_set, _) = messenger.read_signature_set(&mut context.banks_client, &vaa).await.unwrap();
+    let guardian_set = messenger.guardian_set(0);
+    let recv = recv_routed(
+        ID,
+        context.payer.pubkey(),
+        vaa,
+        signature_set,
+        guardian_set,
+        CALLER,
+        1,
+        emitter,
+        vec![],
+    );
+
+    // The caller hasn't registered our emitter, so the message can't be routed to it.
+    assert!(context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[recv.clone()],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .is_err());
+
+    // Once registered the callback is delivered, and the caller checks it came from us.
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[register_route(ID, context.payer.pubkey(), CALLER, 1, emitter), recv.clone()],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let route = messenger::state::route(&ID, &CALLER, 1, &emitter).0;
+    let route = read_account::<Route>(&mut context, route).await;
+    assert_eq!(route.program, CALLER);
+
+    // The same VAA can't be delivered twice. Registering again is a no-op, it's only here so the
+    // transaction differs from the ones above.
+    assert!(context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[recv, register_route(ID, context.payer.pubkey(), CALLER, 1, emitter)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .is_err());
+}
+
+/// Entrypoint for the `CALLER` test program. Empty instructions send a fixed message through the
+/// Messenger, callbacks are accepted only if they carry the expected text.
+fn caller_entrypoint(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if let Some(callback) = cpi::parse_callback(&ID, accounts, data)? {
+        if callback.message.text != "Ping" {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        return Ok(());
+    }
+
+    let accounts = cpi::SendMessageAccounts::new(*program_id, accounts)?;
+    cpi::send_message(
+        &accounts,
+        Message {
+            nick: "Caller".to_string(),
+            text: "Hello from a program!".to_string(),
+        },
+        0,
+        None,
+        0,
+    )
+}
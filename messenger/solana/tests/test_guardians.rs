@@ -0,0 +1,494 @@
+//! Receive messages signed by larger guardian sets, across guardian set upgrades, and check
+//! badly signed VAAs never reach the Messenger, nor do VAAs failing the receive policy. See
+//! `test_messages.rs` for how to get the `bridge.so` these tests need.
+
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{
+    processor,
+    tokio,
+    ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::TransactionError;
+use solana_sdk::transport::TransportError;
+
+use messenger::error::MessengerError;
+use messenger::instruction::{
+    initialize,
+    register_route,
+    set_block,
+    set_emitter_policy,
+    set_governance,
+    upgrade_contract,
+};
+use messenger::state::{
+    BlockMode,
+    Consistency,
+    Nick,
+};
+use messenger::{
+    ContractUpgrade,
+    Envelope,
+    Message,
+    Registration,
+};
+use messenger_client::{
+    Messenger,
+    SignedVaa,
+};
+use messenger_test_support::{
+    add_guardian_set,
+    add_posted_vaa,
+    add_program_data,
+    initialize_bridge,
+    post_vaa,
+    process,
+    recv_instruction,
+    upgrade_guardian_set,
+    Fault,
+    Guardians,
+    CHAIN_ID_SOLANA,
+};
+use wormhole_sdk::bridge_entrypoint;
+
+
+const ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
+
+
+/// Start the bridge with `guardians` and an initialised Messenger. `test` can be used to add
+/// accounts before the environment starts.
+async fn start(mut test: ProgramTest, guardians: &Guardians) -> ProgramTestContext {
+    let deployer = Keypair::new();
+    test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
+    test.add_program("messenger", ID, processor!(messenger::process_instruction));
+    add_program_data(&mut test, ID, deployer.pubkey());
+    let mut context = test.start_with_context().await;
+
+    initialize_bridge(&mut context, guardians, 50, 2_000_000_000).await.unwrap();
+    let payer = context.payer.pubkey();
+    let messenger = initialize(
+        ID,
+        payer,
+        deployer.pubkey(),
+        wormhole_sdk::id(),
+        Consistency::Finalized,
+        vec![Consistency::Finalized],
+    );
+    process(&mut context, &[messenger], &[&deployer]).await.unwrap();
+    context
+}
+
+/// An unsigned VAA carrying a Messenger payload, sequence numbers keep each one distinct.
+fn vaa(sequence: u64) -> SignedVaa {
+    SignedVaa {
+        version:           1,
+        emitter_chain:     CHAIN_ID_SOLANA,
+        emitter_address:   [7; 32],
+        sequence,
+        consistency_level: 32,
+        payload:           Envelope {
+            sender:       [1; 32],
+            target_chain: 0,
+            target:       None,
+            recipient:    None,
+            room:         None,
+            message:      Message {
+                nick: "Alice".to_string(),
+                text: format!("Message {}", sequence),
+            },
+        }
+        .try_to_vec()
+        .unwrap(),
+        ..Default::default()
+    }
+}
+
+/// As `vaa`, but the message is routed to a target program.
+fn routed(sequence: u64) -> SignedVaa {
+    let mut message = vaa(sequence);
+    let mut envelope = Envelope::try_from_slice(&message.payload).unwrap();
+    envelope.target = Some([9; 32]);
+    message.payload = envelope.try_to_vec().unwrap();
+    message
+}
+
+/// A nickname registration for "alice" made at `time`, on the same emitter as `vaa`.
+fn registration(sequence: u64, owner: u8, time: u32) -> SignedVaa {
+    let mut message = vaa(sequence);
+    message.timestamp = time;
+    message.payload = Registration {
+        owner: [owner; 32],
+        nick:  "alice".to_string(),
+    }
+    .payload();
+    message
+}
+
+/// Post `vaa` and deliver it to the Messenger.
+async fn receive(context: &mut ProgramTestContext, guardians: &Guardians, vaa: &SignedVaa) -> bool {
+    let posted_vaa = match post_vaa(context, guardians, vaa).await {
+        Ok(posted_vaa) => posted_vaa,
+        Err(_) => return false,
+    };
+
+    let recv = recv_instruction(context, ID, posted_vaa).await;
+    process(context, &[recv], &[]).await.is_ok()
+}
+
+/// Post `vaa` and deliver it to the Messenger, returning the Messenger error it was refused with.
+async fn refusal(context: &mut ProgramTestContext, guardians: &Guardians, vaa: &SignedVaa) -> Option<u32> {
+    let posted_vaa = post_vaa(context, guardians, vaa).await.unwrap();
+    let recv = recv_instruction(context, ID, posted_vaa).await;
+    error_code(process(context, &[recv], &[]).await)
+}
+
+/// Post the governance VAA `vaa` and submit it to upgrade the Messenger to `buffer`, returning
+/// the Messenger error it was refused with.
+async fn upgrade_refusal(
+    context: &mut ProgramTestContext,
+    guardians: &Guardians,
+    vaa: &SignedVaa,
+    buffer: Pubkey,
+) -> Option<u32> {
+    let payer = context.payer.pubkey();
+    let messenger = Messenger::new(ID, wormhole_sdk::id());
+    let posted_vaa = post_vaa(context, guardians, vaa).await.unwrap();
+    let posted = messenger.read_posted_vaa_accounts(&mut context.banks_client, &posted_vaa).await.unwrap();
+    let upgrade = upgrade_contract(ID, wormhole_sdk::id(), payer, &posted, buffer);
+    error_code(process(context, &[upgrade], &[]).await)
+}
+
+/// The custom error a transaction failed with, if any.
+fn error_code(result: Result<(), TransportError>) -> Option<u32> {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(_, InstructionError::Custom(code)))) => {
+            Some(code)
+        }
+        _ => None,
+    }
+}
+
+/// A governance VAA from `emitter` upgrading the Messenger on Solana to `buffer`.
+fn governance(sequence: u64, emitter: [u8; 32], buffer: Pubkey) -> SignedVaa {
+    let mut message = vaa(sequence);
+    message.emitter_address = emitter;
+    message.payload = ContractUpgrade {
+        chain:        CHAIN_ID_SOLANA,
+        new_contract: buffer.to_bytes(),
+    }
+    .payload();
+    message
+}
+
+
+#[tokio::test]
+pub async fn test_full_guardian_set() {
+    // 19 guardians need 13 signatures, more than fit in a single verify_signatures transaction.
+    let guardians = Guardians::new(0, 19);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+
+    let mut message = vaa(0);
+    guardians.sign(&mut message);
+    assert_eq!(message.signatures.len(), 13);
+    assert!(receive(&mut context, &guardians, &message).await);
+}
+
+#[tokio::test]
+pub async fn test_faulty_vaas() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+
+    let faults = [
+        Fault::BelowQuorum,
+        Fault::ForeignSigner,
+        Fault::TamperedBody,
+        Fault::DuplicateSigner,
+        Fault::WrongGuardianSet,
+    ];
+
+    for (sequence, fault) in faults.iter().enumerate() {
+        let mut message = vaa(sequence as u64);
+        guardians.sign_faulty(&mut message, *fault);
+        assert!(!receive(&mut context, &guardians, &message).await, "{:?} was accepted", fault);
+    }
+}
+
+#[tokio::test]
+pub async fn test_guardian_set_upgrade() {
+    let current = Guardians::new(0, 4);
+    let next = current.next(7);
+    let mut context = start(ProgramTest::default(), &current).await;
+
+    upgrade_guardian_set(&mut context, &current, &next, 0).await.unwrap();
+
+    // The new set can sign messages, and the old one remains valid until it expires.
+    let mut message = vaa(0);
+    next.sign(&mut message);
+    assert!(receive(&mut context, &next, &message).await);
+
+    let mut message = vaa(1);
+    current.sign(&mut message);
+    assert!(receive(&mut context, &current, &message).await);
+}
+
+#[tokio::test]
+pub async fn test_expired_guardian_set() {
+    let current = Guardians::new(0, 4);
+    let expired = current.next(4);
+
+    let mut test = ProgramTest::default();
+    add_guardian_set(&mut test, &expired, 1);
+    let mut context = start(test, &current).await;
+
+    let mut message = vaa(0);
+    expired.sign(&mut message);
+    assert!(!receive(&mut context, &expired, &message).await);
+}
+
+#[tokio::test]
+pub async fn test_posted_with_expired_guardian_set() {
+    // VAAs posted while their guardian set was valid, one by a set that has since expired.
+    let current = Guardians::new(0, 4);
+    let expired = current.next(4);
+
+    let mut valid = vaa(0);
+    current.sign(&mut valid);
+    let mut stale = vaa(1);
+    expired.sign(&mut stale);
+
+    let mut test = ProgramTest::default();
+    add_guardian_set(&mut test, &expired, 1);
+    let valid = add_posted_vaa(&mut test, &valid);
+    let stale = add_posted_vaa(&mut test, &stale);
+    let mut context = start(test, &current).await;
+
+    let recv = recv_instruction(&mut context, ID, valid).await;
+    process(&mut context, &[recv], &[]).await.unwrap();
+
+    let recv = recv_instruction(&mut context, ID, stale).await;
+    assert!(process(&mut context, &[recv], &[]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_emitter_policy() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+    let payer = context.payer.pubkey();
+
+    // Without a policy any consistency level is accepted.
+    let mut message = vaa(0);
+    message.consistency_level = 1;
+    guardians.sign(&mut message);
+    assert_eq!(refusal(&mut context, &guardians, &message).await, None);
+
+    // Require finalized messages from our emitter.
+    let policy = set_emitter_policy(ID, payer, CHAIN_ID_SOLANA, [7; 32], 32, None);
+    process(&mut context, &[policy], &[]).await.unwrap();
+
+    let mut message = vaa(1);
+    message.consistency_level = 1;
+    guardians.sign(&mut message);
+    let code = MessengerError::InsufficientConsistency as u32;
+    assert_eq!(refusal(&mut context, &guardians, &message).await, Some(code));
+
+    let mut message = vaa(2);
+    guardians.sign(&mut message);
+    assert_eq!(refusal(&mut context, &guardians, &message).await, None);
+
+    // Accept confirmed messages, but only once final if they are routed to a program.
+    let policy = set_emitter_policy(ID, payer, CHAIN_ID_SOLANA, [7; 32], 1, Some(32));
+    process(&mut context, &[policy], &[]).await.unwrap();
+
+    let mut message = vaa(3);
+    message.consistency_level = 1;
+    guardians.sign(&mut message);
+    assert_eq!(refusal(&mut context, &guardians, &message).await, None);
+
+    let mut message = routed(4);
+    message.consistency_level = 1;
+    guardians.sign(&mut message);
+    let code = MessengerError::NotFinal as u32;
+    assert_eq!(refusal(&mut context, &guardians, &message).await, Some(code));
+
+    // Only the admin sets policies.
+    let stranger = solana_sdk::signature::Keypair::new();
+    let forged = set_emitter_policy(ID, stranger.pubkey(), CHAIN_ID_SOLANA, [7; 32], 0, None);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_target_chain() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+
+    // Messages for any chain or for Solana are received, ones meant for Terra are not.
+    let cases = [
+        (0, 0, None),
+        (1, CHAIN_ID_SOLANA, None),
+        (2, 3, Some(MessengerError::WrongTargetChain as u32)),
+    ];
+    for (sequence, chain, code) in cases {
+        let mut message = vaa(sequence);
+        let mut envelope = Envelope::try_from_slice(&message.payload).unwrap();
+        envelope.target_chain = chain;
+        message.payload = envelope.try_to_vec().unwrap();
+        guardians.sign(&mut message);
+        assert_eq!(refusal(&mut context, &guardians, &message).await, code);
+    }
+}
+
+#[tokio::test]
+pub async fn test_direct_messages() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+    let payer = context.payer.pubkey();
+
+    let direct = |sequence| {
+        let mut message = vaa(sequence);
+        let mut envelope = Envelope::try_from_slice(&message.payload).unwrap();
+        envelope.recipient = Some(payer.to_bytes());
+        message.payload = envelope.try_to_vec().unwrap();
+        guardians.sign(&mut message);
+        message
+    };
+
+    // The recipient's block list entry must be passed, whether or not it exists.
+    let message = direct(0);
+    let posted_vaa = post_vaa(&mut context, &guardians, &message).await.unwrap();
+    let mut recv = recv_instruction(&mut context, ID, posted_vaa).await;
+    recv.accounts.pop();
+    assert!(process(&mut context, &[recv], &[]).await.is_err());
+    assert!(receive(&mut context, &guardians, &direct(1)).await);
+
+    // Muted and blocked senders' messages are still received, quarantined or dropped.
+    for (sequence, mode) in vec![(2, BlockMode::Mute), (3, BlockMode::Block)] {
+        let block = set_block(ID, payer, CHAIN_ID_SOLANA, [1; 32], Some(mode));
+        process(&mut context, &[block], &[]).await.unwrap();
+        assert!(receive(&mut context, &guardians, &direct(sequence)).await);
+    }
+
+    // Direct messages can't be batched, as the batch doesn't carry block list entries.
+    let posted_vaa = post_vaa(&mut context, &guardians, &direct(4)).await.unwrap();
+    let messenger = Messenger::new(ID, wormhole_sdk::id());
+    let batch = messenger.recv_batch(&mut context.banks_client, &payer, &[posted_vaa]).await.unwrap();
+    let code = MessengerError::RecipientInBatch as u32;
+    assert_eq!(error_code(process(&mut context, &[batch], &[]).await), Some(code));
+}
+
+#[tokio::test]
+pub async fn test_recv_batch() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+    let payer = context.payer.pubkey();
+
+    // Finalized messages only, so the confirmed one can't be received.
+    let policy = set_emitter_policy(ID, payer, CHAIN_ID_SOLANA, [7; 32], 32, None);
+    process(&mut context, &[policy], &[]).await.unwrap();
+
+    let mut confirmed = vaa(1);
+    confirmed.consistency_level = 1;
+    let mut posted = Vec::new();
+    for mut message in vec![vaa(0), confirmed, routed(2), vaa(3)] {
+        guardians.sign(&mut message);
+        posted.push(post_vaa(&mut context, &guardians, &message).await.unwrap());
+    }
+
+    // The confirmed and routed messages are skipped without failing the rest of the batch.
+    let messenger = Messenger::new(ID, wormhole_sdk::id());
+    let batch = messenger.recv_batch(&mut context.banks_client, &payer, &posted).await.unwrap();
+    process(&mut context, &[batch.clone()], &[]).await.unwrap();
+
+    // Every VAA must come with all of its accounts.
+    let mut truncated = batch;
+    truncated.accounts.pop();
+    assert!(process(&mut context, &[truncated], &[]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_nick_directory() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+    let payer = context.payer.pubkey();
+    let messenger = Messenger::new(ID, wormhole_sdk::id());
+
+    // Registrations are only accepted from other Messengers, registered as routes to ourselves.
+    let mut message = registration(0, 1, 100);
+    guardians.sign(&mut message);
+    let code = MessengerError::UnregisteredEmitter as u32;
+    assert_eq!(refusal(&mut context, &guardians, &message).await, Some(code));
+
+    let route = register_route(ID, payer, ID, CHAIN_ID_SOLANA, [7; 32]);
+    process(&mut context, &[route], &[]).await.unwrap();
+
+    let mut message = registration(1, 1, 100);
+    guardians.sign(&mut message);
+    assert!(receive(&mut context, &guardians, &message).await);
+    let owner = |nick: Option<Nick>| nick.map(|nick| nick.claim.owner);
+    let entry = messenger.read_nick(&mut context.banks_client, "alice").await.unwrap();
+    assert_eq!(owner(entry), Some([1; 32]));
+
+    // A later registration for the same nick is delivered but loses.
+    let mut message = registration(2, 2, 200);
+    guardians.sign(&mut message);
+    assert!(receive(&mut context, &guardians, &message).await);
+    let entry = messenger.read_nick(&mut context.banks_client, "alice").await.unwrap();
+    assert_eq!(owner(entry), Some([1; 32]));
+
+    // One registered earlier wins, however late it arrives.
+    let mut message = registration(3, 3, 50);
+    guardians.sign(&mut message);
+    assert!(receive(&mut context, &guardians, &message).await);
+    let entry = messenger.read_nick(&mut context.banks_client, "alice").await.unwrap();
+    assert_eq!(entry.as_ref().map(|nick| nick.claim.time), Some(50));
+    assert_eq!(owner(entry), Some([3; 32]));
+
+    // Registrations act on the directory, so must be final.
+    let policy = set_emitter_policy(ID, payer, CHAIN_ID_SOLANA, [7; 32], 1, Some(32));
+    process(&mut context, &[policy], &[]).await.unwrap();
+    let mut message = registration(4, 4, 10);
+    message.consistency_level = 1;
+    guardians.sign(&mut message);
+    let code = MessengerError::NotFinal as u32;
+    assert_eq!(refusal(&mut context, &guardians, &message).await, Some(code));
+}
+
+#[tokio::test]
+pub async fn test_governance() {
+    let guardians = Guardians::new(0, 4);
+    let mut context = start(ProgramTest::default(), &guardians).await;
+    let payer = context.payer.pubkey();
+    let buffer = Pubkey::new_unique();
+
+    // Only the admin sets the governance emitter, and only once.
+    let stranger = Keypair::new();
+    let forged = set_governance(ID, stranger.pubkey(), CHAIN_ID_SOLANA, [5; 32]);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+    process(&mut context, &[set_governance(ID, payer, CHAIN_ID_SOLANA, [5; 32])], &[]).await.unwrap();
+    let again = set_governance(ID, payer, CHAIN_ID_SOLANA, [7; 32]);
+    let code = MessengerError::GovernanceAlreadySet as u32;
+    assert_eq!(error_code(process(&mut context, &[again], &[]).await), Some(code));
+
+    // Upgrades signed by any other emitter, or naming another buffer, never reach the loader.
+    let code = MessengerError::InvalidGovernanceVAA as u32;
+    for (sequence, emitter, named) in vec![(0, [7; 32], buffer), (1, [5; 32], Pubkey::new_unique())] {
+        let mut message = governance(sequence, emitter, named);
+        guardians.sign(&mut message);
+        assert_eq!(upgrade_refusal(&mut context, &guardians, &message, buffer).await, Some(code));
+    }
+
+    // Governance must be signed by the current guardian set, not one that has been replaced but
+    // not expired.
+    let next = guardians.next(4);
+    upgrade_guardian_set(&mut context, &guardians, &next, 0).await.unwrap();
+    let mut message = governance(2, [5; 32], buffer);
+    guardians.sign(&mut message);
+    let code = MessengerError::GuardianSetNotCurrent as u32;
+    assert_eq!(upgrade_refusal(&mut context, &guardians, &message, buffer).await, Some(code));
+}
@@ -26,76 +26,125 @@ use solana_program_test::{
     ProgramTestContext,
 };
 use solana_sdk::signature::Keypair;
+use solana_sdk::system_instruction;
 use solana_sdk::signer::Signer;
 use solana_sdk::transaction::Transaction;
-use solana_sdk::secp256k1_instruction::new_secp256k1_instruction;
 
 // Import necessary components from the Messenger Program so we can test them.
+use messenger::Envelope;
 use messenger::Message;
+use messenger::Registration;
 use messenger::process_instruction;
 use messenger::instruction::{
+    approve,
+    cancel_proposal,
+    cancel_scheduled,
+    crank,
+    create_multisig,
+    create_room,
+    deposit,
+    initialize,
+    migrate,
     send_message,
-    recv_message,
+    propose,
+    register_nick,
+    register_route,
+    remove_room_member,
+    schedule_message,
+    send_batch,
     send_message_raw,
+    send_room_message,
+    set_block,
+    set_destination_limit,
+    set_exempt,
+    set_paused,
+    set_protocol_fee,
+    set_rate_limit,
+    set_room_member,
+    sponsor,
+    sponsored,
+    withdraw,
+    withdraw_treasury,
+};
+use messenger::state::{
+    AccountKind,
+    BlockMode,
+    Config,
+    Consistency,
+    Destination,
+    Due,
+    Header,
+    Inbox,
+    Nick,
+    Proposal,
+    RateLimit,
+    Room,
+    Sender,
+    Sponsor,
+    Sponsorship,
+    Versioned,
+    RESERVED,
 };
 
 // We utilise the bridge_endpoint, which is re-exposed by the SDK, to run instructions against
 // within the Solana program test framework.
-use wormhole_sdk::Chain;
 use wormhole_sdk::MessageData;
-use wormhole_sdk::VAA;
-use wormhole_sdk::PostVAAData;
-use wormhole_sdk::VerifySignaturesData;
 use wormhole_sdk::bridge_entrypoint;
 
-// Borsh to deserialise the Wormhole Message account for asserting data.
-use borsh::BorshDeserialize;
+// Simulated guardians, signing and posting VAAs to the bridge for us.
+use messenger_test_support::{
+    add_program_data,
+    initialize_bridge,
+    message_vaa,
+    post_vaa,
+    process,
+    recv_instruction,
+    Guardians,
+};
 
-// Secp256k1 so we can produce a Guardian secret key to test with.
-use secp256k1::SecretKey;
+// Borsh to deserialise the Wormhole Message account for asserting data.
+use borsh::{
+    BorshDeserialize,
+    BorshSerialize,
+};
 
 
 /// We need an address to deploy our Messenger program at, we just hardcode one for use throughout
 /// the tests.
 const ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
 
+/// Secret key of the single guardian the bridge is initialised with.
+const GUARDIAN: &str = "ff2f9d893e5c12618c442b34a98cfa3f646c402bf5e2a180ce761a7d8a43d452";
+
 
 #[tokio::test]
 pub async fn test_publish_message() {
-    // Guardian
-    let guardian = hex::decode("B7f0900393F869eE15E00e01Dc71E7ba8590E51f").unwrap();
-    let guardian = &guardian.try_into().unwrap();
-
     // Initialize Test Environment with instruction processors. This lets us load the wormhole
     // processor into scope so we can inspect whether the messages it emits are in fact as we
     // expect them to be.
+    let deployer = Keypair::new();
     let mut context = {
         let mut test = ProgramTest::default();
         test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
         test.add_program("messenger", ID, processor!(process_instruction));
+        add_program_data(&mut test, ID, deployer.pubkey());
         test.start_with_context().await
     };
 
     // Initialize Wormhole
-    context
-        .banks_client
-        .process_transaction(Transaction::new_signed_with_payer(
-            &[wormhole_sdk::instructions::initialize(
-                wormhole_sdk::id(),
-                context.payer.pubkey(),
-                50,
-                2_000_000_000,
-                &[*guardian],
-            ).unwrap()],
-            Some(&context.payer.pubkey()),
-            &[&context.payer],
-            context.last_blockhash,
-        ))
-        .await
-        .unwrap();
+    initialize_bridge(&mut context, &guardians(), 50, 2_000_000_000).await.unwrap();
+
+    // Initialize Messenger, allowing senders to pick either consistency level.
+    initialize_messenger(
+        &mut context,
+        &deployer,
+        Consistency::Finalized,
+        vec![Consistency::Confirmed, Consistency::Finalized],
+    )
+    .await;
 
-    // Message & Emitter Account Keys
-    let message = Keypair::new();
+    // Message & Emitter Account Keys, the payer has not sent anything yet so this is message 0.
+    let message = messenger::state::message(&ID, &context.payer.pubkey(), 0).0;
     let emitter = wormhole_sdk::emitter(&ID);
 
     // Submit a cross-chain message via Wormhole.
@@ -104,17 +153,20 @@ pub async fn test_publish_message() {
         .process_transaction(Transaction::new_signed_with_payer(
             &[send_message(
                 ID,
+                wormhole_sdk::id(),
                 context.payer.pubkey(),
-                emitter.0,
-                message.pubkey(),
+                0,
                 Message {
                     nick: "Alice".to_string(),
                     text: "Hello from Bob!".to_string(),
                 },
                 0,
+                None,
+                0,
+                None,
             )],
             Some(&context.payer.pubkey()),
-            &[&context.payer, &message],
+            &[&context.payer],
             context.last_blockhash,
         ))
         .await
@@ -125,7 +177,7 @@ pub async fn test_publish_message() {
     let message = MessageData::try_from_slice(
         &context
             .banks_client
-            .get_account(message.pubkey())
+            .get_account(message)
             .await
             .unwrap()
             .unwrap()
@@ -136,13 +188,24 @@ pub async fn test_publish_message() {
     assert_eq!(message.consistency_level, 32);
     assert_eq!(message.vaa_time, 0);
     assert_eq!(message.nonce, 0);
+
+    // The payer's next message will go to the next PDA along.
+    let sender = read_account::<Sender>(&mut context, messenger::state::sender(&ID, &context.payer.pubkey()).0).await;
+    assert_eq!(sender.count, 1);
     assert_eq!(message.emitter_chain, 1);
     assert_eq!(message.emitter_address, emitter.0.to_bytes());
     assert_eq!(
-        Message::try_from_slice(&message.payload).unwrap(),
-        Message {
-            nick: "Alice".to_string(),
-            text: "Hello from Bob!".to_string(),
+        Envelope::try_from_slice(&message.payload).unwrap(),
+        Envelope {
+            sender:       context.payer.pubkey().to_bytes(),
+            target_chain: 0,
+            target:       None,
+            recipient:    None,
+            room:         None,
+            message:      Message {
+                nick: "Alice".to_string(),
+                text: "Hello from Bob!".to_string(),
+            },
         }
     );
 
@@ -150,14 +213,11 @@ pub async fn test_publish_message() {
     let vaa = simulate_guardians(&mut context, &message).await;
 
     // We can now test the recv_message endpoint by submitting the signed VAA.
+    let recv = recv_instruction(&mut context, ID, vaa).await;
     context
         .banks_client
         .process_transaction(Transaction::new_signed_with_payer(
-            &[recv_message(
-                ID,
-                context.payer.pubkey(),
-                vaa,
-            )],
+            &[recv],
             Some(&context.payer.pubkey()),
             &[&context.payer],
             context.last_blockhash,
@@ -168,40 +228,32 @@ pub async fn test_publish_message() {
 
 #[tokio::test]
 pub async fn test_publish_message_raw() {
-    // Guardian
-    let guardian = hex::decode("966745cb54d907a93272dd154e1d1bb94b38c69b").unwrap();
-    let guardian = &guardian.try_into().unwrap();
-
     // Initialize Test Environment with instruction processors. This lets us load the wormhole
     // processor into scope so we can inspect whether the messages it emits are in fact as we
     // expect them to be.
+    let deployer = Keypair::new();
     let mut context = {
         let mut test = ProgramTest::default();
         test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
         test.add_program("messenger", ID, processor!(process_instruction));
+        add_program_data(&mut test, ID, deployer.pubkey());
         test.start_with_context().await
     };
 
     // Initialize Wormhole
-    context
-        .banks_client
-        .process_transaction(Transaction::new_signed_with_payer(
-            &[wormhole_sdk::instructions::initialize(
-                wormhole_sdk::id(),
-                context.payer.pubkey(),
-                50,
-                2_000_000_000,
-                &[*guardian],
-            ).unwrap()],
-            Some(&context.payer.pubkey()),
-            &[&context.payer],
-            context.last_blockhash,
-        ))
-        .await
-        .unwrap();
+    initialize_bridge(&mut context, &guardians(), 50, 2_000_000_000).await.unwrap();
+
+    // Initialize Messenger, allowing senders to pick either consistency level.
+    initialize_messenger(
+        &mut context,
+        &deployer,
+        Consistency::Finalized,
+        vec![Consistency::Confirmed, Consistency::Finalized],
+    )
+    .await;
 
-    // Message & Emitter Account Keys
-    let message = Keypair::new();
+    // Message & Emitter Account Keys, the payer has not sent anything yet so this is message 0.
+    let message = messenger::state::message(&ID, &context.payer.pubkey(), 0).0;
     let emitter = wormhole_sdk::emitter(&ID);
 
     // Submit a cross-chain message via Wormhole.
@@ -210,17 +262,20 @@ pub async fn test_publish_message_raw() {
         .process_transaction(Transaction::new_signed_with_payer(
             &[send_message_raw(
                 ID,
+                wormhole_sdk::id(),
                 context.payer.pubkey(),
-                emitter.0,
-                message.pubkey(),
+                0,
                 Message {
                     nick: "Alice".to_string(),
                     text: "Hello from Bob!".to_string(),
                 },
                 1,
+                None,
+                0,
+                None,
             )],
             Some(&context.payer.pubkey()),
-            &[&context.payer, &message],
+            &[&context.payer],
             context.last_blockhash,
         ))
         .await
@@ -230,7 +285,7 @@ pub async fn test_publish_message_raw() {
     let message = MessageData::try_from_slice(
         &context
             .banks_client
-            .get_account(message.pubkey())
+            .get_account(message)
             .await
             .unwrap()
             .unwrap()
@@ -244,86 +299,234 @@ pub async fn test_publish_message_raw() {
     assert_eq!(message.emitter_chain, 1);
     assert_eq!(message.emitter_address, emitter.0.to_bytes());
     assert_eq!(
-        Message::try_from_slice(&message.payload).unwrap(),
-        Message {
-            nick: "Alice".to_string(),
-            text: "Hello from Bob!".to_string(),
+        Envelope::try_from_slice(&message.payload).unwrap(),
+        Envelope {
+            sender:       context.payer.pubkey().to_bytes(),
+            target_chain: 0,
+            target:       None,
+            recipient:    None,
+            room:         None,
+            message:      Message {
+                nick: "Alice".to_string(),
+                text: "Hello from Bob!".to_string(),
+            },
         }
     );
 }
 
-pub async fn simulate_guardians(context: &mut ProgramTestContext, message: &MessageData) -> Pubkey {
-    // Emulate Guardian signatures by signing manually. First we produce a VAA.
-    let vaa = {
-        let mut vaa           = VAA::default();
-        vaa.timestamp         = message.submission_time;
-        vaa.nonce             = message.nonce;
-        vaa.emitter_chain     = Chain::Solana;
-        vaa.emitter_address   = message.emitter_address;
-        vaa.sequence          = message.sequence;
-        vaa.consistency_level = message.consistency_level;
-        vaa.payload           = message.payload.clone();
-        vaa
-    };
+#[tokio::test]
+pub async fn test_publish_message_consistency() {
+    // Only allow Confirmed messages, so that we can check both the default and the rejection of
+    // a level the config does not allow.
+    let mut context = start(Consistency::Confirmed, vec![Consistency::Confirmed]).await;
+
+    // Sending without a consistency level should use the config default.
+    let message = messenger::state::message(&ID, &context.payer.pubkey(), 0).0;
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[send_message(
+                ID,
+                wormhole_sdk::id(),
+                context.payer.pubkey(),
+                0,
+                Message {
+                    nick: "Alice".to_string(),
+                    text: "Quick hello".to_string(),
+                },
+                0,
+                None,
+                0,
+                None,
+            )],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
 
-    // Hash the body to produce our message to sign.
-    let body = vaa.digest().unwrap();
+    let posted = MessageData::try_from_slice(
+        &context
+            .banks_client
+            .get_account(message)
+            .await
+            .unwrap()
+            .unwrap()
+            .data[3..]
+    ).unwrap();
 
-    // Place to store Signatures on Solana.
-    let signatures = Keypair::new();
-    let mut signers = [-1; 19];
-    signers[0] = 0;
+    assert_eq!(posted.consistency_level, 1);
 
-    // Verify Signatures
+    // Asking for Finalized should now be rejected.
+    assert!(context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[send_message(
+                ID,
+                wormhole_sdk::id(),
+                context.payer.pubkey(),
+                1,
+                Message {
+                    nick: "Alice".to_string(),
+                    text: "Slow hello".to_string(),
+                },
+                1,
+                Some(Consistency::Finalized),
+                0,
+                None,
+            )],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+pub async fn test_sponsored_message() {
+    // Wormhole charges a fee of 50 lamports per message.
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+
+    // Fund a sponsor, have them deposit into the vault and cover a single message for the payer.
+    // An unfunded griefer granting the payer everything first must not get in the way.
+    let sponsor_key = Keypair::new();
+    let griefer = Keypair::new();
     context
         .banks_client
         .process_transaction(Transaction::new_signed_with_payer(
             &[
-                new_secp256k1_instruction(
-                    &SecretKey::parse(
-                        (&*hex::decode("ff2f9d893e5c12618c442b34a98cfa3f646c402bf5e2a180ce761a7d8a43d452")
-                            .unwrap())
-                            .try_into()
-                            .unwrap()
-                    ).unwrap(),
-                    &body
-                ),
-                wormhole_sdk::instructions::verify_signatures(
-                    wormhole_sdk::id(),
-                    context.payer.pubkey(),
-                    0,
-                    signatures.pubkey(),
-                    VerifySignaturesData { signers },
-                ).unwrap(),
+                system_instruction::transfer(&context.payer.pubkey(), &griefer.pubkey(), 10_000_000),
+                sponsor(ID, griefer.pubkey(), context.payer.pubkey(), u64::MAX),
+                system_instruction::transfer(&context.payer.pubkey(), &sponsor_key.pubkey(), 2_000_000_000),
+                deposit(ID, sponsor_key.pubkey(), 1_000_000_000),
+                sponsor(ID, sponsor_key.pubkey(), context.payer.pubkey(), 1),
             ],
             Some(&context.payer.pubkey()),
-            &[&context.payer, &signatures],
+            &[&context.payer, &sponsor_key, &griefer],
             context.last_blockhash,
         ))
         .await
         .unwrap();
 
+    // Send a sponsored message, the fee should come out of the sponsor's vault balance.
     context
         .banks_client
         .process_transaction(Transaction::new_signed_with_payer(
-            &[
-                wormhole_sdk::instructions::post_vaa(
+            &[sponsored(
+                send_message(
+                    ID,
                     wormhole_sdk::id(),
                     context.payer.pubkey(),
-                    signatures.pubkey(),
-                    PostVAAData {
-                        version:            0,
-                        guardian_set_index: 0,
-                        timestamp:          vaa.timestamp,
-                        nonce:              vaa.nonce,
-                        emitter_chain:      vaa.emitter_chain as u16,
-                        emitter_address:    vaa.emitter_address,
-                        sequence:           vaa.sequence,
-                        consistency_level:  vaa.consistency_level,
-                        payload:            vaa.payload,
+                    0,
+                    Message {
+                        nick: "Alice".to_string(),
+                        text: "Thanks for the SOL!".to_string(),
                     },
+                    0,
+                    None,
+                    0,
+                    None,
                 ),
-            ],
+                sponsor_key.pubkey(),
+            )],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let funds = read_account::<Sponsor>(&mut context, messenger::state::sponsor(&ID, &sponsor_key.pubkey()).0).await;
+    let grant = read_account::<Sponsorship>(&mut context, messenger::state::sponsorship(&ID, &sponsor_key.pubkey(), &context.payer.pubkey()).0).await;
+    assert_eq!(funds.balance, 1_000_000_000 - 50);
+    assert_eq!(grant.remaining, 0);
+
+    // The sponsor can take back whatever is left, but no more.
+    assert!(context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[withdraw(ID, sponsor_key.pubkey(), 1_000_000_000)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &sponsor_key],
+            context.last_blockhash,
+        ))
+        .await
+        .is_err());
+
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[withdraw(ID, sponsor_key.pubkey(), 1_000_000_000 - 50)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &sponsor_key],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    let funds = read_account::<Sponsor>(&mut context, messenger::state::sponsor(&ID, &sponsor_key.pubkey()).0).await;
+    assert_eq!(funds.balance, 0);
+}
+
+#[tokio::test]
+pub async fn test_send_batch() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+
+    let payloads: Vec<Message> = (0..3)
+        .map(|i| Message {
+            nick: "Alice".to_string(),
+            text: format!("Message {}", i),
+        })
+        .collect();
+
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[send_batch(
+                ID,
+                wormhole_sdk::id(),
+                context.payer.pubkey(),
+                0,
+                payloads.clone(),
+                7,
+                0,
+            )],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+
+    // Every message should have landed in its own PDA, in order, under the shared nonce.
+    for (i, payload) in payloads.iter().enumerate() {
+        let message = messenger::state::message(&ID, &context.payer.pubkey(), i as u64).0;
+        let message = MessageData::try_from_slice(
+            &context
+                .banks_client
+                .get_account(message)
+                .await
+                .unwrap()
+                .unwrap()
+                .data[3..]
+        ).unwrap();
+
+        assert_eq!(message.nonce, 7);
+        assert_eq!(message.sequence, i as u64);
+        assert_eq!(&Envelope::try_from_slice(&message.payload).unwrap().message, payload);
+    }
+
+    let sender = read_account::<Sender>(&mut context, messenger::state::sender(&ID, &context.payer.pubkey()).0).await;
+    assert_eq!(sender.count, 3);
+
+    // A full batch fits in a single transaction.
+    let full = vec![payloads[0].clone(); messenger::MAX_BATCH];
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[send_batch(ID, wormhole_sdk::id(), context.payer.pubkey(), 3, full, 8, 0)],
             Some(&context.payer.pubkey()),
             &[&context.payer],
             context.last_blockhash,
@@ -331,9 +534,586 @@ pub async fn simulate_guardians(context: &mut ProgramTestContext, message: &Mess
         .await
         .unwrap();
 
-    // Derive VAA Destination.
-    Pubkey::find_program_address(
-        &[b"PostedVAA", &body],
-        &wormhole_sdk::id(),
-    ).0
+    let count = 3 + messenger::MAX_BATCH as u64;
+    let sender = read_account::<Sender>(&mut context, messenger::state::sender(&ID, &context.payer.pubkey()).0).await;
+    assert_eq!(sender.count, count);
+
+    // Batches larger than MAX_BATCH are rejected outright.
+    let payloads = vec![payloads[0].clone(); messenger::MAX_BATCH + 1];
+    assert!(context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[send_batch(ID, wormhole_sdk::id(), context.payer.pubkey(), count, payloads, 9, 0)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        ))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+pub async fn test_rate_limits() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+
+    // Nonces differ between attempts so retried sends aren't identical transactions.
+    let send = |count: u64, nonce: u32, chain: u16| {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "Hello".to_string(),
+        };
+        send_message(ID, wormhole_sdk::id(), payer, count, message, nonce, None, chain, None)
+    };
+
+    // Limits without a window to count them over are refused.
+    let broken = RateLimit { window: 0, limit: 1 };
+    assert!(process(&mut context, &[set_rate_limit(ID, payer, broken)], &[]).await.is_err());
+    assert!(process(&mut context, &[set_destination_limit(ID, payer, 2, broken)], &[]).await.is_err());
+
+    // Two messages an hour per sender, the third is refused.
+    let hourly = RateLimit { window: 3600, limit: 2 };
+    process(&mut context, &[set_rate_limit(ID, payer, hourly)], &[]).await.unwrap();
+    process(&mut context, &[send(0, 0, 0), send(1, 1, 0)], &[]).await.unwrap();
+    assert!(process(&mut context, &[send(2, 2, 0)], &[]).await.is_err());
+
+    // Exempt senders aren't limited at all.
+    process(&mut context, &[set_exempt(ID, payer, payer, true)], &[]).await.unwrap();
+    process(&mut context, &[send(2, 3, 0)], &[]).await.unwrap();
+    process(&mut context, &[set_exempt(ID, payer, payer, false)], &[]).await.unwrap();
+
+    // With the sender limit lifted, a cap on chain 2 still applies to chain 2 only.
+    let cap = RateLimit { window: 3600, limit: 1 };
+    process(&mut context, &[set_rate_limit(ID, payer, RateLimit::UNLIMITED)], &[]).await.unwrap();
+    process(&mut context, &[set_destination_limit(ID, payer, 2, cap)], &[]).await.unwrap();
+    process(&mut context, &[send(3, 4, 2)], &[]).await.unwrap();
+    assert!(process(&mut context, &[send(4, 5, 2)], &[]).await.is_err());
+    process(&mut context, &[send(4, 6, 3)], &[]).await.unwrap();
+
+    let destination = read_account::<Destination>(&mut context, messenger::state::destination(&ID, 2).0).await;
+    assert_eq!(destination.usage.used, 1);
+
+    // Leaving out the destination account doesn't get around the cap.
+    let mut skip = send(4, 7, 2);
+    let capped = messenger::state::destination(&ID, 2).0;
+    skip.accounts.retain(|account| account.pubkey != capped);
+    assert!(process(&mut context, &[skip], &[]).await.is_err());
+
+    // Only the admin can change limits.
+    let stranger = Keypair::new();
+    let forged = set_exempt(ID, stranger.pubkey(), payer, true);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_protocol_fee() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+    let treasury = messenger::state::treasury(&ID).0;
+
+    let send = |count: u64| {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "Hello".to_string(),
+        };
+        send_message(ID, wormhole_sdk::id(), payer, count, message, count as u32, None, 0, None)
+    };
+
+    // No fee is charged until the admin sets one.
+    process(&mut context, &[send(0)], &[]).await.unwrap();
+    assert_eq!(context.banks_client.get_balance(treasury).await.unwrap(), 0);
+
+    process(&mut context, &[set_protocol_fee(ID, payer, 1_000)], &[]).await.unwrap();
+    process(&mut context, &[send(1), send(2)], &[]).await.unwrap();
+    assert_eq!(context.banks_client.get_balance(treasury).await.unwrap(), 2_000);
+
+    // The admin withdraws to any account, nobody else can touch the treasury or the fee.
+    let recipient = Keypair::new().pubkey();
+    process(&mut context, &[withdraw_treasury(ID, payer, recipient, 1_500)], &[]).await.unwrap();
+    assert_eq!(context.banks_client.get_balance(recipient).await.unwrap(), 1_500);
+    assert_eq!(context.banks_client.get_balance(treasury).await.unwrap(), 500);
+
+    let stranger = Keypair::new();
+    let forged = withdraw_treasury(ID, stranger.pubkey(), stranger.pubkey(), 500);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+    let forged = set_protocol_fee(ID, stranger.pubkey(), 0);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_initialize() {
+    let deployer = Keypair::new();
+    let mut context = {
+        let mut test = ProgramTest::default();
+        test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
+        test.add_program("messenger", ID, processor!(process_instruction));
+        add_program_data(&mut test, ID, deployer.pubkey());
+        test.start_with_context().await
+    };
+    initialize_bridge(&mut context, &guardians(), 50, 2_000_000_000).await.unwrap();
+
+    // Only the program's upgrade authority can approve the config, so nobody can claim a fresh
+    // deployment before its deployer does.
+    let payer = context.payer.pubkey();
+    let init = |authority| {
+        initialize(
+            ID,
+            payer,
+            authority,
+            wormhole_sdk::id(),
+            Consistency::Finalized,
+            vec![Consistency::Finalized],
+        )
+    };
+    let stranger = Keypair::new();
+    assert!(process(&mut context, &[init(stranger.pubkey())], &[&stranger]).await.is_err());
+    process(&mut context, &[init(deployer.pubkey())], &[&deployer]).await.unwrap();
+
+    let config: Config = read_account(&mut context, messenger::state::config(&ID).0).await;
+    assert_eq!(config.admin, payer);
+    assert_eq!(config.wormhole, wormhole_sdk::id());
+}
+
+#[tokio::test]
+pub async fn test_pause() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+
+    let send = |count: u64| {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "Hello".to_string(),
+        };
+        send_message(ID, wormhole_sdk::id(), payer, count, message, count as u32, None, 0, None)
+    };
+
+    // Sending is refused while paused, and resumes afterwards.
+    process(&mut context, &[set_paused(ID, payer, true, false)], &[]).await.unwrap();
+    assert!(process(&mut context, &[send(0)], &[]).await.is_err());
+    process(&mut context, &[set_paused(ID, payer, false, false)], &[]).await.unwrap();
+    process(&mut context, &[send(0)], &[]).await.unwrap();
+
+    // Likewise receiving.
+    let account = messenger::state::message(&ID, &payer, 0).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let posted_vaa = simulate_guardians(&mut context, &MessageData::try_from_slice(&data[3..]).unwrap()).await;
+    process(&mut context, &[set_paused(ID, payer, false, true)], &[]).await.unwrap();
+    let recv = recv_instruction(&mut context, ID, posted_vaa).await;
+    assert!(process(&mut context, &[recv.clone()], &[]).await.is_err());
+    process(&mut context, &[set_paused(ID, payer, false, false)], &[]).await.unwrap();
+    process(&mut context, &[recv], &[]).await.unwrap();
+
+    let config = read_account::<Config>(&mut context, messenger::state::config(&ID).0).await;
+    assert!(!config.send_paused && !config.receive_paused);
+
+    // Only the admin may pause.
+    let stranger = Keypair::new();
+    let forged = set_paused(ID, stranger.pubkey(), true, true);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_migrate() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+
+    // Accounts are created behind a header, with room for their layout to grow.
+    let key = messenger::state::config(&ID).0;
+    let account = context.banks_client.get_account(key).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), Header::SIZE + Config::SIZE + RESERVED);
+    assert_eq!(account.data[..Header::SIZE], [AccountKind::Config as u8, Config::VERSION]);
+
+    // Migrating an account already in the current layout leaves it as it was.
+    let config = read_account::<Config>(&mut context, key).await;
+    process(&mut context, &[migrate(ID, key)], &[]).await.unwrap();
+    assert_eq!(read_account::<Config>(&mut context, key).await, config);
+
+    // Only our own accounts can be migrated.
+    assert!(process(&mut context, &[migrate(ID, payer)], &[]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_channels() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+
+    let send = |count: u64, channel: Option<&str>| {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "Hello".to_string(),
+        };
+        send_message(ID, wormhole_sdk::id(), payer, count, message, count as u32, None, 0, channel)
+    };
+
+    // One message on the default emitter, then one on each of two channels.
+    process(&mut context, &[send(0, None), send(1, Some("news")), send(2, Some("alerts"))], &[]).await.unwrap();
+
+    // Each channel posts from its own emitter, so each starts its own sequence at 0.
+    let mut emitters = Vec::new();
+    for (count, channel) in [(0, None), (1, Some("news")), (2, Some("alerts"))] {
+        let account = messenger::state::message(&ID, &payer, count).0;
+        let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+        let message = MessageData::try_from_slice(&data[3..]).unwrap();
+        assert_eq!(message.sequence, 0);
+        assert_eq!(message.emitter_address, messenger::state::emitter(&ID, channel).to_bytes());
+        emitters.push(message.emitter_address);
+    }
+    assert_eq!(
+        emitters[1],
+        messenger::state::channel_emitter(&ID, "news").0.to_bytes()
+    );
+    assert_ne!(emitters[1], emitters[2]);
+
+    // The raw handler derives the same channel emitter, continuing its sequence.
+    let message = Message {
+        nick: "Alice".to_string(),
+        text: "Raw".to_string(),
+    };
+    let raw = send_message_raw(ID, wormhole_sdk::id(), payer, 3, message, 3, None, 0, Some("news"));
+    process(&mut context, &[raw], &[]).await.unwrap();
+    let account = messenger::state::message(&ID, &payer, 3).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let message = MessageData::try_from_slice(&data[3..]).unwrap();
+    assert_eq!(message.sequence, 1);
+    assert_eq!(message.emitter_address, emitters[1]);
+
+    // Channel names must fit in a PDA seed. No emitter can be derived for an over-long name, so
+    // reuse a valid channel's accounts.
+    let message = Message {
+        nick: "Alice".to_string(),
+        text: "Long".to_string(),
+    };
+    let long = "x".repeat(messenger::state::MAX_CHANNEL_LEN + 1);
+    let mut invalid = send(4, Some("news"));
+    invalid.data = messenger::Instruction::SendMessage(message, 4, None, 0, Some(long)).try_to_vec().unwrap();
+    assert!(process(&mut context, &[invalid], &[]).await.is_err());
+    assert!(process(&mut context, &[send(4, Some(""))], &[]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_rooms() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let owner = context.payer.pubkey();
+    let member = Keypair::new();
+    let fund = system_instruction::transfer(&owner, &member.pubkey(), 1_000_000_000);
+    process(&mut context, &[fund, create_room(ID, owner, 7)], &[]).await.unwrap();
+
+    let send = |sender: Pubkey, count: u64, nonce: u32| {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "Hello room".to_string(),
+        };
+        send_room_message(ID, wormhole_sdk::id(), sender, count, 7, message, nonce, None, 0)
+    };
+
+    // Rooms can't be created twice.
+    assert!(process(&mut context, &[create_room(ID, owner, 7)], &[]).await.is_err());
+
+    // Non-members are refused, as are members that haven't been allowed to post.
+    assert!(process(&mut context, &[send(member.pubkey(), 0, 0)], &[&member]).await.is_err());
+    let join = set_room_member(ID, owner, 7, member.pubkey(), false);
+    process(&mut context, &[join], &[]).await.unwrap();
+    assert!(process(&mut context, &[send(member.pubkey(), 0, 1)], &[&member]).await.is_err());
+
+    // Only the owner manages membership.
+    let forged = set_room_member(ID, member.pubkey(), 7, member.pubkey(), true);
+    assert!(process(&mut context, &[forged], &[&member]).await.is_err());
+    let promote = set_room_member(ID, owner, 7, member.pubkey(), true);
+    process(&mut context, &[promote], &[]).await.unwrap();
+    process(&mut context, &[send(member.pubkey(), 0, 2), send(owner, 0, 2)], &[&member]).await.unwrap();
+
+    let room = read_account::<Room>(&mut context, messenger::state::room(&ID, 7).0).await;
+    assert_eq!(room.owner, owner);
+    assert_eq!(room.members.len(), 2);
+
+    // The posted envelope carries the room, and receiving it delivers it to the room's inbox.
+    let account = messenger::state::message(&ID, &member.pubkey(), 0).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let message = MessageData::try_from_slice(&data[3..]).unwrap();
+    assert_eq!(Envelope::try_from_slice(&message.payload).unwrap().room, Some(7));
+
+    // Until our emitter is registered as another Messenger, as a route to ourselves, its room
+    // envelopes are refused. Anyone else could emit them without being a member.
+    let posted_vaa = simulate_guardians(&mut context, &message).await;
+    let recv = recv_instruction(&mut context, ID, posted_vaa).await;
+    assert!(process(&mut context, &[recv.clone()], &[]).await.is_err());
+
+    let emitter = wormhole_sdk::emitter(&ID).0.to_bytes();
+    let trust = register_route(ID, owner, ID, 1, emitter);
+    process(&mut context, &[trust.clone(), recv.clone()], &[]).await.unwrap();
+
+    // The same VAA can't be delivered twice. Registering again is a no-op, it's only here so the
+    // transaction differs from the one above.
+    assert!(process(&mut context, &[recv, trust], &[]).await.is_err());
+
+    let inbox = read_account::<Inbox>(&mut context, messenger::state::inbox(&ID, 7).0).await;
+    assert_eq!(inbox.count, 1);
+    assert_eq!(inbox.entries[0].vaa, posted_vaa);
+    assert_eq!(inbox.entries[0].sender, member.pubkey().to_bytes());
+    assert!(!inbox.entries[0].quarantined);
+
+    // Once the owner mutes the member their messages are quarantined, once blocked they are
+    // dropped. Either way the receive succeeds.
+    let sender = member.pubkey().to_bytes();
+    let mute = set_block(ID, owner, 1, sender, Some(BlockMode::Mute));
+    process(&mut context, &[mute, send(member.pubkey(), 1, 3)], &[&member]).await.unwrap();
+    let account = messenger::state::message(&ID, &member.pubkey(), 1).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let posted_vaa = simulate_guardians(&mut context, &MessageData::try_from_slice(&data[3..]).unwrap()).await;
+    let recv = recv_instruction(&mut context, ID, posted_vaa).await;
+    process(&mut context, &[recv], &[]).await.unwrap();
+
+    let block = set_block(ID, owner, 1, sender, Some(BlockMode::Block));
+    process(&mut context, &[block, send(member.pubkey(), 2, 4)], &[&member]).await.unwrap();
+    let account = messenger::state::message(&ID, &member.pubkey(), 2).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let posted_vaa = simulate_guardians(&mut context, &MessageData::try_from_slice(&data[3..]).unwrap()).await;
+    let recv = recv_instruction(&mut context, ID, posted_vaa).await;
+    process(&mut context, &[recv], &[]).await.unwrap();
+
+    let inbox = read_account::<Inbox>(&mut context, messenger::state::inbox(&ID, 7).0).await;
+    assert_eq!(inbox.count, 2);
+    assert!(inbox.entries[1].quarantined);
+
+    // Entries can only be removed by their owner, and only once.
+    let entry = messenger::state::block(&ID, &owner, 1, &sender).0;
+    let forged = set_block(ID, member.pubkey(), 1, sender, None);
+    assert!(process(&mut context, &[forged], &[&member]).await.is_err());
+    assert!(context.banks_client.get_account(entry).await.unwrap().is_some());
+    process(&mut context, &[set_block(ID, owner, 1, sender, None)], &[]).await.unwrap();
+    assert!(context.banks_client.get_account(entry).await.unwrap().is_none());
+    assert!(process(&mut context, &[set_block(ID, owner, 1, sender, None)], &[]).await.is_err());
+
+    // Members can leave, after which they can no longer post.
+    let leave = remove_room_member(ID, member.pubkey(), 7, member.pubkey());
+    process(&mut context, &[leave], &[&member]).await.unwrap();
+    assert!(process(&mut context, &[send(member.pubkey(), 3, 5)], &[&member]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_register_nick() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+
+    let register = register_nick(ID, wormhole_sdk::id(), payer, 0, "alice", 0);
+    process(&mut context, &[register], &[]).await.unwrap();
+
+    let entry = read_account::<Nick>(&mut context, messenger::state::nick(&ID, "alice").0).await;
+    assert_eq!(entry.claim.chain, 1);
+    assert_eq!(entry.claim.owner, payer.to_bytes());
+
+    // The registration is posted for other chains as the payer's next message.
+    let account = messenger::state::message(&ID, &payer, 0).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let message = MessageData::try_from_slice(&data[3..]).unwrap();
+    let registration = Registration::parse(&message.payload).unwrap();
+    assert_eq!(registration.nick, "alice");
+    assert_eq!(registration.owner, payer.to_bytes());
+
+    // Nicks can only be registered once, and must fit the directory.
+    let other = Keypair::new();
+    let fund = system_instruction::transfer(&payer, &other.pubkey(), 1_000_000_000);
+    process(&mut context, &[fund], &[]).await.unwrap();
+    let taken = register_nick(ID, wormhole_sdk::id(), other.pubkey(), 0, "alice", 0);
+    assert!(process(&mut context, &[taken], &[&other]).await.is_err());
+    let empty = register_nick(ID, wormhole_sdk::id(), other.pubkey(), 0, "", 0);
+    assert!(process(&mut context, &[empty], &[&other]).await.is_err());
+    let bob = register_nick(ID, wormhole_sdk::id(), other.pubkey(), 0, "bob", 0);
+    process(&mut context, &[bob], &[&other]).await.unwrap();
+}
+
+#[tokio::test]
+pub async fn test_scheduled_messages() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+    let cranker = Keypair::new();
+    let fund = system_instruction::transfer(&payer, &cranker.pubkey(), 1_000_000_000);
+    process(&mut context, &[fund], &[]).await.unwrap();
+
+    // The cranker is sponsored, but is paid back from the escrow so the grant must go untouched.
+    let sponsor_key = Keypair::new();
+    let grant = [
+        system_instruction::transfer(&payer, &sponsor_key.pubkey(), 2_000_000_000),
+        deposit(ID, sponsor_key.pubkey(), 1_000_000_000),
+        sponsor(ID, sponsor_key.pubkey(), cranker.pubkey(), 1),
+    ];
+    process(&mut context, &grant, &[&sponsor_key]).await.unwrap();
+
+    let schedule = |id: u64, due: Due| {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "Later".to_string(),
+        };
+        schedule_message(ID, wormhole_sdk::id(), payer, id, message, 0, due, 0, 10_000_000)
+    };
+    let crank = |id: u64| {
+        let crank = crank(ID, wormhole_sdk::id(), cranker.pubkey(), payer, 0, id, 0);
+        sponsored(crank, sponsor_key.pubkey())
+    };
+
+    // Messages can't be cranked before they are due, and can be cancelled until then.
+    process(&mut context, &[schedule(0, Due::Slot(u64::MAX))], &[]).await.unwrap();
+    assert!(process(&mut context, &[schedule(0, Due::Time(0))], &[]).await.is_err());
+    assert!(process(&mut context, &[crank(0)], &[&cranker]).await.is_err());
+
+    let escrow = messenger::state::scheduled(&ID, &payer, 0).0;
+    let stranger = Keypair::new();
+    let forged = cancel_scheduled(ID, stranger.pubkey(), 0);
+    assert!(process(&mut context, &[forged], &[&stranger]).await.is_err());
+    process(&mut context, &[cancel_scheduled(ID, payer, 0)], &[]).await.unwrap();
+    assert!(context.banks_client.get_account(escrow).await.unwrap().is_none());
+
+    // Once due anyone can crank, posting the message as the sender's and earning the reward. The
+    // scheduled account's rent goes back to the sender, more than covering the transaction fee.
+    process(&mut context, &[schedule(1, Due::Time(0))], &[]).await.unwrap();
+    let before = context.banks_client.get_balance(cranker.pubkey()).await.unwrap();
+    let sender_before = context.banks_client.get_balance(payer).await.unwrap();
+    process(&mut context, &[crank(1)], &[&cranker]).await.unwrap();
+    assert!(context.banks_client.get_balance(cranker.pubkey()).await.unwrap() > before);
+    assert!(context.banks_client.get_balance(payer).await.unwrap() > sender_before);
+    assert!(context.banks_client.get_account(messenger::state::scheduled(&ID, &payer, 1).0).await.unwrap().is_none());
+
+    let funds = read_account::<Sponsor>(&mut context, messenger::state::sponsor(&ID, &sponsor_key.pubkey()).0).await;
+    let grant = read_account::<Sponsorship>(&mut context, messenger::state::sponsorship(&ID, &sponsor_key.pubkey(), &cranker.pubkey()).0).await;
+    assert_eq!(funds.balance, 1_000_000_000);
+    assert_eq!(grant.remaining, 1);
+
+    let account = messenger::state::message(&ID, &payer, 0).0;
+    let data = context.banks_client.get_account(account).await.unwrap().unwrap().data;
+    let message = MessageData::try_from_slice(&data[3..]).unwrap();
+    let envelope = Envelope::try_from_slice(&message.payload).unwrap();
+    assert_eq!(envelope.sender, payer.to_bytes());
+    assert_eq!(envelope.message.text, "Later");
+
+    // Posted messages are gone, so can't be cranked twice.
+    assert!(process(&mut context, &[crank(1)], &[&cranker]).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_multisig() {
+    let mut context = start(Consistency::Finalized, vec![Consistency::Finalized]).await;
+    let payer = context.payer.pubkey();
+    let signers = vec![Keypair::new(), Keypair::new(), Keypair::new()];
+    let stranger = Keypair::new();
+    for key in signers.iter().chain(Some(&stranger)) {
+        let fund = system_instruction::transfer(&payer, &key.pubkey(), 1_000_000_000);
+        process(&mut context, &[fund], &[]).await.unwrap();
+    }
+    let keys: Vec<Pubkey> = signers.iter().map(|signer| signer.pubkey()).collect();
+    let multisig = messenger::state::multisig(&ID, 0).0;
+
+    // Thresholds must be reachable.
+    assert!(process(&mut context, &[create_multisig(ID, payer, 0, keys.clone(), 4)], &[]).await.is_err());
+    process(&mut context, &[create_multisig(ID, payer, 0, keys.clone(), 2)], &[]).await.unwrap();
+
+    let message = Message {
+        nick: "DAO".to_string(),
+        text: "Vote passed".to_string(),
+    };
+    let propose = |proposer: &Keypair, proposal: u64, expiry: i64| {
+        propose(ID, proposer.pubkey(), 0, proposal, message.clone(), 0, 0, expiry)
+    };
+    let approve = |signer: &Keypair, proposal: u64, count: u64| {
+        approve(ID, wormhole_sdk::id(), signer.pubkey(), 0, proposal, keys[0], count, 0)
+    };
+
+    // Only signers can propose, and each signer approves once.
+    assert!(process(&mut context, &[propose(&stranger, 0, i64::MAX)], &[&stranger]).await.is_err());
+    process(&mut context, &[propose(&signers[0], 0, i64::MAX)], &[&signers[0]]).await.unwrap();
+    process(&mut context, &[approve(&signers[0], 0, 0)], &[&signers[0]]).await.unwrap();
+    assert!(process(&mut context, &[approve(&signers[0], 0, 0)], &[&signers[0]]).await.is_err());
+    assert!(process(&mut context, &[approve(&stranger, 0, 0)], &[&stranger]).await.is_err());
+
+    let account = messenger::state::proposal(&ID, &multisig, 0).0;
+    assert_eq!(read_account::<Proposal>(&mut context, account).await.votes(), 1);
+
+    // The second approval meets the threshold, posting the message as the multisig's.
+    process(&mut context, &[approve(&signers[1], 0, 0)], &[&signers[1]]).await.unwrap();
+    assert!(context.banks_client.get_account(account).await.unwrap().is_none());
+
+    let message_account = messenger::state::message(&ID, &multisig, 0).0;
+    let data = context.banks_client.get_account(message_account).await.unwrap().unwrap().data;
+    let posted = MessageData::try_from_slice(&data[3..]).unwrap();
+    let envelope = Envelope::try_from_slice(&posted.payload).unwrap();
+    assert_eq!(envelope.sender, multisig.to_bytes());
+    assert_eq!(envelope.message.text, "Vote passed");
+
+    // Expired proposals can't be approved, but anyone can close them.
+    process(&mut context, &[propose(&signers[0], 1, 0)], &[&signers[0]]).await.unwrap();
+    assert!(process(&mut context, &[approve(&signers[2], 1, 1)], &[&signers[2]]).await.is_err());
+    let cancel = cancel_proposal(ID, stranger.pubkey(), keys[0], 0, 1);
+    process(&mut context, &[cancel], &[&stranger]).await.unwrap();
+
+    // Pending proposals can only be cancelled by signers.
+    process(&mut context, &[propose(&signers[0], 2, i64::MAX)], &[&signers[0]]).await.unwrap();
+    let cancel = cancel_proposal(ID, stranger.pubkey(), keys[0], 0, 2);
+    assert!(process(&mut context, &[cancel], &[&stranger]).await.is_err());
+    let cancel = cancel_proposal(ID, keys[2], keys[0], 0, 2);
+    process(&mut context, &[cancel], &[&signers[2]]).await.unwrap();
+    let account = messenger::state::proposal(&ID, &multisig, 2).0;
+    assert!(context.banks_client.get_account(account).await.unwrap().is_none());
+}
+
+/// Start a test environment with the bridge initialised with a single guardian and a 50 lamport
+/// fee, and the Messenger initialised with the given consistency policy.
+pub async fn start(default: Consistency, allowed: Vec<Consistency>) -> ProgramTestContext {
+    let deployer = Keypair::new();
+    let mut context = {
+        let mut test = ProgramTest::default();
+        test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
+        test.add_program("messenger", ID, processor!(process_instruction));
+        add_program_data(&mut test, ID, deployer.pubkey());
+        test.start_with_context().await
+    };
+
+    initialize_bridge(&mut context, &guardians(), 50, 2_000_000_000).await.unwrap();
+
+    initialize_messenger(&mut context, &deployer, default, allowed).await;
+    context
+}
+
+/// Fetch and decode a Messenger owned account.
+pub async fn read_account<T: Versioned>(context: &mut ProgramTestContext, key: Pubkey) -> T {
+    let account = context.banks_client.get_account(key).await.unwrap().unwrap();
+    messenger::state::decode(&account.data).unwrap()
+}
+
+/// Create the Messenger config account pointing at the bridge deployed in the test environment,
+/// approved by the `deployer` named as the program's upgrade authority.
+pub async fn initialize_messenger(
+    context: &mut ProgramTestContext,
+    deployer: &Keypair,
+    default: Consistency,
+    allowed: Vec<Consistency>,
+) {
+    context
+        .banks_client
+        .process_transaction(Transaction::new_signed_with_payer(
+            &[initialize(
+                ID,
+                context.payer.pubkey(),
+                deployer.pubkey(),
+                wormhole_sdk::id(),
+                default,
+                allowed,
+            )],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, deployer],
+            context.last_blockhash,
+        ))
+        .await
+        .unwrap();
+}
+
+pub async fn simulate_guardians(context: &mut ProgramTestContext, message: &MessageData) -> Pubkey {
+    // Emulate the guardians observing the message, signing it, and posting the VAA.
+    let mut vaa = message_vaa(message);
+    guardians().sign(&mut vaa);
+    post_vaa(context, &guardians(), &vaa).await.unwrap()
+}
+
+/// The guardian set the bridge is initialised with in every test.
+pub fn guardians() -> Guardians {
+    let secret = hex::decode(GUARDIAN).unwrap();
+    Guardians::from_secrets(0, &[secret.try_into().unwrap()])
 }
@@ -0,0 +1,76 @@
+//! Gas used by the contract's `execute` as the message grows, measured by running the compiled
+//! contract in `cosmwasm-vm`. Build the wasm first:
+//!
+//! ```
+//! $ cargo build --release --target wasm32-unknown-unknown --lib
+//! $ cargo bench --bench gas > gas.txt
+//! ```
+//!
+//! The table has the same columns as the Solana `costs` benchmark so the two can be compared.
+//! `compute_units` is cosmwasm-vm gas, which is 100 times Cosmos SDK gas, and `tx_bytes` is the
+//! length of the JSON execute message. Terra has no rent. RecvMessage is not measured as it asks
+//! the Wormhole contract to verify the VAA, which the mock querier cannot answer.
+
+use borsh::BorshSerialize;
+use cosmwasm_std::Response;
+use cosmwasm_vm::testing::{
+    execute,
+    instantiate,
+    mock_env,
+    mock_info,
+    mock_instance_with_gas_limit,
+};
+use serde_json::json;
+
+use messenger_common::Message;
+
+
+const WASM: &str = "target/wasm32-unknown-unknown/release/wormhole_messenger_terra.wasm";
+
+/// Text lengths to measure, the same as the Solana benchmark.
+const SIZES: &[usize] = &[0, 32, 128, 256, 512, 768];
+
+
+fn main() {
+    let wasm = std::fs::read(WASM).expect("contract not built, see the benchmark's documentation");
+    let mut instance = mock_instance_with_gas_limit(&wasm, 10_000_000_000);
+
+    let _: Response = instantiate(
+        &mut instance,
+        mock_env(),
+        mock_info("creator", &[]),
+        json!({ "version": "1.0.0" }),
+    )
+    .unwrap();
+
+    println!(
+        "{:<20} {:>8} {:>14} {:>9} {:>10}",
+        "instruction", "payload", "compute_units", "tx_bytes", "rent"
+    );
+
+    for &size in SIZES {
+        let message = Message {
+            nick: "Alice".to_string(),
+            text: "x".repeat(size),
+        };
+        let payload = message.try_to_vec().unwrap().len();
+        let msg = json!({
+            "SendMessage": {
+                "nonce": 0,
+                "nick": message.nick,
+                "text": message.text,
+                "target": null,
+            }
+        });
+        let tx_bytes = serde_json::to_vec(&msg).unwrap().len();
+
+        let before = instance.get_gas_left();
+        let _: Response = execute(&mut instance, mock_env(), mock_info("sender", &[]), msg).unwrap();
+        let gas = before - instance.get_gas_left();
+
+        println!(
+            "{:<20} {:>8} {:>14} {:>9} {:>10}",
+            "send_message", payload, gas, tx_bytes, "-"
+        );
+    }
+}
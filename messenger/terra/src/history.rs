@@ -0,0 +1,133 @@
+//! Message history. Every message sent or received is recorded under an id counting up from 0,
+//! with index buckets for looking it up by VAA and listing it by sender, emitter chain and time.
+//! Listings are paginated by passing the last id of the previous page as `start_after`.
+
+use cosmwasm_std::{
+    Order,
+    StdError,
+    StdResult,
+    Storage,
+};
+use cosmwasm_storage::ReadonlyBucket;
+
+use crate::messages::MessageResponse;
+use crate::state::{
+    by_chain,
+    by_sender,
+    by_time,
+    by_time_read,
+    by_vaa,
+    chain_counts,
+    history,
+    history_count,
+    history_count_read,
+    history_read,
+    sender_counts,
+    time_key,
+    vaa_key,
+    Direction,
+    HistoryEntry,
+};
+
+/// Page size when a listing doesn't give one.
+pub const DEFAULT_LIMIT: u32 = 10;
+
+/// Largest page a listing returns, to bound query gas.
+pub const MAX_LIMIT: u32 = 30;
+
+
+/// Record a message, returning its id.
+pub fn record(storage: &mut dyn Storage, entry: &HistoryEntry) -> StdResult<u64> {
+    let mut count = history_count_read(storage).may_load()?.unwrap_or_default();
+    let id = count.sent + count.received;
+    match entry.direction {
+        Direction::Sent => count.sent += 1,
+        Direction::Received => count.received += 1,
+    }
+    history_count(storage).save(&count)?;
+
+    let key = id.to_be_bytes();
+    history(storage).save(&key, entry)?;
+    if let Some(sequence) = entry.sequence {
+        by_vaa(storage).save(&vaa_key(entry.emitter_chain, &entry.emitter_address, sequence), &id)?;
+    }
+    by_sender(storage, &entry.sender).save(&key, &id)?;
+    by_chain(storage, entry.emitter_chain).save(&key, &id)?;
+    by_time(storage).save(&time_key(entry.timestamp, id), &id)?;
+
+    sender_counts(storage).update(&entry.sender, |count| -> StdResult<_> { Ok(count.unwrap_or(0) + 1) })?;
+    chain_counts(storage).update(&entry.emitter_chain.to_be_bytes(), |count| -> StdResult<_> {
+        Ok(count.unwrap_or(0) + 1)
+    })?;
+    Ok(id)
+}
+
+/// Record the sequence the bridge assigned a sent message, indexing it by its VAA.
+pub fn set_sequence(storage: &mut dyn Storage, id: u64, sequence: u64) -> StdResult<()> {
+    let key = id.to_be_bytes();
+    let mut entry = history_read(storage).load(&key)?;
+    if entry.sequence.is_some() {
+        return Err(StdError::generic_err("Sequence already recorded"));
+    }
+    entry.sequence = Some(sequence);
+    history(storage).save(&key, &entry)?;
+    by_vaa(storage).save(&vaa_key(entry.emitter_chain, &entry.emitter_address, sequence), &id)
+}
+
+/// List a page of an index keyed by id, such as `by_sender_read` or `by_chain_read`.
+pub fn list(
+    storage: &dyn Storage,
+    index: ReadonlyBucket<u64>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<MessageResponse>> {
+    let start = start_after.map(|id| after(&id.to_be_bytes()));
+    page(storage, &index, start, None, limit)
+}
+
+/// List a page of messages with timestamps from `since` until, but not including, `until`.
+pub fn list_by_time(
+    storage: &dyn Storage,
+    since: u64,
+    until: Option<u64>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<MessageResponse>> {
+    let mut start = since.to_be_bytes().to_vec();
+    if let Some(id) = start_after {
+        let entry = history_read(storage).load(&id.to_be_bytes())?;
+        start = start.max(after(&time_key(entry.timestamp, id)));
+    }
+    let end = until.map(|until| until.to_be_bytes().to_vec());
+    page(storage, &by_time_read(storage), Some(start), end, limit)
+}
+
+/// Load the entry for `id`.
+pub fn load(storage: &dyn Storage, id: u64) -> StdResult<MessageResponse> {
+    Ok(MessageResponse {
+        id,
+        message: history_read(storage).load(&id.to_be_bytes())?,
+    })
+}
+
+
+/// Read up to `limit` ids from an index between `start` and `end` and load their entries.
+fn page(
+    storage: &dyn Storage,
+    index: &ReadonlyBucket<u64>,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    limit: Option<u32>,
+) -> StdResult<Vec<MessageResponse>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    index
+        .range(start.as_deref(), end.as_deref(), Order::Ascending)
+        .take(limit)
+        .map(|item| load(storage, item?.1))
+        .collect()
+}
+
+/// The first key after `key` among keys of the same length.
+fn after(key: &[u8]) -> Vec<u8> {
+    [key, &[0]].concat()
+}
@@ -1,91 +1,1537 @@
+use std::convert::TryInto;
+
 use borsh::{
     BorshDeserialize,
     BorshSerialize,
 };
 use cosmwasm_std::{
     entry_point,
+    to_binary,
+    BankMsg,
+    Binary,
+    CanonicalAddr,
+    Coin,
+    Deps,
     DepsMut,
     Env,
     MessageInfo,
+    Reply,
     Response,
     StdError,
     StdResult,
+    Storage,
+    SubMsg,
+    Uint128,
+    WasmMsg,
 };
 use wormhole_sdk::{
     parse_vaa,
     post_message,
 };
 
-use messenger_common::Message;
+use messenger_common::{
+    pad_address,
+    valid_nick,
+    Claim,
+    ContractUpgrade,
+    Envelope,
+    Message,
+    Registration,
+    CHAIN_ID_TERRA,
+};
 
+mod history;
 mod messages;
 use messages::*;
 
+pub mod state;
+use state::{
+    block_key,
+    blocks,
+    blocks_read,
+    by_chain_read,
+    by_sender_read,
+    by_vaa_read,
+    chain_counts_read,
+    config,
+    config_read,
+    consumed,
+    consumed_read,
+    destinations,
+    destinations_read,
+    fees,
+    history_count_read,
+    multisig_sender,
+    multisigs,
+    multisigs_read,
+    nicks,
+    nicks_read,
+    policies,
+    policies_read,
+    policy_key,
+    proposal_key,
+    proposals,
+    proposals_read,
+    sender_counts_read,
+    senders,
+    senders_read,
+    trusted,
+    trusted_read,
+    vaa_key,
+    BlockMode,
+    Config,
+    Destination,
+    Direction,
+    HistoryEntry,
+    MultisigState,
+    ProposalState,
+    RateLimit,
+    SenderState,
+};
+
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    _deps: DepsMut,
+    deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
+    // The instantiator administers rate limits, which start out unlimited, and the protocol fee,
+    // which starts at zero.
+    config(deps.storage).save(&Config {
+        admin:          deps.api.addr_canonicalize(info.sender.as_str())?,
+        sender_limit:   RateLimit::default(),
+        protocol_fee:   Coin::new(0, "uluna"),
+        send_paused:    false,
+        receive_paused: false,
+
+        governance_chain:   0,
+        governance_emitter: vec![],
+    })?;
+
     Ok(Response::default().add_attribute("version", msg.version))
 }
 
+/// Run by the runtime when a governance upgrade migrates the contract to new code.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
+    Ok(Response::default())
+}
+
+/// Run when the bridge has posted a message sent by `post_envelope`, whose history id is the reply
+/// id. The bridge reports the sequence it assigned in a `message.sequence` attribute.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> StdResult<Response> {
+    let result = msg.result.into_result().map_err(StdError::generic_err)?;
+    let sequence = result
+        .events
+        .iter()
+        .flat_map(|event| event.attributes.iter())
+        .find(|attribute| attribute.key == "message.sequence")
+        .and_then(|attribute| attribute.value.parse::<u64>().ok())
+        .ok_or_else(|| StdError::generic_err("Missing message sequence"))?;
+    history::set_sequence(deps.storage, msg.id, sequence)?;
+    Ok(Response::default().add_attribute("sequence", sequence.to_string()))
+}
+
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+pub fn execute(mut deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    check_paused(deps.storage, &msg)?;
+
     match msg {
         // Emit a new message targetting an address on a foreign chain. The message is emitted via
-        // Wormhole and routed by the Guardians to the destination contract.
-        ExecuteMsg::SendMessage { nonce, nick, text } => Ok(Response::default()
-            .add_attribute("action", "send_message")
-            .add_message(post_message(
-                nonce,
-                &Message { nick, text }
-                    .try_to_vec()
-                    .map_err(|_| StdError::generic_err("Encoding Failed"))?,
-            )?)),
+        // Wormhole and routed by the Guardians to the destination contract. The envelope records
+        // the canonical address of whoever sent it.
+        ExecuteMsg::SendMessage {
+            nonce,
+            nick,
+            text,
+            target,
+            recipient,
+            target_chain,
+        } => {
+            let target = target
+                .map(|target| target.as_slice().try_into())
+                .transpose()
+                .map_err(|_| StdError::generic_err("Target must be 32 bytes"))?;
+            let recipient = recipient
+                .map(|recipient| recipient.as_slice().try_into())
+                .transpose()
+                .map_err(|_| StdError::generic_err("Recipient must be 32 bytes"))?;
+
+            let sender = deps.api.addr_canonicalize(info.sender.as_str())?;
+            charge_fee(deps.storage, &info)?;
+            consume_limits(deps.storage, &sender, target_chain, env.block.time.seconds())?;
+
+            let envelope = Envelope {
+                sender: pad_address(sender.as_slice()),
+                target_chain,
+                target,
+                recipient,
+                room: None,
+                message: Message { nick, text },
+            };
+
+            Ok(Response::default()
+                .add_attribute("action", "send_message")
+                .add_submessage(post_envelope(deps, &env, nonce, envelope)?))
+        }
 
         // Receive a VAA containing a message from another chain. The message is stored in the
-        // Terra contract state and can be read out via QueryMsg.
+        // Terra contract state and can be read out via QueryMsg, see `history`.
         ExecuteMsg::RecvMessage { vaa } => {
-            // Parse VAA and decode Payload into message.
-            let vaa = parse_vaa(deps, env, &vaa)?;
-            let msg = Message::try_from_slice(&vaa.payload)
+            // Parse VAA and decode Payload into message. The bridge refuses VAAs signed by a
+            // guardian set that has expired by the current block, so only the emitter's policy is
+            // left to check.
+            let vaa = parse_vaa(deps.branch(), env, &vaa)?;
+
+            // Nickname registrations from other Messenger deployments go into the directory,
+            // unless the nick was registered first elsewhere. They must be final, as routed
+            // messages must.
+            if let Some(registration) = Registration::parse(&vaa.payload) {
+                check_policy(deps.storage, vaa.emitter_chain, &vaa.emitter_address, vaa.consistency_level, true)?;
+                let key = policy_key(vaa.emitter_chain, &vaa.emitter_address);
+                if !trusted_read(deps.storage).may_load(&key)?.unwrap_or(false) {
+                    return Err(StdError::generic_err("Emitter not trusted"));
+                }
+
+                let claim = Claim {
+                    chain: vaa.emitter_chain,
+                    owner: registration.owner,
+                    time:  u64::from(vaa.timestamp),
+                };
+                let registered = claim_nick(deps.storage, &registration.nick, claim)?;
+                return Ok(Response::default()
+                    .add_attribute("action", "receive_registration")
+                    .add_attribute("nick", registration.nick)
+                    .add_attribute("registered", registered.to_string()));
+            }
+
+            let envelope = Envelope::try_from_slice(&vaa.payload)
                 .map_err(|_| StdError::generic_err("Invalid Message"))?;
 
+            // Messages for another chain would otherwise get past that chain's destination cap.
+            if envelope.target_chain != 0 && envelope.target_chain != CHAIN_ID_TERRA {
+                return Err(StdError::generic_err("Wrong target chain"));
+            }
+            check_policy(
+                deps.storage,
+                vaa.emitter_chain,
+                &vaa.emitter_address,
+                vaa.consistency_level,
+                envelope.target.is_some(),
+            )?;
+
+            // A direct message's recipient may have blocked or muted the sender. Blocked messages
+            // are dropped without failing, so relayers needn't check block lists.
+            let mode = match envelope.recipient {
+                Some(recipient) => {
+                    let key = block_key(&recipient, vaa.emitter_chain, &envelope.sender);
+                    blocks_read(deps.storage).may_load(&key)?
+                }
+                None => None,
+            };
+            let sender = Binary::from(&envelope.sender[..]).to_base64();
+            if mode == Some(BlockMode::Block) {
+                return Ok(Response::default()
+                    .add_attribute("action", "drop_message")
+                    .add_attribute("sender", sender));
+            }
+
+            // Nicks are only verified when registered to the sender on the emitting chain, and the
+            // emitter is a trusted Messenger that can't have forged the sender.
+            let key = policy_key(vaa.emitter_chain, &vaa.emitter_address);
+            let messenger = trusted_read(deps.storage).may_load(&key)?.unwrap_or(false);
+            let verified = messenger
+                && nicks_read(deps.storage)
+                    .may_load(envelope.message.nick.as_bytes())?
+                    .map_or(false, |entry| entry.chain == vaa.emitter_chain && entry.owner == envelope.sender);
+            let quarantined = mode == Some(BlockMode::Mute);
+            let msg = envelope.message;
+
+            // VAAs may be submitted more than once, but are only recorded the first time.
+            let key = vaa_key(vaa.emitter_chain, &vaa.emitter_address, vaa.sequence);
+            if by_vaa_read(deps.storage).may_load(&key)?.is_none() {
+                let entry = HistoryEntry {
+                    direction:         Direction::Received,
+                    emitter_chain:     vaa.emitter_chain,
+                    emitter_address:   Binary::from(vaa.emitter_address),
+                    sequence:          Some(vaa.sequence),
+                    nonce:             vaa.nonce,
+                    consistency_level: Some(vaa.consistency_level),
+                    timestamp:         u64::from(vaa.timestamp),
+                    sender:            Binary::from(&envelope.sender[..]),
+                    target_chain:      envelope.target_chain,
+                    nick:              msg.nick.clone(),
+                    text:              msg.text.clone(),
+                    verified,
+                    quarantined,
+                };
+                history::record(deps.storage, &entry)?;
+            }
+
             Ok(Response::default()
                 .add_attribute("action", "receive_message")
+                .add_attribute("sender", sender)
+                .add_attribute("verified", verified.to_string())
+                .add_attribute("quarantined", quarantined.to_string())
                 .add_attribute("nick", msg.nick)
                 .add_attribute("text", msg.text))
         }
+
+        // Emergency pause, see `check_paused`.
+        ExecuteMsg::SetPaused { send, receive } => {
+            let mut cfg = check_admin(deps.as_ref(), &info)?;
+            cfg.send_paused = send;
+            cfg.receive_paused = receive;
+            config(deps.storage).save(&cfg)?;
+            Ok(Response::default()
+                .add_attribute("action", "set_paused")
+                .add_attribute("send", send.to_string())
+                .add_attribute("receive", receive.to_string()))
+        }
+
+        // Rate limit administration, see `consume_limits`.
+        ExecuteMsg::SetRateLimit { limit } => {
+            let mut cfg = check_admin(deps.as_ref(), &info)?;
+            check_rate_limit(&limit)?;
+            cfg.sender_limit = limit;
+            config(deps.storage).save(&cfg)?;
+            Ok(Response::default().add_attribute("action", "set_rate_limit"))
+        }
+
+        ExecuteMsg::SetDestinationLimit { chain, limit } => {
+            check_admin(deps.as_ref(), &info)?;
+            check_rate_limit(&limit)?;
+            let key = chain.to_be_bytes();
+            let usage = destinations_read(deps.storage)
+                .may_load(&key)?
+                .map(|destination| destination.usage)
+                .unwrap_or_default();
+            destinations(deps.storage).save(&key, &Destination { limit, usage })?;
+            Ok(Response::default()
+                .add_attribute("action", "set_destination_limit")
+                .add_attribute("chain", chain.to_string()))
+        }
+
+        ExecuteMsg::SetExempt { address, exempt } => {
+            check_admin(deps.as_ref(), &info)?;
+            let address = deps.api.addr_canonicalize(&address)?;
+            senders(deps.storage).update(address.as_slice(), |state| -> StdResult<_> {
+                Ok(SenderState {
+                    exempt,
+                    ..state.unwrap_or_default()
+                })
+            })?;
+            Ok(Response::default().add_attribute("action", "set_exempt"))
+        }
+
+        ExecuteMsg::SetEmitterPolicy {
+            chain,
+            emitter,
+            policy,
+        } => {
+            check_admin(deps.as_ref(), &info)?;
+            if emitter.len() != 32 {
+                return Err(StdError::generic_err("Emitter must be 32 bytes"));
+            }
+            policies(deps.storage).save(&policy_key(chain, &emitter), &policy)?;
+            Ok(Response::default()
+                .add_attribute("action", "set_emitter_policy")
+                .add_attribute("chain", chain.to_string()))
+        }
+
+        // Protocol fee administration, fees are collected by `check_fee`.
+        ExecuteMsg::SetProtocolFee { fee } => {
+            let mut cfg = check_admin(deps.as_ref(), &info)?;
+            cfg.protocol_fee = fee;
+            config(deps.storage).save(&cfg)?;
+            Ok(Response::default().add_attribute("action", "set_protocol_fee"))
+        }
+
+        ExecuteMsg::WithdrawFees { amount, recipient } => {
+            check_admin(deps.as_ref(), &info)?;
+            let recipient = deps.api.addr_validate(&recipient)?;
+            fees(deps.storage).update(amount.denom.as_bytes(), |accrued| {
+                accrued
+                    .unwrap_or_default()
+                    .checked_sub(amount.amount)
+                    .map_err(|_| StdError::generic_err("Withdrawal exceeds collected fees"))
+            })?;
+            Ok(Response::default()
+                .add_attribute("action", "withdraw_fees")
+                .add_message(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount:     vec![amount],
+                }))
+        }
+
+        // Register a nickname here and post the registration for other chains, which costs and is
+        // limited as a message is.
+        ExecuteMsg::RegisterNick { nonce, nick } => {
+            let sender = deps.api.addr_canonicalize(info.sender.as_str())?;
+            charge_fee(deps.storage, &info)?;
+            consume_limits(deps.storage, &sender, 0, env.block.time.seconds())?;
+
+            // Anything already in the directory was registered before now.
+            let claim = Claim {
+                chain: CHAIN_ID_TERRA,
+                owner: pad_address(sender.as_slice()),
+                time:  env.block.time.seconds(),
+            };
+            if !claim_nick(deps.storage, &nick, claim)? {
+                return Err(StdError::generic_err("Nickname taken"));
+            }
+
+            let registration = Registration {
+                owner: claim.owner,
+                nick,
+            };
+            Ok(Response::default()
+                .add_attribute("action", "register_nick")
+                .add_attribute("nick", registration.nick.clone())
+                .add_message(post_message(nonce, &registration.payload())?))
+        }
+
+        ExecuteMsg::SetTrustedMessenger {
+            chain,
+            emitter,
+            trusted: trust,
+        } => {
+            check_admin(deps.as_ref(), &info)?;
+            if emitter.len() != 32 {
+                return Err(StdError::generic_err("Emitter must be 32 bytes"));
+            }
+            trusted(deps.storage).save(&policy_key(chain, &emitter), &trust)?;
+            Ok(Response::default()
+                .add_attribute("action", "set_trusted_messenger")
+                .add_attribute("chain", chain.to_string()))
+        }
+
+        // Governance, following the core bridge's `Governance.sol`.
+        ExecuteMsg::SetGovernance { chain, emitter } => {
+            let mut cfg = check_admin(deps.as_ref(), &info)?;
+            if cfg.governance_chain != 0 {
+                return Err(StdError::generic_err("Governance already set"));
+            }
+            if chain == 0 || emitter.len() != 32 {
+                return Err(StdError::generic_err("Invalid governance emitter"));
+            }
+            cfg.governance_chain = chain;
+            cfg.governance_emitter = emitter.to_vec();
+            config(deps.storage).save(&cfg)?;
+            Ok(Response::default().add_attribute("action", "set_governance"))
+        }
+
+        ExecuteMsg::SubmitUpgrade { vaa } => {
+            let contract = env.contract.address.to_string();
+            let vaa = parse_vaa(deps.branch(), env, &vaa)?;
+
+            // As in `Governance.sol`, only the current guardian set may sign governance.
+            let current: WormholeGuardianSetInfoResponse = deps
+                .querier
+                .query_wasm_smart(wormhole_sdk::id().to_string(), &WormholeQueryMsg::GuardianSetInfo {})?;
+            check_governance_signers(vaa.guardian_set_index, current.guardian_set_index)?;
+
+            let cfg = config_read(deps.storage).load()?;
+            if cfg.governance_chain == 0
+                || vaa.emitter_chain != cfg.governance_chain
+                || vaa.emitter_address != cfg.governance_emitter
+            {
+                return Err(StdError::generic_err("Invalid governance VAA"));
+            }
+            if consumed_read(deps.storage).may_load(&vaa.hash)?.unwrap_or(false) {
+                return Err(StdError::generic_err("Governance VAA already consumed"));
+            }
+            consumed(deps.storage).save(&vaa.hash, &true)?;
+
+            let new_code_id = upgrade_code_id(&vaa.payload)?;
+            Ok(Response::default()
+                .add_attribute("action", "submit_upgrade")
+                .add_attribute("new_code_id", new_code_id.to_string())
+                .add_message(WasmMsg::Migrate {
+                    contract_addr: contract,
+                    new_code_id,
+                    msg: to_binary(&MigrateMsg {})?,
+                }))
+        }
+
+        // Block lists belong to the caller, as the recipient messages are targetted at.
+        ExecuteMsg::SetBlock { chain, sender, mode } => {
+            if sender.len() != 32 {
+                return Err(StdError::generic_err("Sender must be 32 bytes"));
+            }
+            let recipient = deps.api.addr_canonicalize(info.sender.as_str())?;
+            let key = block_key(&pad_address(recipient.as_slice()), chain, &sender);
+            match mode {
+                Some(mode) => blocks(deps.storage).save(&key, &mode)?,
+                None => blocks(deps.storage).remove(&key),
+            }
+            Ok(Response::default()
+                .add_attribute("action", "set_block")
+                .add_attribute("chain", chain.to_string()))
+        }
+
+        // Multisig messages, sent as the multisig once enough of its signers approve them.
+        ExecuteMsg::CreateMultisig {
+            id,
+            signers,
+            threshold,
+        } => {
+            if multisigs_read(deps.storage).may_load(&id.to_be_bytes())?.is_some() {
+                return Err(StdError::generic_err("Multisig exists"));
+            }
+            let signers = signers
+                .iter()
+                .map(|signer| deps.api.addr_canonicalize(signer))
+                .collect::<StdResult<Vec<_>>>()?;
+            let duplicate = signers.iter().enumerate().any(|(i, signer)| signers[..i].contains(signer));
+            if signers.is_empty() || duplicate || threshold == 0 || threshold as usize > signers.len() {
+                return Err(StdError::generic_err("Invalid multisig"));
+            }
+
+            let state = MultisigState {
+                signers,
+                threshold,
+                proposals: 0,
+            };
+            multisigs(deps.storage).save(&id.to_be_bytes(), &state)?;
+            Ok(Response::default()
+                .add_attribute("action", "create_multisig")
+                .add_attribute("multisig", id.to_string()))
+        }
+
+        ExecuteMsg::Propose {
+            multisig,
+            nonce,
+            nick,
+            text,
+            expiry,
+            target_chain,
+        } => {
+            let mut state = load_multisig(deps.storage, multisig)?;
+            let proposer = check_signer(deps.as_ref(), &info, &state)?;
+
+            let proposal = state.proposals;
+            let pending = ProposalState {
+                proposer,
+                nonce,
+                nick,
+                text,
+                target_chain,
+                expiry,
+                approvals: vec![],
+            };
+            proposals(deps.storage).save(&proposal_key(multisig, proposal), &pending)?;
+            state.proposals += 1;
+            multisigs(deps.storage).save(&multisig.to_be_bytes(), &state)?;
+            Ok(Response::default()
+                .add_attribute("action", "propose")
+                .add_attribute("proposal", proposal.to_string()))
+        }
+
+        ExecuteMsg::Approve { multisig, proposal } => {
+            let state = load_multisig(deps.storage, multisig)?;
+            let signer = check_signer(deps.as_ref(), &info, &state)?;
+            let key = proposal_key(multisig, proposal);
+            let mut pending = load_proposal(deps.storage, &key)?;
+            if env.block.time.seconds() >= pending.expiry {
+                return Err(StdError::generic_err("Proposal expired"));
+            }
+            if pending.approvals.contains(&signer) {
+                return Err(StdError::generic_err("Already approved"));
+            }
+
+            pending.approvals.push(signer);
+            let response = Response::default()
+                .add_attribute("action", "approve")
+                .add_attribute("votes", pending.approvals.len().to_string());
+            if pending.approvals.len() < state.threshold as usize {
+                proposals(deps.storage).save(&key, &pending)?;
+                return Ok(response.add_attribute("sent", "false"));
+            }
+
+            // The threshold is met, so the message is sent, costing and limited as any other.
+            let sender = multisig_sender(multisig);
+            charge_fee(deps.storage, &info)?;
+            consume_limits(
+                deps.storage,
+                &CanonicalAddr::from(sender.to_vec()),
+                pending.target_chain,
+                env.block.time.seconds(),
+            )?;
+            proposals(deps.storage).remove(&key);
+
+            let envelope = Envelope {
+                sender,
+                target_chain: pending.target_chain,
+                target: None,
+                recipient: None,
+                room: None,
+                message: Message {
+                    nick: pending.nick,
+                    text: pending.text,
+                },
+            };
+            Ok(response
+                .add_attribute("sent", "true")
+                .add_submessage(post_envelope(deps, &env, pending.nonce, envelope)?))
+        }
+
+        ExecuteMsg::CancelProposal { multisig, proposal } => {
+            let state = load_multisig(deps.storage, multisig)?;
+            let key = proposal_key(multisig, proposal);
+            let pending = load_proposal(deps.storage, &key)?;
+            if env.block.time.seconds() < pending.expiry {
+                check_signer(deps.as_ref(), &info, &state)?;
+            }
+            proposals(deps.storage).remove(&key);
+            Ok(Response::default()
+                .add_attribute("action", "cancel_proposal")
+                .add_attribute("proposal", proposal.to_string()))
+        }
     }
 }
 
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::SendCost {} => {
+            let bridge: WormholeStateResponse = deps
+                .querier
+                .query_wasm_smart(wormhole_sdk::id().to_string(), &WormholeQueryMsg::GetState {})?;
+            to_binary(&SendCostResponse {
+                bridge_fee:   bridge.fee,
+                protocol_fee: config_read(deps.storage).load()?.protocol_fee,
+            })
+        }
+
+        QueryMsg::Paused {} => {
+            let cfg = config_read(deps.storage).load()?;
+            to_binary(&PausedResponse {
+                send:    cfg.send_paused,
+                receive: cfg.receive_paused,
+            })
+        }
+
+        QueryMsg::Nick { nick } => {
+            let entry = nicks_read(deps.storage).may_load(nick.as_bytes())?;
+            to_binary(&entry.map(|entry| NickResponse {
+                chain: entry.chain,
+                owner: Binary::from(&entry.owner[..]),
+                time:  entry.time,
+            }))
+        }
+
+        QueryMsg::Blocked {
+            recipient,
+            chain,
+            sender,
+        } => {
+            let recipient = deps.api.addr_canonicalize(&recipient)?;
+            let key = block_key(&pad_address(recipient.as_slice()), chain, &sender);
+            to_binary(&blocks_read(deps.storage).may_load(&key)?)
+        }
+
+        QueryMsg::Multisig { id } => {
+            let state = load_multisig(deps.storage, id)?;
+            to_binary(&MultisigResponse {
+                signers:   humanize(deps, &state.signers)?,
+                threshold: state.threshold,
+                sender:    Binary::from(&multisig_sender(id)[..]),
+                proposals: state.proposals,
+            })
+        }
+
+        QueryMsg::Proposal { multisig, proposal } => {
+            let pending = proposals_read(deps.storage).may_load(&proposal_key(multisig, proposal))?;
+            let response = pending
+                .map(|pending| -> StdResult<_> {
+                    Ok(ProposalResponse {
+                        proposer:     deps.api.addr_humanize(&pending.proposer)?.to_string(),
+                        nick:         pending.nick,
+                        text:         pending.text,
+                        target_chain: pending.target_chain,
+                        expiry:       pending.expiry,
+                        approvals:    humanize(deps, &pending.approvals)?,
+                    })
+                })
+                .transpose()?;
+            to_binary(&response)
+        }
+
+        QueryMsg::Message {
+            chain,
+            emitter,
+            sequence,
+        } => {
+            let id = by_vaa_read(deps.storage).may_load(&vaa_key(chain, &emitter, sequence))?;
+            to_binary(&id.map(|id| history::load(deps.storage, id)).transpose()?)
+        }
+
+        QueryMsg::MessagesBySender {
+            sender,
+            start_after,
+            limit,
+        } => {
+            let index = by_sender_read(deps.storage, &sender);
+            let messages = history::list(deps.storage, index, start_after, limit)?;
+            to_binary(&MessagesResponse { messages })
+        }
+
+        QueryMsg::MessagesByChain {
+            chain,
+            start_after,
+            limit,
+        } => {
+            let index = by_chain_read(deps.storage, chain);
+            let messages = history::list(deps.storage, index, start_after, limit)?;
+            to_binary(&MessagesResponse { messages })
+        }
+
+        QueryMsg::MessagesByTime {
+            since,
+            until,
+            start_after,
+            limit,
+        } => {
+            let messages = history::list_by_time(deps.storage, since, until, start_after, limit)?;
+            to_binary(&MessagesResponse { messages })
+        }
+
+        QueryMsg::MessageCount { sender, chain } => {
+            let count = match (sender, chain) {
+                (None, None) => {
+                    let count = history_count_read(deps.storage).may_load()?.unwrap_or_default();
+                    count.sent + count.received
+                }
+                (Some(sender), None) => sender_counts_read(deps.storage).may_load(&sender)?.unwrap_or(0),
+                (None, Some(chain)) => chain_counts_read(deps.storage).may_load(&chain.to_be_bytes())?.unwrap_or(0),
+                (Some(_), Some(_)) => return Err(StdError::generic_err("Count by sender or by chain, not both")),
+            };
+            to_binary(&MessageCountResponse { count })
+        }
+    }
+}
+
+/// Claim `nick` in the directory, unless an existing claim wins over this one, see
+/// `Claim::precedes`. Returns whether the directory was changed.
+fn claim_nick(storage: &mut dyn Storage, nick: &str, claim: Claim) -> StdResult<bool> {
+    if !valid_nick(nick) {
+        return Err(StdError::generic_err("Invalid nickname"));
+    }
+
+    if let Some(existing) = nicks_read(storage).may_load(nick.as_bytes())? {
+        if !claim.precedes(&existing.into()) {
+            return Ok(false);
+        }
+    }
+    nicks(storage).save(nick.as_bytes(), &claim.into())?;
+    Ok(true)
+}
+
+/// The code id a governance payload upgrades the contract to. Code ids fit in the last 8 bytes of
+/// the upgrade's 32 byte new contract, the rest must be zero.
+fn upgrade_code_id(payload: &[u8]) -> StdResult<u64> {
+    let upgrade = ContractUpgrade::parse(payload)
+        .filter(|upgrade| upgrade.chain == CHAIN_ID_TERRA && upgrade.new_contract[..24] == [0u8; 24])
+        .ok_or_else(|| StdError::generic_err("Invalid governance VAA"))?;
+    Ok(u64::from_be_bytes(upgrade.new_contract[24..].try_into().unwrap()))
+}
+
+/// Check a governance VAA was signed by the `current` guardian set.
+fn check_governance_signers(guardian_set_index: u32, current: u32) -> StdResult<()> {
+    if guardian_set_index != current {
+        return Err(StdError::generic_err("Guardian set not current"));
+    }
+    Ok(())
+}
+
+/// Refuse sends and receives while the admin has paused them.
+fn check_paused(storage: &dyn Storage, msg: &ExecuteMsg) -> StdResult<()> {
+    let cfg = config_read(storage).load()?;
+    match msg {
+        ExecuteMsg::SendMessage { .. } | ExecuteMsg::RegisterNick { .. } | ExecuteMsg::Approve { .. }
+            if cfg.send_paused =>
+        {
+            Err(StdError::generic_err("Sending paused"))
+        }
+        ExecuteMsg::RecvMessage { .. } if cfg.receive_paused => Err(StdError::generic_err("Receiving paused")),
+        _ => Ok(()),
+    }
+}
+
+/// Check the sender attached exactly the protocol fee, and add it to the fees the admin may
+/// withdraw.
+fn charge_fee(storage: &mut dyn Storage, info: &MessageInfo) -> StdResult<()> {
+    let fee = config_read(storage).load()?.protocol_fee;
+    let paid = info
+        .funds
+        .iter()
+        .filter(|coin| coin.denom == fee.denom)
+        .fold(Uint128::zero(), |total, coin| total + coin.amount);
+    if paid < fee.amount {
+        return Err(StdError::generic_err("Insufficient protocol fee"));
+    }
+
+    // Anything on top of the fee would be stuck in the contract, so it is refused.
+    let other = info.funds.iter().any(|coin| coin.denom != fee.denom && !coin.amount.is_zero());
+    if paid > fee.amount || other {
+        return Err(StdError::generic_err("Attach exactly the protocol fee"));
+    }
+
+    fees(storage).update(fee.denom.as_bytes(), |accrued| -> StdResult<_> {
+        Ok(accrued.unwrap_or_default() + fee.amount)
+    })?;
+    Ok(())
+}
+
+/// Check a received VAA's consistency level against its emitter's policy, if it has one. Routed
+/// messages, which may carry value, must also have been emitted once final on their chain.
+fn check_policy(
+    storage: &dyn Storage,
+    chain: u16,
+    emitter: &[u8],
+    consistency_level: u8,
+    routed: bool,
+) -> StdResult<()> {
+    let policy = match policies_read(storage).may_load(&policy_key(chain, emitter))? {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+
+    if consistency_level < policy.min_consistency {
+        return Err(StdError::generic_err("Insufficient consistency level"));
+    }
+    match policy.finality {
+        Some(finality) if routed && consistency_level < finality => Err(StdError::generic_err("Message not final")),
+        _ => Ok(()),
+    }
+}
+
+/// Load the config, failing unless the message was sent by the admin.
+fn check_admin(deps: Deps, info: &MessageInfo) -> StdResult<Config> {
+    let cfg = config_read(deps.storage).load()?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != cfg.admin {
+        return Err(StdError::generic_err("Unauthorized"));
+    }
+    Ok(cfg)
+}
+
+/// Post an envelope from this contract, recording it in the history as sent. The bridge assigns
+/// the sequence when the post executes, so it is recorded by `reply`.
+fn post_envelope(deps: DepsMut, env: &Env, nonce: u32, envelope: Envelope) -> StdResult<SubMsg> {
+    let payload = envelope
+        .try_to_vec()
+        .map_err(|_| StdError::generic_err("Encoding Failed"))?;
+    let emitter = deps.api.addr_canonicalize(env.contract.address.as_str())?;
+    let entry = HistoryEntry {
+        direction:         Direction::Sent,
+        emitter_chain:     CHAIN_ID_TERRA,
+        emitter_address:   Binary::from(&pad_address(emitter.as_slice())[..]),
+        sequence:          None,
+        nonce,
+        consistency_level: None,
+        timestamp:         env.block.time.seconds(),
+        sender:            Binary::from(&envelope.sender[..]),
+        target_chain:      envelope.target_chain,
+        nick:              envelope.message.nick,
+        text:              envelope.message.text,
+        verified:          false,
+        quarantined:       false,
+    };
+    let id = history::record(deps.storage, &entry)?;
+    Ok(SubMsg::reply_on_success(post_message(nonce, &payload)?, id))
+}
+
+/// Check the message was sent by one of the multisig's signers, returning their address.
+fn check_signer(deps: Deps, info: &MessageInfo, multisig: &MultisigState) -> StdResult<CanonicalAddr> {
+    let signer = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if !multisig.signers.contains(&signer) {
+        return Err(StdError::generic_err("Not a multisig signer"));
+    }
+    Ok(signer)
+}
+
+fn load_multisig(storage: &dyn Storage, id: u64) -> StdResult<MultisigState> {
+    multisigs_read(storage)
+        .may_load(&id.to_be_bytes())?
+        .ok_or_else(|| StdError::generic_err("Unknown multisig"))
+}
+
+fn load_proposal(storage: &dyn Storage, key: &[u8]) -> StdResult<ProposalState> {
+    proposals_read(storage)
+        .may_load(key)?
+        .ok_or_else(|| StdError::generic_err("Unknown proposal"))
+}
+
+fn humanize(deps: Deps, addresses: &[CanonicalAddr]) -> StdResult<Vec<String>> {
+    addresses
+        .iter()
+        .map(|address| Ok(deps.api.addr_humanize(address)?.to_string()))
+        .collect()
+}
+
+/// Refuse rate limits that could never apply, see `RateLimit::is_valid`.
+fn check_rate_limit(limit: &RateLimit) -> StdResult<()> {
+    if !limit.is_valid() {
+        return Err(StdError::generic_err("Rate limit window must not be 0"));
+    }
+    Ok(())
+}
+
+/// Count one message from `sender` to `target_chain` against the sender's rate limit and the
+/// destination chain's cap, failing if either is used up. Exempt senders skip both.
+fn consume_limits(storage: &mut dyn Storage, sender: &CanonicalAddr, target_chain: u16, now: u64) -> StdResult<()> {
+    let mut state = senders_read(storage).may_load(sender.as_slice())?.unwrap_or_default();
+    if state.exempt {
+        return Ok(());
+    }
+
+    let cfg = config_read(storage).load()?;
+    if !state.usage.consume(&cfg.sender_limit, now) {
+        return Err(StdError::generic_err("Sender rate limit exceeded"));
+    }
+
+    let key = target_chain.to_be_bytes();
+    if let Some(mut destination) = destinations_read(storage).may_load(&key)? {
+        if !destination.usage.consume(&destination.limit, now) {
+            return Err(StdError::generic_err("Destination chain cap reached"));
+        }
+        destinations(storage).save(&key, &destination)?;
+    }
+
+    senders(storage).save(sender.as_slice(), &state)
+}
+
 #[cfg(test)]
 mod testing {
     use cosmwasm_std::testing::{
         mock_dependencies,
         mock_env,
         mock_info,
+        MOCK_CONTRACT_ADDR,
     };
     use cosmwasm_std::{
+        coins,
         Attribute,
+        BankMsg,
         Binary,
+        Coin,
+        ContractResult,
         CosmosMsg,
+        Event,
+        Reply,
         SubMsg,
+        SubMsgExecutionResponse,
         WasmMsg,
     };
-    use messenger_common::Message;
+    use borsh::BorshSerialize;
+    use cosmwasm_std::Api;
+    use cosmwasm_std::from_binary;
+    use messenger_common::{
+        pad_address,
+        Claim,
+        ContractUpgrade,
+        Envelope,
+        Message,
+        CHAIN_ID_TERRA,
+    };
 
     use super::{
+        check_governance_signers,
+        check_policy,
+        claim_nick,
         execute,
+        history,
         instantiate,
+        query,
+        reply,
+        upgrade_code_id,
         ExecuteMsg,
         InstantiateMsg,
+        MessageCountResponse,
+        MessageResponse,
+        MessagesResponse,
+        MultisigResponse,
+        NickResponse,
+        PausedResponse,
+        ProposalResponse,
+        QueryMsg,
+        RateLimit,
+    };
+    use crate::state::{
+        multisig_sender,
+        BlockMode,
+        Direction,
+        EmitterPolicy,
+        HistoryEntry,
     };
 
+    fn send(chain: u16) -> ExecuteMsg {
+        ExecuteMsg::SendMessage {
+            nonce:        0,
+            nick:         "Bob".to_string(),
+            text:         "Hello Alice".to_string(),
+            target:       None,
+            recipient:    None,
+            target_chain: chain,
+        }
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let mut deps = mock_dependencies(&[]);
+        let admin = mock_info("admin", &[]);
+        let user = mock_info("addr0000", &[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), instantiate_msg).unwrap();
+
+        // Limits without a window to count them over are refused.
+        let broken = ExecuteMsg::SetRateLimit {
+            limit: RateLimit { window: 0, limit: 1 },
+        };
+        let error = execute(deps.as_mut(), mock_env(), admin.clone(), broken).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Rate limit window must not be 0");
+        let broken = ExecuteMsg::SetDestinationLimit {
+            chain: 2,
+            limit: RateLimit { window: 0, limit: 1 },
+        };
+        assert!(execute(deps.as_mut(), mock_env(), admin.clone(), broken).is_err());
+
+        // Only the admin may set limits.
+        let hourly = ExecuteMsg::SetRateLimit {
+            limit: RateLimit { window: 3600, limit: 1 },
+        };
+        assert!(execute(deps.as_mut(), mock_env(), user.clone(), hourly.clone()).is_err());
+        execute(deps.as_mut(), mock_env(), admin.clone(), hourly).unwrap();
+
+        // One message an hour, the second is refused until the window passes.
+        execute(deps.as_mut(), mock_env(), user.clone(), send(0)).unwrap();
+        let error = execute(deps.as_mut(), mock_env(), user.clone(), send(0)).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Sender rate limit exceeded");
+
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(3600);
+        execute(deps.as_mut(), later, user.clone(), send(0)).unwrap();
+
+        // Exempt senders aren't limited.
+        let exempt = ExecuteMsg::SetExempt {
+            address: "addr0000".to_string(),
+            exempt:  true,
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), exempt).unwrap();
+        execute(deps.as_mut(), mock_env(), user.clone(), send(0)).unwrap();
+
+        // Destination caps apply to other senders, and only to the capped chain.
+        let cap = ExecuteMsg::SetDestinationLimit {
+            chain: 2,
+            limit: RateLimit { window: 3600, limit: 1 },
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), cap).unwrap();
+        execute(deps.as_mut(), mock_env(), admin, send(2)).unwrap();
+
+        let other = mock_info("addr0001", &[]);
+        let error = execute(deps.as_mut(), mock_env(), other.clone(), send(2)).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Destination chain cap reached");
+        execute(deps.as_mut(), mock_env(), other, send(3)).unwrap();
+    }
+
+    #[test]
+    fn test_emitter_policy() {
+        let mut deps = mock_dependencies(&[]);
+        let admin = mock_info("admin", &[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), instantiate_msg).unwrap();
+
+        // Without a policy any level is accepted.
+        let emitter = [7u8; 32];
+        check_policy(&deps.storage, 1, &emitter, 1, true).unwrap();
+
+        // Confirmed messages are accepted, but routed ones only once finalized.
+        let set_policy = ExecuteMsg::SetEmitterPolicy {
+            chain:   1,
+            emitter: Binary::from(&emitter[..]),
+            policy:  EmitterPolicy {
+                min_consistency: 1,
+                finality:        Some(32),
+            },
+        };
+        let user = mock_info("addr0000", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), user, set_policy.clone()).is_err());
+        execute(deps.as_mut(), mock_env(), admin, set_policy).unwrap();
+
+        let error = check_policy(&deps.storage, 1, &emitter, 0, false).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Insufficient consistency level");
+        check_policy(&deps.storage, 1, &emitter, 1, false).unwrap();
+        let error = check_policy(&deps.storage, 1, &emitter, 1, true).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Message not final");
+        check_policy(&deps.storage, 1, &emitter, 32, true).unwrap();
+
+        // Policies are per emitter.
+        check_policy(&deps.storage, 2, &emitter, 0, true).unwrap();
+    }
+
+    #[test]
+    fn test_protocol_fee() {
+        let mut deps = mock_dependencies(&[]);
+        let admin = mock_info("admin", &[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), instantiate_msg).unwrap();
+
+        // Only the admin may set the fee.
+        let set_fee = ExecuteMsg::SetProtocolFee {
+            fee: Coin::new(100, "uluna"),
+        };
+        let user = mock_info("addr0000", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), user, set_fee.clone()).is_err());
+        execute(deps.as_mut(), mock_env(), admin.clone(), set_fee).unwrap();
+
+        // Senders must attach the fee in the right denomination.
+        let short = mock_info("addr0000", &coins(99, "uluna"));
+        let error = execute(deps.as_mut(), mock_env(), short, send(0)).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Insufficient protocol fee");
+        let wrong = mock_info("addr0000", &coins(100, "uusd"));
+        assert!(execute(deps.as_mut(), mock_env(), wrong, send(0)).is_err());
+
+        // Overpaying would leave funds nobody can withdraw, so it is refused too.
+        let over = mock_info("addr0000", &coins(101, "uluna"));
+        let error = execute(deps.as_mut(), mock_env(), over, send(0)).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Attach exactly the protocol fee");
+        let extra = mock_info("addr0000", &[Coin::new(100, "uluna"), Coin::new(1, "uusd")]);
+        assert!(execute(deps.as_mut(), mock_env(), extra, send(0)).is_err());
+
+        let paid = mock_info("addr0000", &coins(100, "uluna"));
+        execute(deps.as_mut(), mock_env(), paid, send(0)).unwrap();
+
+        // Only the admin may withdraw.
+        let withdraw = ExecuteMsg::WithdrawFees {
+            amount:    Coin::new(100, "uluna"),
+            recipient: "treasury".to_string(),
+        };
+        let user = mock_info("addr0000", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), user, withdraw.clone()).is_err());
+        let result = execute(deps.as_mut(), mock_env(), admin.clone(), withdraw.clone()).unwrap();
+        assert_eq!(
+            result.messages,
+            vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount:     coins(100, "uluna"),
+            }))]
+        );
+
+        // Only collected fees can be withdrawn, and only once.
+        let error = execute(deps.as_mut(), mock_env(), admin, withdraw).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Withdrawal exceeds collected fees");
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut deps = mock_dependencies(&[]);
+        let admin = mock_info("admin", &[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), instantiate_msg).unwrap();
+
+        // Only the admin may pause.
+        let pause = ExecuteMsg::SetPaused {
+            send:    true,
+            receive: true,
+        };
+        let user = mock_info("addr0000", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), user.clone(), pause.clone()).is_err());
+        execute(deps.as_mut(), mock_env(), admin.clone(), pause).unwrap();
+
+        let paused: PausedResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Paused {}).unwrap()).unwrap();
+        assert_eq!(paused, PausedResponse { send: true, receive: true });
+
+        let error = execute(deps.as_mut(), mock_env(), user.clone(), send(0)).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Sending paused");
+        let recv = ExecuteMsg::RecvMessage {
+            vaa: Binary::from(vec![]),
+        };
+        let error = execute(deps.as_mut(), mock_env(), user.clone(), recv).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Receiving paused");
+
+        // Administration carries on while paused.
+        let resume = ExecuteMsg::SetPaused {
+            send:    false,
+            receive: true,
+        };
+        execute(deps.as_mut(), mock_env(), admin, resume).unwrap();
+        execute(deps.as_mut(), mock_env(), user, send(0)).unwrap();
+    }
+
+    #[test]
+    fn test_governance() {
+        let mut deps = mock_dependencies(&[]);
+        let admin = mock_info("admin", &[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), instantiate_msg).unwrap();
+
+        // Only the admin sets the governance emitter, and only once.
+        let set_governance = |emitter: &[u8]| ExecuteMsg::SetGovernance {
+            chain:   1,
+            emitter: Binary::from(emitter),
+        };
+        let user = mock_info("addr0000", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), user, set_governance(&[5; 32])).is_err());
+        assert!(execute(deps.as_mut(), mock_env(), admin.clone(), set_governance(&[5; 20])).is_err());
+        execute(deps.as_mut(), mock_env(), admin.clone(), set_governance(&[5; 32])).unwrap();
+        let error = execute(deps.as_mut(), mock_env(), admin, set_governance(&[7; 32])).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Governance already set");
+
+        // Upgrades name a code id, and only for Terra.
+        let upgrade = |chain, new_contract| ContractUpgrade { chain, new_contract }.payload();
+        let code_id = pad_address(&42u64.to_be_bytes());
+        assert_eq!(upgrade_code_id(&upgrade(CHAIN_ID_TERRA, code_id)).unwrap(), 42);
+        assert!(upgrade_code_id(&upgrade(1, code_id)).is_err());
+        assert!(upgrade_code_id(&upgrade(CHAIN_ID_TERRA, [1; 32])).is_err());
+
+        // Governance VAAs must be signed by the current guardian set.
+        assert!(check_governance_signers(2, 2).is_ok());
+        let error = check_governance_signers(1, 2).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Guardian set not current");
+    }
+
+    #[test]
+    fn test_nick_directory() {
+        let mut deps = mock_dependencies(&[]);
+        let admin = mock_info("admin", &[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), instantiate_msg).unwrap();
+
+        let register = |nick: &str| ExecuteMsg::RegisterNick {
+            nonce: 0,
+            nick:  nick.to_string(),
+        };
+        let lookup = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, nick: &str| -> Option<NickResponse> {
+            let query_msg = QueryMsg::Nick {
+                nick: nick.to_string(),
+            };
+            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+        };
+
+        // The first to register a nick gets it, and it's posted for other chains.
+        let user = mock_info("addr0000", &[]);
+        let result = execute(deps.as_mut(), mock_env(), user, register("Bob")).unwrap();
+        assert_eq!(result.messages.len(), 1);
+
+        let owner = pad_address(deps.api.addr_canonicalize("addr0000").unwrap().as_slice());
+        let entry = lookup(&deps, "Bob").unwrap();
+        assert_eq!(entry.chain, CHAIN_ID_TERRA);
+        assert_eq!(entry.owner, Binary::from(&owner[..]));
+        assert_eq!(entry.time, mock_env().block.time.seconds());
+        assert_eq!(lookup(&deps, "Alice"), None);
+
+        let other = mock_info("addr0001", &[]);
+        let error = execute(deps.as_mut(), mock_env(), other.clone(), register("Bob")).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Nickname taken");
+        let error = execute(deps.as_mut(), mock_env(), other, register("")).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Invalid nickname");
+
+        // A registration made earlier on another chain wins, a later one doesn't.
+        let later = Claim {
+            chain: 1,
+            owner: [1; 32],
+            time:  entry.time + 1,
+        };
+        assert!(!claim_nick(&mut deps.storage, "Bob", later).unwrap());
+        let earlier = Claim {
+            time: entry.time - 1,
+            ..later
+        };
+        assert!(claim_nick(&mut deps.storage, "Bob", earlier).unwrap());
+        assert_eq!(lookup(&deps, "Bob").unwrap().chain, 1);
+
+        // Only the admin may trust other deployments.
+        let trust = ExecuteMsg::SetTrustedMessenger {
+            chain:   1,
+            emitter: Binary::from(&[7u8; 32][..]),
+            trusted: true,
+        };
+        let user = mock_info("addr0000", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), user, trust.clone()).is_err());
+        execute(deps.as_mut(), mock_env(), admin, trust).unwrap();
+    }
+
+    #[test]
+    fn test_block_list() {
+        let mut deps = mock_dependencies(&[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), instantiate_msg).unwrap();
+
+        let set_block = |mode| ExecuteMsg::SetBlock {
+            chain:  1,
+            sender: Binary::from(&[7u8; 32][..]),
+            mode,
+        };
+        let blocked = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, recipient: &str| -> Option<BlockMode> {
+            let query_msg = QueryMsg::Blocked {
+                recipient: recipient.to_string(),
+                chain:     1,
+                sender:    Binary::from(&[7u8; 32][..]),
+            };
+            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+        };
+
+        // Lists belong to whoever sets them.
+        let user = mock_info("addr0000", &[]);
+        execute(deps.as_mut(), mock_env(), user.clone(), set_block(Some(BlockMode::Mute))).unwrap();
+        assert_eq!(blocked(&deps, "addr0000"), Some(BlockMode::Mute));
+        assert_eq!(blocked(&deps, "addr0001"), None);
+
+        execute(deps.as_mut(), mock_env(), user.clone(), set_block(Some(BlockMode::Block))).unwrap();
+        assert_eq!(blocked(&deps, "addr0000"), Some(BlockMode::Block));
+        execute(deps.as_mut(), mock_env(), user.clone(), set_block(None)).unwrap();
+        assert_eq!(blocked(&deps, "addr0000"), None);
+
+        // Senders are 32 byte padded addresses.
+        let short = ExecuteMsg::SetBlock {
+            chain:  1,
+            sender: Binary::from(&[7u8; 20][..]),
+            mode:   Some(BlockMode::Block),
+        };
+        assert!(execute(deps.as_mut(), mock_env(), user, short).is_err());
+    }
+
+    #[test]
+    fn test_multisig() {
+        let mut deps = mock_dependencies(&[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), instantiate_msg).unwrap();
+
+        let signers = vec!["addr0000".to_string(), "addr0001".to_string(), "addr0002".to_string()];
+        let create = |threshold| ExecuteMsg::CreateMultisig {
+            id: 0,
+            signers: signers.clone(),
+            threshold,
+        };
+        let now = mock_env().block.time.seconds();
+        let propose = |expiry| ExecuteMsg::Propose {
+            multisig: 0,
+            nonce: 0,
+            nick: "DAO".to_string(),
+            text: "Vote passed".to_string(),
+            expiry,
+            target_chain: 0,
+        };
+        let approve = |proposal| ExecuteMsg::Approve { multisig: 0, proposal };
+        let cancel = |proposal| ExecuteMsg::CancelProposal { multisig: 0, proposal };
+        let pending = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, proposal| -> Option<ProposalResponse> {
+            let query_msg = QueryMsg::Proposal { multisig: 0, proposal };
+            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+        };
+
+        // Thresholds must be reachable.
+        let admin = mock_info("admin", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), admin.clone(), create(4)).is_err());
+        execute(deps.as_mut(), mock_env(), admin, create(2)).unwrap();
+        let query_msg = QueryMsg::Multisig { id: 0 };
+        let multisig: MultisigResponse = from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(multisig.signers, signers);
+        assert_eq!(multisig.sender, Binary::from(&multisig_sender(0)[..]));
+
+        // Only signers can propose, and each signer approves once.
+        let stranger = mock_info("addr0003", &[]);
+        assert!(execute(deps.as_mut(), mock_env(), stranger.clone(), propose(now + 3600)).is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), propose(now + 3600)).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), approve(0)).unwrap();
+        assert!(execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), approve(0)).is_err());
+        assert!(execute(deps.as_mut(), mock_env(), stranger.clone(), approve(0)).is_err());
+        assert_eq!(pending(&deps, 0).unwrap().approvals, vec!["addr0000".to_string()]);
+
+        // The second approval meets the threshold, sending the message as the multisig.
+        let result = execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), approve(0)).unwrap();
+        assert_eq!(pending(&deps, 0), None);
+        let payload = Envelope {
+            sender:       multisig_sender(0),
+            target_chain: 0,
+            target:       None,
+            recipient:    None,
+            room:         None,
+            message:      Message {
+                nick: "DAO".to_string(),
+                text: "Vote passed".to_string(),
+            },
+        }
+        .try_to_vec()
+        .unwrap();
+        assert_eq!(
+            result.messages,
+            vec![SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: wormhole_sdk::id().to_string(),
+                    funds:         vec![],
+                    msg:           Binary::from(
+                        format!(
+                            r#"{{"post_message":{{"message":"{}","nonce":0}}}}"#,
+                            Binary::from(payload).to_base64()
+                        )
+                        .as_bytes()
+                    ),
+                }),
+                0
+            )]
+        );
+
+        // Expired proposals can't be approved, but anyone can cancel them.
+        execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), propose(now)).unwrap();
+        let error = execute(deps.as_mut(), mock_env(), mock_info("addr0002", &[]), approve(1)).unwrap_err();
+        assert_eq!(error.to_string(), "Generic error: Proposal expired");
+        execute(deps.as_mut(), mock_env(), stranger.clone(), cancel(1)).unwrap();
+
+        // Pending proposals can only be cancelled by signers.
+        execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), propose(now + 3600)).unwrap();
+        assert!(execute(deps.as_mut(), mock_env(), stranger, cancel(2)).is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("addr0002", &[]), cancel(2)).unwrap();
+        assert_eq!(pending(&deps, 2), None);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut deps = mock_dependencies(&[]);
+        let instantiate_msg = InstantiateMsg {
+            version: "1.0.0".to_string(),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), instantiate_msg).unwrap();
+
+        let send = |text: &str, chain| ExecuteMsg::SendMessage {
+            nonce:        0,
+            nick:         "Bob".to_string(),
+            text:         text.to_string(),
+            target:       None,
+            recipient:    None,
+            target_chain: chain,
+        };
+        let texts = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, query_msg| -> Vec<String> {
+            let response: MessagesResponse = from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            response.messages.into_iter().map(|message| message.message.text).collect()
+        };
+        let count = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, sender, chain| -> u64 {
+            let query_msg = QueryMsg::MessageCount { sender, chain };
+            let response: MessageCountResponse = from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            response.count
+        };
+
+        // Three messages sent from here, a minute apart, and one received from Solana before them.
+        let now = mock_env().block.time.seconds();
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(60);
+        execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), send("One", 0)).unwrap();
+        execute(deps.as_mut(), later.clone(), mock_info("addr0001", &[]), send("Two", 2)).unwrap();
+        execute(deps.as_mut(), later, mock_info("addr0000", &[]), send("Three", 0)).unwrap();
+
+        let received = HistoryEntry {
+            direction:         Direction::Received,
+            emitter_chain:     1,
+            emitter_address:   Binary::from(&[4u8; 32][..]),
+            sequence:          Some(9),
+            nonce:             0,
+            consistency_level: Some(32),
+            timestamp:         now - 60,
+            sender:            Binary::from(&[5u8; 32][..]),
+            target_chain:      0,
+            nick:              "Carol".to_string(),
+            text:              "Four".to_string(),
+            verified:          false,
+            quarantined:       false,
+        };
+        history::record(deps.as_mut().storage, &received).unwrap();
+
+        // Listing by sender, in pages continuing from the last id seen.
+        let sender = deps.api.addr_canonicalize("addr0000").unwrap();
+        let sender = Binary::from(&pad_address(sender.as_slice())[..]);
+        let by_sender = |start_after, limit| QueryMsg::MessagesBySender {
+            sender: sender.clone(),
+            start_after,
+            limit,
+        };
+        assert_eq!(texts(&deps, by_sender(None, None)), vec!["One", "Three"]);
+        assert_eq!(texts(&deps, by_sender(None, Some(1))), vec!["One"]);
+        assert_eq!(texts(&deps, by_sender(Some(0), Some(1))), vec!["Three"]);
+
+        // Listing by the chain messages were emitted from.
+        let by_chain = |chain| QueryMsg::MessagesByChain {
+            chain,
+            start_after: None,
+            limit:       None,
+        };
+        assert_eq!(texts(&deps, by_chain(CHAIN_ID_TERRA)), vec!["One", "Two", "Three"]);
+        assert_eq!(texts(&deps, by_chain(1)), vec!["Four"]);
+
+        // Listing by time follows timestamps rather than the order messages were recorded in.
+        let by_time = |since, until, start_after, limit| QueryMsg::MessagesByTime {
+            since,
+            until,
+            start_after,
+            limit,
+        };
+        assert_eq!(texts(&deps, by_time(0, None, None, None)), vec!["Four", "One", "Two", "Three"]);
+        assert_eq!(texts(&deps, by_time(0, Some(now), None, None)), vec!["Four"]);
+        assert_eq!(texts(&deps, by_time(now + 60, None, None, None)), vec!["Two", "Three"]);
+        assert_eq!(texts(&deps, by_time(0, None, None, Some(2))), vec!["Four", "One"]);
+        assert_eq!(texts(&deps, by_time(0, None, Some(0), Some(2))), vec!["Two", "Three"]);
+
+        // Counts.
+        assert_eq!(count(&deps, None, None), 4);
+        assert_eq!(count(&deps, Some(sender.clone()), None), 2);
+        assert_eq!(count(&deps, None, Some(CHAIN_ID_TERRA)), 3);
+        let both = QueryMsg::MessageCount {
+            sender: Some(sender),
+            chain:  Some(1),
+        };
+        assert!(query(deps.as_ref(), mock_env(), both).is_err());
+
+        // Received messages can be looked up by VAA, sent ones once the bridge reports their
+        // sequence.
+        let lookup = |deps: &cosmwasm_std::OwnedDeps<_, _, _>, chain, emitter: &[u8], sequence| -> Option<MessageResponse> {
+            let query_msg = QueryMsg::Message {
+                chain,
+                emitter: Binary::from(emitter),
+                sequence,
+            };
+            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+        };
+        let found = lookup(&deps, 1, &[4u8; 32], 9).unwrap();
+        assert_eq!((found.id, found.message), (3, received));
+
+        let contract = deps.api.addr_canonicalize(MOCK_CONTRACT_ADDR).unwrap();
+        let contract = pad_address(contract.as_slice());
+        assert_eq!(lookup(&deps, CHAIN_ID_TERRA, &contract, 7), None);
+
+        let posted = Reply {
+            id:     1,
+            result: ContractResult::Ok(SubMsgExecutionResponse {
+                events: vec![Event::new("wasm").add_attribute("message.sequence", "7")],
+                data:   None,
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), posted.clone()).unwrap();
+        let found = lookup(&deps, CHAIN_ID_TERRA, &contract, 7).unwrap();
+        assert_eq!(found.message.text, "Two");
+        assert_eq!(found.message.sequence, Some(7));
+        assert!(reply(deps.as_mut(), mock_env(), posted).is_err());
+    }
+
     #[test]
     fn test_send_message() {
         // Test Messages
@@ -94,9 +1540,12 @@ mod testing {
         };
 
         let send_msg = ExecuteMsg::SendMessage {
-            nonce: 0,
-            nick:  "Bob".to_string(),
-            text:  "Hello Alice".to_string(),
+            nonce:  0,
+            nick:   "Bob".to_string(),
+            text:   "Hello Alice".to_string(),
+            target: None,
+            recipient: None,
+            target_chain: 0,
         };
 
         // Instantiate Contract
@@ -109,19 +1558,40 @@ mod testing {
         let info = mock_info("addr0000", &[]);
         let result = execute(deps.as_mut(), mock_env(), info, send_msg).unwrap();
 
-        // Contract should have emitted a Msg targetting the wormhole contract.
+        // The payload should carry the sender's canonical address alongside the message.
+        let sender = deps.api.addr_canonicalize("addr0000").unwrap();
+        let payload = Envelope {
+            sender:       pad_address(sender.as_slice()),
+            target_chain: 0,
+            target:       None,
+            recipient:    None,
+            room:         None,
+            message:      Message {
+                nick: "Bob".to_string(),
+                text: "Hello Alice".to_string(),
+            },
+        }
+        .try_to_vec()
+        .unwrap();
+
+        // Contract should have emitted a Msg targetting the wormhole contract, replying with the
+        // sequence for the first history entry.
         assert_eq!(
             result.messages,
-            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: wormhole_sdk::id().to_string(),
-                funds:         vec![],
-                msg:           Binary::from(&[
-                    123, 34, 112, 111, 115, 116, 95, 109, 101, 115, 115, 97, 103, 101, 34, 58, 123,
-                    34, 109, 101, 115, 115, 97, 103, 101, 34, 58, 34, 65, 119, 65, 65, 65, 69,
-                    74, 118, 89, 103, 115, 65, 65, 65, 66, 73, 90, 87, 120, 115, 98, 121, 66, 66, 98,
-                    71, 108, 106, 90, 81, 61, 61, 34, 44, 34, 110, 111, 110, 99, 101, 34, 58, 48, 125, 125
-                ]),
-            }))]
+            vec![SubMsg::reply_on_success(
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: wormhole_sdk::id().to_string(),
+                    funds:         vec![],
+                    msg:           Binary::from(
+                        format!(
+                            r#"{{"post_message":{{"message":"{}","nonce":0}}}}"#,
+                            Binary::from(payload).to_base64()
+                        )
+                        .as_bytes()
+                    ),
+                }),
+                0
+            )]
         );
     }
 
@@ -157,18 +1627,26 @@ mod testing {
                 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x01, 0x3c, 0x1b,
                 0xfa, // Sequence
                 0x00, // Consistency
-                // Payload
-                5, 0, 0, 0, 65, 108, 105, 99, 101, 9, 0, 0, 0, 72, 101, 108, 108, 111, 32, 66, 111,
-                98,
+                // Payload, an Envelope from sender [1; 32] to Terra with no target, recipient or room.
+                1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+                1, 1, 1, 1, 3, 0, 0, 0, 0, 5, 0, 0, 0, 65, 108, 105, 99, 101, 9, 0, 0, 0, 72, 101,
+                108, 108, 111, 32, 66, 111, 98,
             ]),
         };
 
         use borsh::BorshSerialize;
         println!(
             "{:?}",
-            Message {
-                nick: "Alice".to_string(),
-                text: "Hello Bob".to_string(),
+            Envelope {
+                sender:       [1; 32],
+                target_chain: CHAIN_ID_TERRA,
+                target:       None,
+                recipient:    None,
+                room:         None,
+                message:      Message {
+                    nick: "Alice".to_string(),
+                    text: "Hello Bob".to_string(),
+                },
             }
             .try_to_vec()
         );
@@ -189,6 +1667,18 @@ mod testing {
                     key:   "action".to_string(),
                     value: "receive_message".to_string(),
                 },
+                Attribute {
+                    key:   "sender".to_string(),
+                    value: "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=".to_string(),
+                },
+                Attribute {
+                    key:   "verified".to_string(),
+                    value: "false".to_string(),
+                },
+                Attribute {
+                    key:   "quarantined".to_string(),
+                    value: "false".to_string(),
+                },
                 Attribute {
                     key:   "nick".to_string(),
                     value: "Alice".to_string(),
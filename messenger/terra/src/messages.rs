@@ -1,13 +1,23 @@
 //! CosmWasm defines Message types to its various entrypoints. These are JSON encoded and decoded
 //! by the runtime before being passed into the various entrypoints.
 
-use cosmwasm_std::Binary;
+use cosmwasm_std::{
+    Binary,
+    Coin,
+};
 use schemars::JsonSchema;
 use serde::{
     Deserialize,
     Serialize,
 };
 
+use crate::state::{
+    BlockMode,
+    EmitterPolicy,
+    HistoryEntry,
+    RateLimit,
+};
+
 /// InstantiateMsg is passed into the contract initialiser when the contract is first deployed,
 /// this is a one off message.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -15,6 +25,11 @@ pub struct InstantiateMsg {
     pub version: String,
 }
 
+/// MigrateMsg is passed into the migrate handler when governance upgrades the contract, see
+/// `ExecuteMsg::SubmitUpgrade`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
 /// ExecuteMsg is passed into the execute contract handler whenever a user submits a transaction
 /// targetting our contract, this is our "main" entrypoint.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -27,5 +42,286 @@ pub enum ExecuteMsg {
         nonce: u32,
         nick:  String,
         text:  String,
+
+        /// 32 byte address of a program or contract on the receiving chain to forward the message
+        /// to, see `Envelope::target`.
+        target: Option<Binary>,
+
+        /// 32 byte address of the user on the receiving chain a direct message is for, see
+        /// `Envelope::recipient`.
+        #[serde(default)]
+        recipient: Option<Binary>,
+
+        /// Wormhole chain id the message is for, 0 (the default) for any chain.
+        #[serde(default)]
+        target_chain: u16,
+    },
+
+    /// Set how many messages each sender may send per window. Admin only.
+    SetRateLimit {
+        limit: RateLimit,
+    },
+
+    /// Cap the messages sent to `chain` per window, a limit of 0 lifts the cap. Chain 0 caps
+    /// messages for any chain. Admin only.
+    SetDestinationLimit {
+        chain: u16,
+        limit: RateLimit,
+    },
+
+    /// Exempt a sender from, or return them to, rate limiting. Admin only.
+    SetExempt {
+        address: String,
+        exempt:  bool,
+    },
+
+    /// Set the receive policy for the 32 byte `emitter` on `chain`. Admin only.
+    SetEmitterPolicy {
+        chain:   u16,
+        emitter: Binary,
+        policy:  EmitterPolicy,
+    },
+
+    /// Pause or resume sending, including nickname registrations, and receiving. Admin only.
+    SetPaused {
+        send:    bool,
+        receive: bool,
+    },
+
+    /// Set the protocol fee senders must attach to each message. Admin only.
+    SetProtocolFee {
+        fee: Coin,
+    },
+
+    /// Send collected protocol fees to `recipient`, at most what has been collected in `amount`'s
+    /// denom and not yet withdrawn. Admin only.
+    WithdrawFees {
+        amount:    Coin,
+        recipient: String,
+    },
+
+    /// Register `nick` to the sender in the nickname directory, and post the registration with
+    /// `nonce` so other chains add it too. Costs the same as a message.
+    RegisterNick {
+        nonce: u32,
+        nick:  String,
+    },
+
+    /// Trust, or stop trusting, the 32 byte `emitter` on `chain` as another Messenger deployment
+    /// whose nickname registrations are copied into the directory. Admin only.
+    SetTrustedMessenger {
+        chain:   u16,
+        emitter: Binary,
+        trusted: bool,
+    },
+
+    /// Set the 32 byte `emitter` on `chain` whose governance VAAs may upgrade the contract. Admin
+    /// only, and only once.
+    SetGovernance {
+        chain:   u16,
+        emitter: Binary,
+    },
+
+    /// Migrate the contract to the code id named in a governance VAA's `ContractUpgrade`, in the
+    /// last 8 bytes of its new contract. The contract must be its own wasm admin. The VAA must be
+    /// finalized and signed by the bridge's current guardian set.
+    SubmitUpgrade {
+        vaa: Binary,
+    },
+
+    /// Block or mute the 32 byte `sender` on `chain` for messages targetting the caller, or take
+    /// them off the caller's list with a null `mode`.
+    SetBlock {
+        chain:  u16,
+        sender: Binary,
+        mode:   Option<BlockMode>,
+    },
+
+    /// Create multisig `id`, which sends messages once `threshold` of `signers` approve them.
+    CreateMultisig {
+        id:        u64,
+        signers:   Vec<String>,
+        threshold: u8,
+    },
+
+    /// Propose a message for `multisig` to send, which can be approved until the unix time
+    /// `expiry`. Signers only, and proposing does not count as approving.
+    Propose {
+        multisig: u64,
+        nonce:    u32,
+        nick:     String,
+        text:     String,
+        expiry:   u64,
+
+        /// Wormhole chain id the message is for, 0 (the default) for any chain.
+        #[serde(default)]
+        target_chain: u16,
+    },
+
+    /// Approve a proposal. Signers only. The approval meeting the threshold sends the message as
+    /// the multisig, so must attach the protocol fee.
+    Approve {
+        multisig: u64,
+        proposal: u64,
+    },
+
+    /// Cancel a proposal. Signers only, or anyone once it has expired.
+    CancelProposal {
+        multisig: u64,
+        proposal: u64,
+    },
+}
+
+/// QueryMsg is passed into the query handler, for reading contract state without a transaction.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum QueryMsg {
+    /// What sending a message currently costs, see `SendCostResponse`.
+    SendCost {},
+
+    /// Whether sending and receiving are paused, see `PausedResponse`.
+    Paused {},
+
+    /// Who `nick` is registered to, see `NickResponse`, or null if nobody.
+    Nick {
+        nick: String,
+    },
+
+    /// How `recipient` has listed the 32 byte `sender` on `chain`, or null if they haven't.
+    Blocked {
+        recipient: String,
+        chain:     u16,
+        sender:    Binary,
     },
+
+    /// Multisig `id`'s signers and threshold, see `MultisigResponse`.
+    Multisig {
+        id: u64,
+    },
+
+    /// A pending proposal, see `ProposalResponse`, or null once sent or cancelled.
+    Proposal {
+        multisig: u64,
+        proposal: u64,
+    },
+
+    /// The message carried in the VAA from the 32 byte `emitter` on `chain` with `sequence`, see
+    /// `MessageResponse`, or null if it isn't in the history.
+    Message {
+        chain:    u16,
+        emitter:  Binary,
+        sequence: u64,
+    },
+
+    /// Messages from the 32 byte padded `sender`, oldest first, see `MessagesResponse`. Pages
+    /// continue from the last id of the previous page, given as `start_after`.
+    MessagesBySender {
+        sender:      Binary,
+        start_after: Option<u64>,
+        limit:       Option<u32>,
+    },
+
+    /// Messages emitted from `chain`, oldest first, paginated as `MessagesBySender`.
+    MessagesByChain {
+        chain:       u16,
+        start_after: Option<u64>,
+        limit:       Option<u32>,
+    },
+
+    /// Messages with timestamps from `since` until, but not including, `until`, in time order and
+    /// paginated as `MessagesBySender`.
+    MessagesByTime {
+        since:       u64,
+        until:       Option<u64>,
+        start_after: Option<u64>,
+        limit:       Option<u32>,
+    },
+
+    /// Number of messages sent and received, see `MessageCountResponse`, counting only those
+    /// from `sender` or emitted from `chain` if either is given.
+    MessageCount {
+        sender: Option<Binary>,
+        chain:  Option<u16>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SendCostResponse {
+    /// Fee the Wormhole bridge charges per message.
+    pub bridge_fee: Coin,
+
+    /// Our own fee, which must be attached to SendMessage.
+    pub protocol_fee: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PausedResponse {
+    pub send:    bool,
+    pub receive: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NickResponse {
+    /// Chain the nick was registered on, and the owner's 32 byte padded address there.
+    pub chain: u16,
+    pub owner: Binary,
+
+    /// Unix time of the registration.
+    pub time: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MultisigResponse {
+    pub signers:   Vec<String>,
+    pub threshold: u8,
+
+    /// The 32 byte sender the multisig's messages are sent as.
+    pub sender: Binary,
+
+    /// Proposals created so far, the next proposal takes this as its id.
+    pub proposals: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProposalResponse {
+    pub proposer:     String,
+    pub nick:         String,
+    pub text:         String,
+    pub target_chain: u16,
+    pub expiry:       u64,
+    pub approvals:    Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MessageResponse {
+    /// Position in the history, for paginating listings.
+    pub id:      u64,
+    pub message: HistoryEntry,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MessagesResponse {
+    pub messages: Vec<MessageResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MessageCountResponse {
+    pub count: u64,
+}
+
+/// The part of the Wormhole bridge's query interface we use.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WormholeQueryMsg {
+    GetState {},
+    GuardianSetInfo {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WormholeStateResponse {
+    pub fee: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WormholeGuardianSetInfoResponse {
+    pub guardian_set_index: u32,
 }
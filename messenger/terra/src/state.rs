@@ -0,0 +1,459 @@
+//! Contract storage. The config is a singleton, per-sender and per-destination rate limit state
+//! live in buckets keyed by canonical address and by chain id, emitter receive policies and
+//! trusted Messenger emitters in buckets keyed by chain id and emitter address, the nickname
+//! directory in a bucket keyed by nick, block lists in a bucket keyed by recipient, chain id and
+//! sender, consumed governance VAAs in a bucket keyed by VAA hash, multisigs and their proposals
+//! in buckets keyed by multisig id and by multisig and proposal id, and the message history in a
+//! bucket keyed by id with index buckets mapping VAA, sender, chain and time to ids. Protocol fees
+//! collected and not yet withdrawn are kept in a bucket keyed by denom.
+
+use cosmwasm_std::{
+    Binary,
+    CanonicalAddr,
+    Coin,
+    Storage,
+    Uint128,
+};
+use cosmwasm_storage::{
+    bucket,
+    bucket_read,
+    singleton,
+    singleton_read,
+    Bucket,
+    ReadonlyBucket,
+    ReadonlySingleton,
+    Singleton,
+};
+use schemars::JsonSchema;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use messenger_common::Claim;
+
+pub static CONFIG_KEY: &[u8] = b"config";
+pub static SENDER_KEY: &[u8] = b"sender";
+pub static DESTINATION_KEY: &[u8] = b"destination";
+pub static POLICY_KEY: &[u8] = b"policy";
+pub static TRUSTED_KEY: &[u8] = b"trusted";
+pub static NICK_KEY: &[u8] = b"nick";
+pub static BLOCK_KEY: &[u8] = b"block";
+pub static CONSUMED_KEY: &[u8] = b"consumed";
+pub static MULTISIG_KEY: &[u8] = b"multisig";
+pub static PROPOSAL_KEY: &[u8] = b"proposal";
+pub static HISTORY_KEY: &[u8] = b"history";
+pub static HISTORY_COUNT_KEY: &[u8] = b"history_count";
+pub static BY_VAA_KEY: &[u8] = b"by_vaa";
+pub static BY_SENDER_KEY: &[u8] = b"by_sender";
+pub static BY_CHAIN_KEY: &[u8] = b"by_chain";
+pub static BY_TIME_KEY: &[u8] = b"by_time";
+pub static SENDER_COUNT_KEY: &[u8] = b"sender_count";
+pub static CHAIN_COUNT_KEY: &[u8] = b"chain_count";
+pub static FEES_KEY: &[u8] = b"fees";
+
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    /// Address allowed to change rate limits, the contract's instantiator.
+    pub admin: CanonicalAddr,
+
+    /// How many messages each sender may send per window.
+    pub sender_limit: RateLimit,
+
+    /// Charged per message on top of the bridge fee, and kept in the contract's balance until the
+    /// admin withdraws it.
+    pub protocol_fee: Coin,
+
+    /// Emergency switches refusing every send or receive while set.
+    pub send_paused:    bool,
+    pub receive_paused: bool,
+
+    /// Emitter whose governance VAAs may upgrade the contract. A chain of 0 means none is set.
+    pub governance_chain:   u16,
+    pub governance_emitter: Vec<u8>,
+}
+
+/// A cap of `limit` messages every `window` seconds. A limit of 0 means no cap.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct RateLimit {
+    pub window: u64,
+    pub limit:  u32,
+}
+
+impl RateLimit {
+    /// Whether the limit can be enforced. A limit over a window of 0 seconds would start a new
+    /// window for every message, and so never apply.
+    pub fn is_valid(&self) -> bool {
+        self.limit == 0 || self.window != 0
+    }
+}
+
+/// Messages counted against a `RateLimit` in the window starting at `start`, in seconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct Usage {
+    pub start: u64,
+    pub used:  u32,
+}
+
+impl Usage {
+    /// Count one message sent at `now`, starting a new window if the current one has passed.
+    /// Returns `false`, leaving the usage untouched, if the limit has already been reached.
+    pub fn consume(&mut self, limit: &RateLimit, now: u64) -> bool {
+        if limit.limit == 0 {
+            return true;
+        }
+
+        let mut usage = *self;
+        if now >= usage.start.saturating_add(limit.window) {
+            usage = Usage { start: now, used: 0 };
+        }
+        if usage.used >= limit.limit {
+            return false;
+        }
+
+        usage.used += 1;
+        *self = usage;
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct SenderState {
+    pub usage: Usage,
+
+    /// Trusted senders the admin has exempted from rate limits.
+    pub exempt: bool,
+}
+
+/// Outbound cap for one destination chain. Chains without an entry are not capped. Messages for
+/// any chain count against chain 0's cap alone, not every chain's.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Destination {
+    pub limit: RateLimit,
+    pub usage: Usage,
+}
+
+/// Receive policy for an emitter on a foreign chain. Consistency levels are the raw values carried
+/// in VAAs, which mean different things on each chain.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct EmitterPolicy {
+    /// Lowest consistency level messages from the emitter are accepted at.
+    pub min_consistency: u8,
+
+    /// Level at which the emitter's chain is final, required of routed messages if set.
+    pub finality: Option<u8>,
+}
+
+/// Who a nickname is registered to, see `Claim`. `owner` is their address on `chain`, padded as
+/// `Envelope::sender`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct NickEntry {
+    pub chain: u16,
+    pub owner: [u8; 32],
+    pub time:  u64,
+}
+
+impl From<Claim> for NickEntry {
+    fn from(claim: Claim) -> Self {
+        NickEntry {
+            chain: claim.chain,
+            owner: claim.owner,
+            time:  claim.time,
+        }
+    }
+}
+
+impl From<NickEntry> for Claim {
+    fn from(entry: NickEntry) -> Self {
+        Claim {
+            chain: entry.chain,
+            owner: entry.owner,
+            time:  entry.time,
+        }
+    }
+}
+
+/// What happens to messages from a sender a recipient has listed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockMode {
+    /// Drop the sender's messages.
+    Block,
+
+    /// Receive the sender's messages, but marked quarantined.
+    Mute,
+}
+
+/// An M-of-N multisig whose approved proposals are sent as `multisig_sender`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MultisigState {
+    pub signers:   Vec<CanonicalAddr>,
+    pub threshold: u8,
+
+    /// Proposals created so far, the next proposal takes this as its id.
+    pub proposals: u64,
+}
+
+/// A message waiting on a multisig's approval. Removed once sent or cancelled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProposalState {
+    pub proposer:     CanonicalAddr,
+    pub nonce:        u32,
+    pub nick:         String,
+    pub text:         String,
+    pub target_chain: u16,
+
+    /// Unix time from which the proposal can no longer be approved.
+    pub expiry: u64,
+
+    /// Signers who have approved so far.
+    pub approvals: Vec<CanonicalAddr>,
+}
+
+/// Whether a history entry was sent from or received by this contract.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A message in the history, along with the metadata of the VAA it was carried in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HistoryEntry {
+    pub direction: Direction,
+
+    /// Emitter of the VAA, this contract for sent messages.
+    pub emitter_chain:   u16,
+    pub emitter_address: Binary,
+
+    /// Assigned by the bridge, so only known for sent messages once the post has succeeded.
+    pub sequence: Option<u64>,
+    pub nonce:    u32,
+
+    /// The VAA's consistency level, not known for sent messages.
+    pub consistency_level: Option<u8>,
+
+    /// Unix time of the VAA, or of the block a sent message was posted in.
+    pub timestamp: u64,
+
+    /// The envelope's 32 byte padded sender, and the chain it was addressed to.
+    pub sender:       Binary,
+    pub target_chain: u16,
+    pub nick:         String,
+    pub text:         String,
+
+    /// Whether the nick was registered to the sender, and whether the recipient had muted them.
+    /// Always false for sent messages.
+    pub verified:    bool,
+    pub quarantined: bool,
+}
+
+/// Number of messages sent and received, the next history id is their sum.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct HistoryCount {
+    pub sent:     u64,
+    pub received: u64,
+}
+
+
+pub fn config(storage: &mut dyn Storage) -> Singleton<Config> {
+    singleton(storage, CONFIG_KEY)
+}
+
+pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<Config> {
+    singleton_read(storage, CONFIG_KEY)
+}
+
+pub fn senders(storage: &mut dyn Storage) -> Bucket<SenderState> {
+    bucket(storage, SENDER_KEY)
+}
+
+pub fn senders_read(storage: &dyn Storage) -> ReadonlyBucket<SenderState> {
+    bucket_read(storage, SENDER_KEY)
+}
+
+pub fn destinations(storage: &mut dyn Storage) -> Bucket<Destination> {
+    bucket(storage, DESTINATION_KEY)
+}
+
+pub fn destinations_read(storage: &dyn Storage) -> ReadonlyBucket<Destination> {
+    bucket_read(storage, DESTINATION_KEY)
+}
+
+pub fn policies(storage: &mut dyn Storage) -> Bucket<EmitterPolicy> {
+    bucket(storage, POLICY_KEY)
+}
+
+pub fn policies_read(storage: &dyn Storage) -> ReadonlyBucket<EmitterPolicy> {
+    bucket_read(storage, POLICY_KEY)
+}
+
+/// Emitters of other Messenger deployments, whose nickname registrations are trusted. Keyed as
+/// policies are.
+pub fn trusted(storage: &mut dyn Storage) -> Bucket<bool> {
+    bucket(storage, TRUSTED_KEY)
+}
+
+pub fn trusted_read(storage: &dyn Storage) -> ReadonlyBucket<bool> {
+    bucket_read(storage, TRUSTED_KEY)
+}
+
+pub fn nicks(storage: &mut dyn Storage) -> Bucket<NickEntry> {
+    bucket(storage, NICK_KEY)
+}
+
+pub fn nicks_read(storage: &dyn Storage) -> ReadonlyBucket<NickEntry> {
+    bucket_read(storage, NICK_KEY)
+}
+
+/// Protocol fees collected and not yet withdrawn, keyed by denom. Only these can be withdrawn, so
+/// funds the contract holds for any other reason stay put.
+pub fn fees(storage: &mut dyn Storage) -> Bucket<Uint128> {
+    bucket(storage, FEES_KEY)
+}
+
+pub fn fees_read(storage: &dyn Storage) -> ReadonlyBucket<Uint128> {
+    bucket_read(storage, FEES_KEY)
+}
+
+/// Block list entries, keyed by `block_key`.
+pub fn blocks(storage: &mut dyn Storage) -> Bucket<BlockMode> {
+    bucket(storage, BLOCK_KEY)
+}
+
+pub fn blocks_read(storage: &dyn Storage) -> ReadonlyBucket<BlockMode> {
+    bucket_read(storage, BLOCK_KEY)
+}
+
+/// Governance VAAs already acted on, keyed by VAA hash.
+pub fn consumed(storage: &mut dyn Storage) -> Bucket<bool> {
+    bucket(storage, CONSUMED_KEY)
+}
+
+pub fn consumed_read(storage: &dyn Storage) -> ReadonlyBucket<bool> {
+    bucket_read(storage, CONSUMED_KEY)
+}
+
+pub fn multisigs(storage: &mut dyn Storage) -> Bucket<MultisigState> {
+    bucket(storage, MULTISIG_KEY)
+}
+
+pub fn multisigs_read(storage: &dyn Storage) -> ReadonlyBucket<MultisigState> {
+    bucket_read(storage, MULTISIG_KEY)
+}
+
+/// Pending proposals, keyed by `proposal_key`.
+pub fn proposals(storage: &mut dyn Storage) -> Bucket<ProposalState> {
+    bucket(storage, PROPOSAL_KEY)
+}
+
+pub fn proposals_read(storage: &dyn Storage) -> ReadonlyBucket<ProposalState> {
+    bucket_read(storage, PROPOSAL_KEY)
+}
+
+/// Message history entries, keyed by id in big endian so they list in the order recorded.
+pub fn history(storage: &mut dyn Storage) -> Bucket<HistoryEntry> {
+    bucket(storage, HISTORY_KEY)
+}
+
+pub fn history_read(storage: &dyn Storage) -> ReadonlyBucket<HistoryEntry> {
+    bucket_read(storage, HISTORY_KEY)
+}
+
+pub fn history_count(storage: &mut dyn Storage) -> Singleton<HistoryCount> {
+    singleton(storage, HISTORY_COUNT_KEY)
+}
+
+pub fn history_count_read(storage: &dyn Storage) -> ReadonlySingleton<HistoryCount> {
+    singleton_read(storage, HISTORY_COUNT_KEY)
+}
+
+/// History ids keyed by `vaa_key`.
+pub fn by_vaa(storage: &mut dyn Storage) -> Bucket<u64> {
+    bucket(storage, BY_VAA_KEY)
+}
+
+pub fn by_vaa_read(storage: &dyn Storage) -> ReadonlyBucket<u64> {
+    bucket_read(storage, BY_VAA_KEY)
+}
+
+/// History ids of `sender`'s messages, keyed by id.
+pub fn by_sender<'a>(storage: &'a mut dyn Storage, sender: &[u8]) -> Bucket<'a, u64> {
+    Bucket::multilevel(storage, &[BY_SENDER_KEY, sender])
+}
+
+pub fn by_sender_read<'a>(storage: &'a dyn Storage, sender: &[u8]) -> ReadonlyBucket<'a, u64> {
+    ReadonlyBucket::multilevel(storage, &[BY_SENDER_KEY, sender])
+}
+
+/// History ids of messages emitted from `chain`, keyed by id.
+pub fn by_chain(storage: &mut dyn Storage, chain: u16) -> Bucket<u64> {
+    Bucket::multilevel(storage, &[BY_CHAIN_KEY, &chain.to_be_bytes()])
+}
+
+pub fn by_chain_read(storage: &dyn Storage, chain: u16) -> ReadonlyBucket<u64> {
+    ReadonlyBucket::multilevel(storage, &[BY_CHAIN_KEY, &chain.to_be_bytes()])
+}
+
+/// History ids keyed by `time_key`.
+pub fn by_time(storage: &mut dyn Storage) -> Bucket<u64> {
+    bucket(storage, BY_TIME_KEY)
+}
+
+pub fn by_time_read(storage: &dyn Storage) -> ReadonlyBucket<u64> {
+    bucket_read(storage, BY_TIME_KEY)
+}
+
+/// Messages per sender, keyed by 32 byte padded sender.
+pub fn sender_counts(storage: &mut dyn Storage) -> Bucket<u64> {
+    bucket(storage, SENDER_COUNT_KEY)
+}
+
+pub fn sender_counts_read(storage: &dyn Storage) -> ReadonlyBucket<u64> {
+    bucket_read(storage, SENDER_COUNT_KEY)
+}
+
+/// Messages per emitter chain, keyed by chain id.
+pub fn chain_counts(storage: &mut dyn Storage) -> Bucket<u64> {
+    bucket(storage, CHAIN_COUNT_KEY)
+}
+
+pub fn chain_counts_read(storage: &dyn Storage) -> ReadonlyBucket<u64> {
+    bucket_read(storage, CHAIN_COUNT_KEY)
+}
+
+/// Key of the VAA emitted by `emitter` on `chain` with `sequence`.
+pub fn vaa_key(chain: u16, emitter: &[u8], sequence: u64) -> Vec<u8> {
+    [&policy_key(chain, emitter)[..], &sequence.to_be_bytes()].concat()
+}
+
+/// Key of history entry `id` recorded with `timestamp`, so entries list in time order.
+pub fn time_key(timestamp: u64, id: u64) -> Vec<u8> {
+    [timestamp.to_be_bytes(), id.to_be_bytes()].concat()
+}
+
+/// Key of `multisig`'s proposal `proposal`.
+pub fn proposal_key(multisig: u64, proposal: u64) -> Vec<u8> {
+    [multisig.to_be_bytes(), proposal.to_be_bytes()].concat()
+}
+
+/// The 32 byte sender multisig `id`'s messages are posted as. Padded Terra addresses start with
+/// zeroes, so the prefix keeps multisig senders apart from them.
+pub fn multisig_sender(id: u64) -> [u8; 32] {
+    let mut sender = [0u8; 32];
+    sender[..8].copy_from_slice(b"multisig");
+    sender[24..].copy_from_slice(&id.to_be_bytes());
+    sender
+}
+
+/// Key of `recipient`'s entry for `sender` on `chain`, both padded to 32 bytes as in `Envelope`.
+pub fn block_key(recipient: &[u8; 32], chain: u16, sender: &[u8]) -> Vec<u8> {
+    [&recipient[..], &policy_key(chain, sender)].concat()
+}
+
+/// Key of the policy for `emitter` on `chain`.
+pub fn policy_key(chain: u16, emitter: &[u8]) -> Vec<u8> {
+    [&chain.to_be_bytes()[..], emitter].concat()
+}
@@ -0,0 +1,199 @@
+//! A simulated guardian set, able to sign VAAs correctly or in deliberately broken ways.
+
+use std::convert::TryInto;
+use std::io::Write;
+
+use secp256k1::{
+    PublicKey,
+    SecretKey,
+};
+use sha3::Digest;
+
+use messenger_client::{
+    GuardianSignature,
+    SignedVaa,
+};
+
+
+/// Ways `Guardians::sign_faulty` can get a VAA wrong, for negative tests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// One signature short of quorum.
+    BelowQuorum,
+
+    /// Quorum, but the first signature is made with a key outside the guardian set.
+    ForeignSigner,
+
+    /// Correctly signed, but the payload is changed afterwards.
+    TamperedBody,
+
+    /// The first guardian's signature repeated to make up the quorum.
+    DuplicateSigner,
+
+    /// Correctly signed, but claiming the next guardian set index.
+    WrongGuardianSet,
+}
+
+
+/// A guardian set whose secret keys we hold.
+#[derive(Clone, Debug)]
+pub struct Guardians {
+    pub index: u32,
+    secrets:   Vec<SecretKey>,
+}
+
+impl Guardians {
+    /// A set of `count` guardians with keys derived from the set index, so tests are repeatable.
+    pub fn new(index: u32, count: usize) -> Self {
+        let secrets = (0..count)
+            .map(|i| {
+                let seed = [&b"guardian"[..], &index.to_be_bytes(), &(i as u32).to_be_bytes()].concat();
+                SecretKey::parse(&keccak(&seed)).unwrap()
+            })
+            .collect();
+
+        Guardians { index, secrets }
+    }
+
+    /// A set made from known secret keys.
+    pub fn from_secrets(index: u32, secrets: &[[u8; 32]]) -> Self {
+        Guardians {
+            index,
+            secrets: secrets.iter().map(|s| SecretKey::parse(s).unwrap()).collect(),
+        }
+    }
+
+    /// The set that replaces this one in a guardian set upgrade.
+    pub fn next(&self, count: usize) -> Self {
+        Guardians::new(self.index + 1, count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Ethereum style addresses of each guardian, which is how the bridge stores them.
+    pub fn addresses(&self) -> Vec<[u8; 20]> {
+        self.secrets.iter().map(address).collect()
+    }
+
+    /// Signatures the bridge requires before it will post a VAA.
+    pub fn quorum(&self) -> usize {
+        self.len() * 2 / 3 + 1
+    }
+
+    /// Sign `vaa` with exactly a quorum of guardians, replacing any existing signatures.
+    pub fn sign(&self, vaa: &mut SignedVaa) {
+        let signers: Vec<u8> = (0..self.quorum() as u8).collect();
+        self.sign_with(vaa, &signers);
+    }
+
+    /// Sign `vaa` with the given guardians, in order, replacing any existing signatures.
+    pub fn sign_with(&self, vaa: &mut SignedVaa, signers: &[u8]) {
+        vaa.guardian_set_index = self.index;
+        let digest = vaa.digest();
+        vaa.signatures = signers
+            .iter()
+            .map(|&index| GuardianSignature {
+                index,
+                signature: sign(&self.secrets[index as usize], &digest),
+            })
+            .collect();
+    }
+
+    /// Sign `vaa` so that the bridge should refuse it.
+    pub fn sign_faulty(&self, vaa: &mut SignedVaa, fault: Fault) {
+        match fault {
+            Fault::BelowQuorum => {
+                let signers: Vec<u8> = (0..self.quorum() as u8 - 1).collect();
+                self.sign_with(vaa, &signers);
+            }
+
+            Fault::ForeignSigner => {
+                self.sign(vaa);
+                let stranger = Guardians::new(u32::MAX, 1);
+                vaa.signatures[0].signature = sign(&stranger.secrets[0], &vaa.digest());
+            }
+
+            Fault::TamperedBody => {
+                self.sign(vaa);
+                vaa.payload.push(0);
+            }
+
+            Fault::DuplicateSigner => {
+                self.sign(vaa);
+                let first = vaa.signatures[0].clone();
+                for signature in vaa.signatures.iter_mut() {
+                    *signature = first.clone();
+                }
+            }
+
+            Fault::WrongGuardianSet => {
+                self.sign(vaa);
+                vaa.guardian_set_index = self.index + 1;
+            }
+        }
+    }
+}
+
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    let mut h = sha3::Keccak256::default();
+    h.write_all(data).unwrap();
+    h.finalize().into()
+}
+
+fn address(secret: &SecretKey) -> [u8; 20] {
+    let public = PublicKey::from_secret_key(secret).serialize();
+    keccak(&public[1..])[12..].try_into().unwrap()
+}
+
+/// Sign a VAA digest the way guardians do, over the hash of the digest.
+fn sign(secret: &SecretKey, digest: &[u8; 32]) -> [u8; 65] {
+    let (signature, recovery) = secp256k1::sign(&secp256k1::Message::parse(&keccak(digest)), secret);
+    let mut out = [0u8; 65];
+    out[..64].copy_from_slice(&signature.serialize());
+    out[64] = recovery.serialize();
+    out
+}
+
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_quorum() {
+        assert_eq!(Guardians::new(0, 1).quorum(), 1);
+        assert_eq!(Guardians::new(0, 3).quorum(), 3);
+        assert_eq!(Guardians::new(0, 19).quorum(), 13);
+    }
+
+    #[test]
+    fn test_faults() {
+        let guardians = Guardians::new(0, 4);
+        let mut vaa = SignedVaa::default();
+
+        guardians.sign(&mut vaa);
+        assert_eq!(vaa.signatures.len(), 3);
+
+        guardians.sign_faulty(&mut vaa, Fault::BelowQuorum);
+        assert_eq!(vaa.signatures.len(), 2);
+
+        guardians.sign_faulty(&mut vaa, Fault::DuplicateSigner);
+        assert!(vaa.signatures.iter().all(|s| s.index == 0));
+
+        guardians.sign_faulty(&mut vaa, Fault::WrongGuardianSet);
+        assert_eq!(vaa.guardian_set_index, 1);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        assert_eq!(Guardians::new(0, 2).addresses(), Guardians::new(0, 2).addresses());
+        assert_ne!(Guardians::new(0, 1).addresses(), Guardians::new(1, 1).addresses());
+    }
+}
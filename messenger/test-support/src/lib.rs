@@ -0,0 +1,308 @@
+//! Test support for the Messenger and programs built on it, simulating the guardian network
+//! against a bridge deployed in `solana-program-test`.
+//!
+//! `Guardians` holds the secret keys of a guardian set and signs VAAs with a 2/3+1 quorum, or in
+//! one of the broken ways listed in `Fault`. The functions here initialise the bridge with a
+//! guardian set, post signed VAAs (splitting signatures across as many `verify_signatures`
+//! transactions as needed), upgrade the guardian set through governance, and preload guardian
+//! sets that have already expired, VAAs posted while an expired set was still valid, or the
+//! program data naming a program's upgrade authority.
+//!
+//! ```ignore
+//! let guardians = Guardians::new(0, 19);
+//! let mut test = ProgramTest::default();
+//! test.add_program("bridge", wormhole_sdk::id(), processor!(bridge_entrypoint));
+//! let mut context = test.start_with_context().await;
+//! initialize_bridge(&mut context, &guardians, 50, 2_000_000_000).await?;
+//!
+//! let mut vaa = message_vaa(&posted_message);
+//! guardians.sign(&mut vaa);
+//! let posted_vaa = post_vaa(&mut context, &guardians, &vaa).await?;
+//! ```
+
+use borsh::BorshSerialize;
+use byteorder::{
+    BigEndian,
+    WriteBytesExt,
+};
+use solana_program::bpf_loader_upgradeable::{
+    self,
+    UpgradeableLoaderState,
+};
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::{
+    ProgramTest,
+    ProgramTestContext,
+};
+use solana_sdk::account::Account;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use solana_sdk::transaction::Transaction;
+use solana_sdk::transport::TransportError;
+use wormhole_sdk::MessageData;
+
+use messenger_client::{
+    GuardianSet,
+    Messenger,
+    SignatureSet,
+    SignedVaa,
+};
+
+mod guardians;
+
+pub use guardians::{
+    Fault,
+    Guardians,
+};
+
+
+/// Wormhole chain id of Solana, governance VAAs are also emitted from here.
+pub const CHAIN_ID_SOLANA: u16 = 1;
+
+/// Governance emitter the test bridge is built with, see `EMITTER_ADDRESS` in the Messenger's
+/// test documentation.
+pub const GOVERNANCE_EMITTER: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4,
+];
+
+
+/// Submit a transaction paid for by the test payer, plus any extra signers.
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let mut all = vec![&context.payer];
+    all.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all,
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+
+/// Initialise the bridge with `guardians` as its first guardian set. Replaced guardian sets stay
+/// valid for `expiration` seconds after an upgrade.
+pub async fn initialize_bridge(
+    context: &mut ProgramTestContext,
+    guardians: &Guardians,
+    fee: u64,
+    expiration: u32,
+) -> Result<(), TransportError> {
+    let payer = context.payer.pubkey();
+    let initialize = wormhole_sdk::instructions::initialize(
+        wormhole_sdk::id(),
+        payer,
+        fee,
+        expiration,
+        &guardians.addresses(),
+    )
+    .unwrap();
+    process(context, &[initialize], &[]).await
+}
+
+/// Add the upgradeable loader's program data account for `program` to the test environment before
+/// it starts, naming `authority` as the program's upgrade authority. `ProgramTest` deploys programs
+/// without one, but the Messenger reads it to check who may initialise it.
+pub fn add_program_data(test: &mut ProgramTest, program: Pubkey, authority: Pubkey) {
+    let data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+        slot:                      0,
+        upgrade_authority_address: Some(authority),
+    })
+    .unwrap();
+
+    test.add_account(
+        Pubkey::find_program_address(&[program.as_ref()], &bpf_loader_upgradeable::id()).0,
+        Account {
+            lamports:   1_000_000_000,
+            data,
+            owner:      bpf_loader_upgradeable::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Add a guardian set account to the test environment before it starts, for example one whose
+/// `expiration_time` has already passed. The bridge does not need to know about it beforehand.
+pub fn add_guardian_set(test: &mut ProgramTest, guardians: &Guardians, expiration_time: u32) {
+    let data = GuardianSet {
+        index:           guardians.index,
+        keys:            guardians.addresses(),
+        creation_time:   0,
+        expiration_time,
+    }
+    .try_to_vec()
+    .unwrap();
+
+    test.add_account(
+        bridge().guardian_set(guardians.index),
+        Account {
+            lamports:   1_000_000_000,
+            data,
+            owner:      wormhole_sdk::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+
+/// Add a PostedVAA account for `vaa` to the test environment before it starts, along with the
+/// signature set the bridge would have recorded for it, as if `vaa` had been posted earlier. The
+/// signatures on `vaa` are not checked. Returns the PostedVAA account.
+pub fn add_posted_vaa(test: &mut ProgramTest, vaa: &SignedVaa) -> Pubkey {
+    let signature_set = Pubkey::new_unique();
+    let signatures = SignatureSet {
+        signatures:         vec![true; vaa.signatures.len()],
+        hash:               vaa.digest(),
+        guardian_set_index: vaa.guardian_set_index,
+    };
+
+    let message = MessageData {
+        vaa_version:           vaa.version,
+        consistency_level:     vaa.consistency_level,
+        vaa_time:              vaa.timestamp,
+        vaa_signature_account: signature_set,
+        submission_time:       0,
+        nonce:                 vaa.nonce,
+        sequence:              vaa.sequence,
+        emitter_chain:         vaa.emitter_chain,
+        emitter_address:       vaa.emitter_address,
+        payload:               vaa.payload.clone(),
+    };
+    let mut posted = b"vaa".to_vec();
+    message.serialize(&mut posted).unwrap();
+
+    let posted_vaa = bridge().posted_vaa(&vaa.digest());
+    let accounts = [(signature_set, signatures.try_to_vec().unwrap()), (posted_vaa, posted)];
+    for (key, data) in accounts {
+        test.add_account(
+            key,
+            Account {
+                lamports:   1_000_000_000,
+                data,
+                owner:      wormhole_sdk::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    posted_vaa
+}
+
+
+/// An unsigned VAA for a message posted on Solana, as the guardians would observe it.
+pub fn message_vaa(message: &MessageData) -> SignedVaa {
+    SignedVaa {
+        version:           1,
+        timestamp:         message.submission_time,
+        nonce:             message.nonce,
+        emitter_chain:     CHAIN_ID_SOLANA,
+        emitter_address:   message.emitter_address,
+        sequence:          message.sequence,
+        consistency_level: message.consistency_level,
+        payload:           message.payload.clone(),
+        ..Default::default()
+    }
+}
+
+/// Verify `vaa`'s signatures against `guardians` and post it to the bridge, returning the
+/// PostedVAA account. Signatures are split across as many `verify_signatures` transactions as
+/// needed.
+pub async fn post_vaa(
+    context: &mut ProgramTestContext,
+    guardians: &Guardians,
+    vaa: &SignedVaa,
+) -> Result<Pubkey, TransportError> {
+    let payer = context.payer.pubkey();
+    let signature_set = Keypair::new();
+    let transactions = messenger_client::vaa::verify_and_post(
+        wormhole_sdk::id(),
+        payer,
+        signature_set.pubkey(),
+        vaa,
+        &guardians.addresses(),
+    )
+    .expect("signature index outside the guardian set");
+
+    let (post, verify) = transactions.split_last().unwrap();
+    for instructions in verify {
+        process(context, instructions, &[&signature_set]).await?;
+    }
+    process(context, post, &[]).await?;
+
+    Ok(bridge().posted_vaa(&vaa.digest()))
+}
+
+/// A RecvMessage instruction delivering a VAA posted with `post_vaa` to the Messenger deployed at
+/// `program_id`, with the signature and guardian set accounts its receive policy checks.
+pub async fn recv_instruction(context: &mut ProgramTestContext, program_id: Pubkey, posted_vaa: Pubkey) -> Instruction {
+    let payer = context.payer.pubkey();
+    Messenger::new(program_id, wormhole_sdk::id())
+        .recv_message(&mut context.banks_client, &payer, &posted_vaa)
+        .await
+        .expect("posted VAA not found")
+}
+
+/// The client's address helpers, only the bridge derived ones are used here.
+fn bridge() -> Messenger {
+    Messenger::new(Pubkey::default(), wormhole_sdk::id())
+}
+
+
+/// Governance payload replacing the current guardian set with `next`.
+pub fn guardian_set_upgrade(next: &Guardians) -> Vec<u8> {
+    let mut payload = Vec::new();
+
+    // Module "Core", left padded to 32 bytes, action 2 (GuardianSetUpgrade), target chain 0 (all).
+    payload.extend_from_slice(&[0u8; 28]);
+    payload.extend_from_slice(b"Core");
+    payload.write_u8(2).unwrap();
+    payload.write_u16::<BigEndian>(0).unwrap();
+    payload.write_u32::<BigEndian>(next.index).unwrap();
+    payload.write_u8(next.len() as u8).unwrap();
+    for address in next.addresses() {
+        payload.extend_from_slice(&address);
+    }
+
+    payload
+}
+
+/// Upgrade the bridge from `current` to `next` with a governance VAA signed by `current`. Each
+/// governance VAA needs its own `sequence`.
+pub async fn upgrade_guardian_set(
+    context: &mut ProgramTestContext,
+    current: &Guardians,
+    next: &Guardians,
+    sequence: u64,
+) -> Result<(), TransportError> {
+    let mut vaa = SignedVaa {
+        version:           1,
+        emitter_chain:     CHAIN_ID_SOLANA,
+        emitter_address:   GOVERNANCE_EMITTER,
+        sequence,
+        consistency_level: 32,
+        payload:           guardian_set_upgrade(next),
+        ..Default::default()
+    };
+    current.sign(&mut vaa);
+    let posted_vaa = post_vaa(context, current, &vaa).await?;
+
+    let payer = context.payer.pubkey();
+    let upgrade = wormhole_sdk::instructions::upgrade_guardian_set(
+        wormhole_sdk::id(),
+        payer,
+        posted_vaa,
+        Pubkey::new_from_array(GOVERNANCE_EMITTER),
+        current.index,
+        next.index,
+        sequence,
+    );
+    process(context, &[upgrade], &[]).await
+}